@@ -0,0 +1,882 @@
+//! A fake BlueZ for running bike without a bluetooth radio.
+//!
+//! It owns `org.bluez` on the session bus (or the bus given as the first
+//! argument, either `system` or a D-Bus address) and exports an adapter, a
+//! smart trainer with the Fitness Machine and Cycling Power services, and a
+//! heart rate strap that only shows up once discovery starts. Start bike with
+//! `BIKE_BLUEZ_BUS` set to the same bus to use it.
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc, time::Duration};
+
+use gtk::{
+    gio::{
+        self, BusNameOwnerFlags, BusType, Cancellable, DBusConnection, DBusConnectionFlags,
+        DBusInterfaceInfo, DBusMethodInvocation, DBusNodeInfo,
+    },
+    glib::{self, ControlFlow, Variant, variant::ObjectPath, variant::ToVariant},
+};
+
+const BLUEZ_BUS_NAME: &str = "org.bluez";
+const ADAPTER_PATH: &str = "/org/bluez/hci0";
+const TRAINER_PATH: &str = "/org/bluez/hci0/dev_F0_5E_11_0B_1C_01";
+const HEART_RATE_PATH: &str = "/org/bluez/hci0/dev_C4_11_9A_2F_60_02";
+
+const ADAPTER_INTERFACE: &str = "org.bluez.Adapter1";
+const DEVICE_INTERFACE: &str = "org.bluez.Device1";
+const GATT_SERVICE_INTERFACE: &str = "org.bluez.GattService1";
+const GATT_CHARACTERISTIC_INTERFACE: &str = "org.bluez.GattCharacteristic1";
+const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+const OBJECT_MANAGER_INTERFACE: &str = "org.freedesktop.DBus.ObjectManager";
+
+const FITNESS_MACHINE_SERVICE: &str = "00001826-0000-1000-8000-00805f9b34fb";
+const CYCLING_POWER_SERVICE: &str = "00001818-0000-1000-8000-00805f9b34fb";
+const HEART_RATE_SERVICE: &str = "0000180d-0000-1000-8000-00805f9b34fb";
+const INDOOR_BIKE_DATA: &str = "00002ad2-0000-1000-8000-00805f9b34fb";
+const FITNESS_MACHINE_FEATURE: &str = "00002acc-0000-1000-8000-00805f9b34fb";
+const SUPPORTED_RESISTANCE_LEVEL_RANGE: &str = "00002ad6-0000-1000-8000-00805f9b34fb";
+const SUPPORTED_POWER_RANGE: &str = "00002ad8-0000-1000-8000-00805f9b34fb";
+const FITNESS_MACHINE_CONTROL_POINT: &str = "00002ad9-0000-1000-8000-00805f9b34fb";
+const FITNESS_MACHINE_STATUS: &str = "00002ada-0000-1000-8000-00805f9b34fb";
+const CYCLING_POWER_MEASUREMENT: &str = "00002a63-0000-1000-8000-00805f9b34fb";
+const CYCLING_POWER_FEATURE: &str = "00002a65-0000-1000-8000-00805f9b34fb";
+const CYCLING_POWER_CONTROL_POINT: &str = "00002a66-0000-1000-8000-00805f9b34fb";
+const SENSOR_LOCATION: &str = "00002a5d-0000-1000-8000-00805f9b34fb";
+const HEART_RATE_MEASUREMENT: &str = "00002a37-0000-1000-8000-00805f9b34fb";
+
+const INTROSPECTION: &str = r#"
+<node>
+  <interface name="org.freedesktop.DBus.ObjectManager">
+    <method name="GetManagedObjects">
+      <arg name="objects" type="a{oa{sa{sv}}}" direction="out"/>
+    </method>
+    <signal name="InterfacesAdded">
+      <arg name="object" type="o"/>
+      <arg name="interfaces" type="a{sa{sv}}"/>
+    </signal>
+    <signal name="InterfacesRemoved">
+      <arg name="object" type="o"/>
+      <arg name="interfaces" type="as"/>
+    </signal>
+  </interface>
+  <interface name="org.bluez.Adapter1">
+    <method name="StartDiscovery"/>
+    <method name="StopDiscovery"/>
+    <method name="RemoveDevice">
+      <arg name="device" type="o" direction="in"/>
+    </method>
+    <property name="Address" type="s" access="read"/>
+    <property name="Name" type="s" access="read"/>
+    <property name="Powered" type="b" access="readwrite"/>
+    <property name="Discovering" type="b" access="read"/>
+  </interface>
+  <interface name="org.bluez.Device1">
+    <method name="Connect"/>
+    <method name="Disconnect"/>
+    <method name="Pair"/>
+    <property name="Address" type="s" access="read"/>
+    <property name="AddressType" type="s" access="read"/>
+    <property name="Name" type="s" access="read"/>
+    <property name="Alias" type="s" access="read"/>
+    <property name="Paired" type="b" access="read"/>
+    <property name="Connected" type="b" access="read"/>
+    <property name="ServicesResolved" type="b" access="read"/>
+    <property name="RSSI" type="n" access="read"/>
+    <property name="UUIDs" type="as" access="read"/>
+    <property name="Adapter" type="o" access="read"/>
+  </interface>
+  <interface name="org.bluez.GattService1">
+    <property name="UUID" type="s" access="read"/>
+    <property name="Primary" type="b" access="read"/>
+    <property name="Device" type="o" access="read"/>
+  </interface>
+  <interface name="org.bluez.GattCharacteristic1">
+    <method name="ReadValue">
+      <arg name="options" type="a{sv}" direction="in"/>
+      <arg name="value" type="ay" direction="out"/>
+    </method>
+    <method name="WriteValue">
+      <arg name="value" type="ay" direction="in"/>
+      <arg name="options" type="a{sv}" direction="in"/>
+    </method>
+    <method name="StartNotify"/>
+    <method name="StopNotify"/>
+    <property name="UUID" type="s" access="read"/>
+    <property name="Service" type="o" access="read"/>
+    <property name="Value" type="ay" access="read"/>
+    <property name="Notifying" type="b" access="read"/>
+    <property name="Flags" type="as" access="read"/>
+  </interface>
+</node>
+"#;
+
+type Properties = HashMap<String, Variant>;
+type Interfaces = HashMap<String, Properties>;
+
+/// Spin down target speed range, in km/h.
+const SPIN_DOWN_SPEED: (f64, f64) = (25.0, 30.0);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SpinDown {
+    SpeedingUp,
+    Coasting(u8),
+}
+
+/// What the simulated rider and trainer are currently doing.
+#[derive(Debug)]
+struct Ride {
+    target_power: Option<i16>,
+    spin_down: Option<SpinDown>,
+    resistance: f64,
+    grade: f64,
+    paused: bool,
+    power: f64,
+    cadence: f64,
+    heart_rate: f64,
+    distance: f64,
+    crank_revolutions: f64,
+    crank_event_time: f64,
+    crank_length: f64,
+}
+
+impl Default for Ride {
+    fn default() -> Self {
+        Self {
+            target_power: None,
+            spin_down: None,
+            resistance: 0.0,
+            grade: 0.0,
+            paused: false,
+            power: 150.0,
+            cadence: 88.0,
+            heart_rate: 95.0,
+            distance: 0.0,
+            crank_revolutions: 0.0,
+            crank_event_time: 0.0,
+            crank_length: 172.5,
+        }
+    }
+}
+
+impl Ride {
+    /// Advances the ride, returning a Fitness Machine Status value when the
+    /// trainer has something to report.
+    fn step(&mut self, seconds: f64) -> Option<Vec<u8>> {
+        let wanted_power = match (self.paused, self.spin_down, self.target_power) {
+            (true, _, _) | (_, Some(SpinDown::Coasting(_)), _) => 0.0,
+            (_, Some(SpinDown::SpeedingUp), _) => 420.0,
+            (_, None, Some(target)) => f64::from(target),
+            (_, None, None) => 150.0 + self.resistance * 2.0 + self.grade * 25.0,
+        };
+        self.power += (wanted_power - self.power) * 0.3 + glib::random_double_range(-6.0, 6.0);
+        self.power = self.power.max(0.0);
+        self.cadence = if self.paused || matches!(self.spin_down, Some(SpinDown::Coasting(_))) {
+            0.0
+        } else {
+            (88.0 + glib::random_double_range(-3.0, 3.0)).max(0.0)
+        };
+        let wanted_heart_rate = 90.0 + self.power * 0.3;
+        self.heart_rate += (wanted_heart_rate - self.heart_rate) * 0.05;
+        self.distance += self.speed() / 3.6 * seconds;
+        if self.cadence > 0.0 {
+            self.crank_revolutions += self.cadence / 60.0 * seconds;
+            self.crank_event_time += seconds;
+        }
+
+        const SPIN_DOWN_STATUS: u8 = 0x14;
+        match self.spin_down {
+            Some(SpinDown::SpeedingUp) if self.speed() >= SPIN_DOWN_SPEED.0 => {
+                self.spin_down = Some(SpinDown::Coasting(0));
+                Some(vec![SPIN_DOWN_STATUS, 0x04])
+            }
+            Some(SpinDown::Coasting(seconds)) if seconds >= 4 => {
+                self.spin_down = None;
+                Some(vec![SPIN_DOWN_STATUS, 0x02])
+            }
+            Some(SpinDown::Coasting(seconds)) => {
+                self.spin_down = Some(SpinDown::Coasting(seconds + 1));
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// A flat road estimate, good enough to look plausible.
+    fn speed(&self) -> f64 {
+        (self.power / 0.15).cbrt() * 3.6 * 0.55
+    }
+
+    fn indoor_bike_data(&self) -> Vec<u8> {
+        // Speed, cadence, total distance, power and heart rate.
+        let flags: u16 = (1 << 2) | (1 << 4) | (1 << 6) | (1 << 9);
+        let mut value = flags.to_le_bytes().to_vec();
+        value.extend(((self.speed() * 100.0) as u16).to_le_bytes());
+        value.extend(((self.cadence * 2.0) as u16).to_le_bytes());
+        value.extend(&(self.distance as u32).to_le_bytes()[..3]);
+        value.extend((self.power as i16).to_le_bytes());
+        value.push(self.heart_rate as u8);
+        value
+    }
+
+    fn cycling_power_measurement(&self) -> Vec<u8> {
+        // Crank revolution data.
+        let flags: u16 = 1 << 5;
+        let mut value = flags.to_le_bytes().to_vec();
+        value.extend((self.power as i16).to_le_bytes());
+        value.extend((self.crank_revolutions as u64 as u16).to_le_bytes());
+        value.extend(((self.crank_event_time * 1024.0) as u64 as u16).to_le_bytes());
+        value
+    }
+
+    fn heart_rate_measurement(&self) -> Vec<u8> {
+        vec![0x00, self.heart_rate as u8]
+    }
+
+    /// Applies a Cycling Power Control Point write and returns the response.
+    fn power_control(&mut self, request: &[u8]) -> Vec<u8> {
+        const SUCCESS: u8 = 0x01;
+        const NOT_SUPPORTED: u8 = 0x02;
+        let op_code = request.first().copied().unwrap_or_default();
+        let mut response = vec![0x20, op_code];
+        match request {
+            [0x04, low, high] => {
+                self.crank_length = f64::from(u16::from_le_bytes([*low, *high])) / 2.0;
+                response.push(SUCCESS);
+            }
+            [0x05] => {
+                response.push(SUCCESS);
+                response.extend(((self.crank_length * 2.0) as u16).to_le_bytes());
+            }
+            [0x0c] => {
+                response.push(SUCCESS);
+                response.extend((glib::random_int_range(-40, 40) as i16).to_le_bytes());
+            }
+            _ => response.push(NOT_SUPPORTED),
+        }
+        response
+    }
+
+    /// Applies an FTMS control point write and returns the response code
+    /// along with any response parameters.
+    fn control(&mut self, request: &[u8]) -> (u8, Vec<u8>) {
+        const SUCCESS: u8 = 0x01;
+        const NOT_SUPPORTED: u8 = 0x02;
+        const INVALID_PARAMETER: u8 = 0x03;
+        let result = match request {
+            [0x00] | [0x01] => SUCCESS,
+            [0x04, level] => {
+                self.target_power = None;
+                self.resistance = f64::from(*level) / 10.0;
+                SUCCESS
+            }
+            [0x05, low, high] => {
+                self.target_power = Some(i16::from_le_bytes([*low, *high]));
+                SUCCESS
+            }
+            [0x07] => {
+                self.paused = false;
+                SUCCESS
+            }
+            [0x08, _] => {
+                self.paused = true;
+                SUCCESS
+            }
+            [0x11, _, _, grade_low, grade_high, _, _] => {
+                self.target_power = None;
+                self.grade = f64::from(i16::from_le_bytes([*grade_low, *grade_high])) / 100.0;
+                SUCCESS
+            }
+            [0x13, 0x01] => {
+                self.spin_down = Some(SpinDown::SpeedingUp);
+                let (low, high) = SPIN_DOWN_SPEED;
+                let mut parameters = ((low * 100.0) as u16).to_le_bytes().to_vec();
+                parameters.extend(((high * 100.0) as u16).to_le_bytes());
+                return (SUCCESS, parameters);
+            }
+            [0x13, 0x02] => {
+                self.spin_down = None;
+                SUCCESS
+            }
+            [0x00..=0x13, ..] => INVALID_PARAMETER,
+            _ => NOT_SUPPORTED,
+        };
+        (result, vec![])
+    }
+}
+
+struct Simulator {
+    connection: DBusConnection,
+    node: DBusNodeInfo,
+    objects: RefCell<HashMap<String, Interfaces>>,
+    ride: RefCell<Ride>,
+}
+
+impl Simulator {
+    fn new(connection: DBusConnection) -> Rc<Self> {
+        Rc::new(Self {
+            connection,
+            node: DBusNodeInfo::for_xml(INTROSPECTION).expect("Invalid introspection data"),
+            objects: RefCell::new(HashMap::new()),
+            ride: RefCell::new(Ride::default()),
+        })
+    }
+
+    fn interface_info(&self, name: &str) -> DBusInterfaceInfo {
+        self.node
+            .lookup_interface(name)
+            .unwrap_or_else(|| panic!("Missing introspection for {name}"))
+    }
+
+    fn property(&self, path: &str, interface: &str, name: &str) -> Option<Variant> {
+        self.objects
+            .borrow()
+            .get(path)
+            .and_then(|interfaces| interfaces.get(interface))
+            .and_then(|properties| properties.get(name))
+            .cloned()
+    }
+
+    fn set_property(&self, path: &str, interface: &str, name: &str, value: Variant) {
+        if let Some(properties) = self
+            .objects
+            .borrow_mut()
+            .get_mut(path)
+            .and_then(|interfaces| interfaces.get_mut(interface))
+        {
+            properties.insert(name.to_string(), value.clone());
+        }
+        let changed = HashMap::from([(name.to_string(), value)]);
+        self.emit(
+            path,
+            PROPERTIES_INTERFACE,
+            "PropertiesChanged",
+            &(interface, changed, Vec::<String>::new()).to_variant(),
+        );
+    }
+
+    fn emit(&self, path: &str, interface: &str, signal: &str, parameters: &Variant) {
+        if let Err(error) =
+            self.connection
+                .emit_signal(None, path, interface, signal, Some(parameters))
+        {
+            log::error!("Could not emit {interface}.{signal} on {path}. {error}");
+        }
+    }
+
+    fn export(self: &Rc<Self>, path: &str, interfaces: Interfaces, announce: bool) {
+        for interface in interfaces.keys() {
+            let slf = self.clone();
+            let getter = self.clone();
+            let setter = self.clone();
+            let result = self
+                .connection
+                .register_object(path, &self.interface_info(interface))
+                .method_call(
+                    move |_, _, path, interface, method, parameters, invocation| {
+                        slf.handle_method(
+                            path,
+                            interface.unwrap_or_default(),
+                            method,
+                            parameters,
+                            invocation,
+                        );
+                    },
+                )
+                .property(move |_, _, path, interface, name| {
+                    getter
+                        .property(path, interface, name)
+                        .unwrap_or_else(|| "".to_variant())
+                })
+                .set_property(move |_, _, path, interface, name, value| {
+                    setter.set_property(path, interface, name, value);
+                    true
+                })
+                .build();
+            if let Err(error) = result {
+                log::error!("Could not export {interface} on {path}. {error}");
+            }
+        }
+        self.objects
+            .borrow_mut()
+            .insert(path.to_string(), interfaces.clone());
+        if announce {
+            self.emit(
+                "/",
+                OBJECT_MANAGER_INTERFACE,
+                "InterfacesAdded",
+                &(object_path(path), interfaces).to_variant(),
+            );
+        }
+    }
+
+    fn export_root(self: &Rc<Self>) {
+        let slf = self.clone();
+        let result = self
+            .connection
+            .register_object("/", &self.interface_info(OBJECT_MANAGER_INTERFACE))
+            .method_call(move |_, _, _, _, method, _, invocation| match method {
+                "GetManagedObjects" => {
+                    let objects = slf
+                        .objects
+                        .borrow()
+                        .iter()
+                        .map(|(path, interfaces)| (object_path(path), interfaces.clone()))
+                        .collect::<HashMap<ObjectPath, Interfaces>>();
+                    invocation.return_value(Some(&(objects,).to_variant()));
+                }
+                _ => invocation.return_dbus_error(
+                    "org.freedesktop.DBus.Error.UnknownMethod",
+                    "Unknown method",
+                ),
+            })
+            .build();
+        if let Err(error) = result {
+            log::error!("Could not export the object manager. {error}");
+        }
+    }
+
+    fn handle_method(
+        self: &Rc<Self>,
+        path: &str,
+        interface: &str,
+        method: &str,
+        parameters: Variant,
+        invocation: DBusMethodInvocation,
+    ) {
+        log::debug!("{interface}.{method} on {path}");
+        match (interface, method) {
+            (ADAPTER_INTERFACE, "StartDiscovery") => {
+                self.set_property(path, interface, "Discovering", true.to_variant());
+                if !self.objects.borrow().contains_key(HEART_RATE_PATH) {
+                    let slf = self.clone();
+                    glib::timeout_add_local_once(Duration::from_secs(2), move || {
+                        slf.export_heart_rate_strap(true)
+                    });
+                }
+                invocation.return_value(None);
+            }
+            (ADAPTER_INTERFACE, "StopDiscovery") => {
+                self.set_property(path, interface, "Discovering", false.to_variant());
+                invocation.return_value(None);
+            }
+            (ADAPTER_INTERFACE, "RemoveDevice") => invocation.return_dbus_error(
+                "org.bluez.Error.NotSupported",
+                "Simulated devices cannot be removed",
+            ),
+            (DEVICE_INTERFACE, "Connect") => {
+                self.set_property(path, interface, "Connected", true.to_variant());
+                self.set_property(path, interface, "ServicesResolved", true.to_variant());
+                invocation.return_value(None);
+            }
+            (DEVICE_INTERFACE, "Disconnect") => {
+                self.set_property(path, interface, "ServicesResolved", false.to_variant());
+                self.set_property(path, interface, "Connected", false.to_variant());
+                invocation.return_value(None);
+            }
+            (DEVICE_INTERFACE, "Pair") => {
+                self.set_property(path, interface, "Paired", true.to_variant());
+                invocation.return_value(None);
+            }
+            (GATT_CHARACTERISTIC_INTERFACE, "ReadValue") => {
+                let value = self
+                    .property(path, interface, "Value")
+                    .unwrap_or_else(|| Vec::<u8>::new().to_variant());
+                invocation.return_value(Some(&Variant::tuple_from_iter([value])));
+            }
+            (GATT_CHARACTERISTIC_INTERFACE, "WriteValue") => {
+                let (value, _) = parameters
+                    .get::<(Vec<u8>, HashMap<String, Variant>)>()
+                    .unwrap_or_default();
+                self.handle_write(path, &value);
+                invocation.return_value(None);
+            }
+            (GATT_CHARACTERISTIC_INTERFACE, "StartNotify") => {
+                self.set_property(path, interface, "Notifying", true.to_variant());
+                invocation.return_value(None);
+            }
+            (GATT_CHARACTERISTIC_INTERFACE, "StopNotify") => {
+                self.set_property(path, interface, "Notifying", false.to_variant());
+                invocation.return_value(None);
+            }
+            _ => invocation
+                .return_dbus_error("org.freedesktop.DBus.Error.UnknownMethod", "Unknown method"),
+        }
+    }
+
+    fn handle_write(&self, path: &str, value: &[u8]) {
+        let uuid = self
+            .property(path, GATT_CHARACTERISTIC_INTERFACE, "UUID")
+            .and_then(|uuid| uuid.get::<String>())
+            .unwrap_or_default();
+        if uuid == FITNESS_MACHINE_CONTROL_POINT {
+            let (result, parameters) = self.ride.borrow_mut().control(value);
+            let op_code = value.first().copied().unwrap_or_default();
+            log::info!("Control point request {value:02x?}, answering {result:#04x}");
+            let mut response = vec![0x80, op_code, result];
+            response.extend(parameters);
+            self.set_property(
+                path,
+                GATT_CHARACTERISTIC_INTERFACE,
+                "Value",
+                response.to_variant(),
+            );
+        } else if uuid == CYCLING_POWER_CONTROL_POINT {
+            let response = self.ride.borrow_mut().power_control(value);
+            log::info!("Power control point request {value:02x?}, answering {response:02x?}");
+            self.set_property(
+                path,
+                GATT_CHARACTERISTIC_INTERFACE,
+                "Value",
+                response.to_variant(),
+            );
+        }
+    }
+
+    fn tick(&self) {
+        let status = self.ride.borrow_mut().step(1.0);
+        let notifying = self
+            .objects
+            .borrow()
+            .iter()
+            .filter_map(|(path, interfaces)| {
+                let characteristic = interfaces.get(GATT_CHARACTERISTIC_INTERFACE)?;
+                characteristic
+                    .get("Notifying")
+                    .and_then(|notifying| notifying.get::<bool>())
+                    .filter(|notifying| *notifying)?;
+                let uuid = characteristic.get("UUID")?.get::<String>()?;
+                Some((path.clone(), uuid))
+            })
+            .collect::<Vec<_>>();
+        for (path, uuid) in notifying {
+            let ride = self.ride.borrow();
+            let value = match uuid.as_str() {
+                FITNESS_MACHINE_STATUS => match &status {
+                    Some(status) => status.clone(),
+                    None => continue,
+                },
+                INDOOR_BIKE_DATA => ride.indoor_bike_data(),
+                CYCLING_POWER_MEASUREMENT => ride.cycling_power_measurement(),
+                HEART_RATE_MEASUREMENT => ride.heart_rate_measurement(),
+                _ => continue,
+            };
+            drop(ride);
+            self.set_property(
+                &path,
+                GATT_CHARACTERISTIC_INTERFACE,
+                "Value",
+                value.to_variant(),
+            );
+        }
+
+        let discovering = self
+            .property(ADAPTER_PATH, ADAPTER_INTERFACE, "Discovering")
+            .and_then(|discovering| discovering.get::<bool>())
+            .unwrap_or(false);
+        if discovering {
+            for (path, base) in [(TRAINER_PATH, -55), (HEART_RATE_PATH, -70)] {
+                if self.objects.borrow().contains_key(path) {
+                    let rssi = (base + glib::random_int_range(-6, 7)) as i16;
+                    self.set_property(path, DEVICE_INTERFACE, "RSSI", rssi.to_variant());
+                }
+            }
+        }
+    }
+
+    fn export_adapter(self: &Rc<Self>) {
+        self.export(
+            ADAPTER_PATH,
+            HashMap::from([(
+                ADAPTER_INTERFACE.to_string(),
+                properties([
+                    ("Address", "00:1A:7D:DA:71:13".to_variant()),
+                    ("Name", "bike-simulator".to_variant()),
+                    ("Powered", true.to_variant()),
+                    ("Discovering", false.to_variant()),
+                ]),
+            )]),
+            false,
+        );
+    }
+
+    fn export_trainer(self: &Rc<Self>) {
+        self.export_device(
+            TRAINER_PATH,
+            "F0:5E:11:0B:1C:01",
+            "Simulated Trainer",
+            &[FITNESS_MACHINE_SERVICE, CYCLING_POWER_SERVICE],
+            false,
+        );
+        // Advertised as an available indoor bike made by Tacx.
+        self.set_property(
+            TRAINER_PATH,
+            DEVICE_INTERFACE,
+            "ManufacturerData",
+            HashMap::from([(0x0689u16, vec![0x01u8, 0x02].to_variant())]).to_variant(),
+        );
+        self.set_property(
+            TRAINER_PATH,
+            DEVICE_INTERFACE,
+            "ServiceData",
+            HashMap::from([(
+                FITNESS_MACHINE_SERVICE.to_string(),
+                vec![0x01u8, 0x20, 0x00].to_variant(),
+            )])
+            .to_variant(),
+        );
+        self.set_property(TRAINER_PATH, DEVICE_INTERFACE, "TxPower", 4i16.to_variant());
+        let ftms = format!("{TRAINER_PATH}/service000a");
+        self.export_service(&ftms, TRAINER_PATH, FITNESS_MACHINE_SERVICE, false);
+        self.export_characteristic(
+            &format!("{ftms}/char000b"),
+            &ftms,
+            INDOOR_BIKE_DATA,
+            &["notify"],
+            vec![],
+            false,
+        );
+        self.export_characteristic(
+            &format!("{ftms}/char000e"),
+            &ftms,
+            FITNESS_MACHINE_FEATURE,
+            &["read"],
+            // Cadence, total distance, heart rate and power measurement;
+            // resistance, power and indoor bike simulation targets.
+            vec![0x06, 0x44, 0x00, 0x00, 0x0c, 0x20, 0x00, 0x00],
+            false,
+        );
+        self.export_characteristic(
+            &format!("{ftms}/char0016"),
+            &ftms,
+            SUPPORTED_RESISTANCE_LEVEL_RANGE,
+            &["read"],
+            // Levels 0 to 20 in steps of 1.
+            vec![0x00, 0x00, 0xc8, 0x00, 0x0a, 0x00],
+            false,
+        );
+        self.export_characteristic(
+            &format!("{ftms}/char0018"),
+            &ftms,
+            SUPPORTED_POWER_RANGE,
+            &["read"],
+            // 0 to 1500 W in steps of 5 W.
+            vec![0x00, 0x00, 0xdc, 0x05, 0x05, 0x00],
+            false,
+        );
+        self.export_characteristic(
+            &format!("{ftms}/char0010"),
+            &ftms,
+            FITNESS_MACHINE_CONTROL_POINT,
+            &["write", "indicate"],
+            vec![],
+            false,
+        );
+        self.export_characteristic(
+            &format!("{ftms}/char0013"),
+            &ftms,
+            FITNESS_MACHINE_STATUS,
+            &["notify"],
+            vec![],
+            false,
+        );
+        let cps = format!("{TRAINER_PATH}/service0020");
+        self.export_service(&cps, TRAINER_PATH, CYCLING_POWER_SERVICE, false);
+        self.export_characteristic(
+            &format!("{cps}/char0021"),
+            &cps,
+            CYCLING_POWER_MEASUREMENT,
+            &["notify"],
+            vec![],
+            false,
+        );
+        self.export_characteristic(
+            &format!("{cps}/char0024"),
+            &cps,
+            CYCLING_POWER_FEATURE,
+            &["read"],
+            // Crank revolution data, offset compensation and crank length
+            // adjustment supported.
+            vec![0x08, 0x12, 0x00, 0x00],
+            false,
+        );
+        self.export_characteristic(
+            &format!("{cps}/char0026"),
+            &cps,
+            CYCLING_POWER_CONTROL_POINT,
+            &["write", "indicate"],
+            vec![],
+            false,
+        );
+        self.export_characteristic(
+            &format!("{cps}/char0029"),
+            &cps,
+            SENSOR_LOCATION,
+            &["read"],
+            // Rear hub.
+            vec![13],
+            false,
+        );
+    }
+
+    fn export_heart_rate_strap(self: &Rc<Self>, announce: bool) {
+        self.export_device(
+            HEART_RATE_PATH,
+            "C4:11:9A:2F:60:02",
+            "Simulated HRM",
+            &[HEART_RATE_SERVICE],
+            announce,
+        );
+        let hrs = format!("{HEART_RATE_PATH}/service000c");
+        self.export_service(&hrs, HEART_RATE_PATH, HEART_RATE_SERVICE, announce);
+        self.export_characteristic(
+            &format!("{hrs}/char000d"),
+            &hrs,
+            HEART_RATE_MEASUREMENT,
+            &["notify"],
+            vec![],
+            announce,
+        );
+    }
+
+    fn export_device(
+        self: &Rc<Self>,
+        path: &str,
+        address: &str,
+        name: &str,
+        services: &[&str],
+        announce: bool,
+    ) {
+        self.export(
+            path,
+            HashMap::from([(
+                DEVICE_INTERFACE.to_string(),
+                properties([
+                    ("Address", address.to_variant()),
+                    ("AddressType", "public".to_variant()),
+                    ("Name", name.to_variant()),
+                    ("Alias", name.to_variant()),
+                    ("Paired", false.to_variant()),
+                    ("Connected", false.to_variant()),
+                    ("ServicesResolved", false.to_variant()),
+                    ("RSSI", (-60i16).to_variant()),
+                    ("UUIDs", services.to_variant()),
+                    ("Adapter", object_path(ADAPTER_PATH).to_variant()),
+                ]),
+            )]),
+            announce,
+        );
+    }
+
+    fn export_service(self: &Rc<Self>, path: &str, device: &str, uuid: &str, announce: bool) {
+        self.export(
+            path,
+            HashMap::from([(
+                GATT_SERVICE_INTERFACE.to_string(),
+                properties([
+                    ("UUID", uuid.to_variant()),
+                    ("Primary", true.to_variant()),
+                    ("Device", object_path(device).to_variant()),
+                ]),
+            )]),
+            announce,
+        );
+    }
+
+    fn export_characteristic(
+        self: &Rc<Self>,
+        path: &str,
+        service: &str,
+        uuid: &str,
+        flags: &[&str],
+        value: Vec<u8>,
+        announce: bool,
+    ) {
+        self.export(
+            path,
+            HashMap::from([(
+                GATT_CHARACTERISTIC_INTERFACE.to_string(),
+                properties([
+                    ("UUID", uuid.to_variant()),
+                    ("Service", object_path(service).to_variant()),
+                    ("Value", value.to_variant()),
+                    ("Notifying", false.to_variant()),
+                    ("Flags", flags.to_variant()),
+                ]),
+            )]),
+            announce,
+        );
+    }
+}
+
+fn properties<const N: usize>(values: [(&str, Variant); N]) -> Properties {
+    values
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect()
+}
+
+fn object_path(path: &str) -> ObjectPath {
+    ObjectPath::try_from(path).expect("Invalid object path")
+}
+
+fn connect(bus: Option<&str>) -> Result<DBusConnection, glib::Error> {
+    match bus {
+        None | Some("session") => gio::bus_get_sync(BusType::Session, Cancellable::NONE),
+        Some("system") => gio::bus_get_sync(BusType::System, Cancellable::NONE),
+        Some(address) => DBusConnection::for_address_sync(
+            address,
+            DBusConnectionFlags::AUTHENTICATION_CLIENT
+                | DBusConnectionFlags::MESSAGE_BUS_CONNECTION,
+            None,
+            Cancellable::NONE,
+        ),
+    }
+}
+
+fn main() -> glib::ExitCode {
+    env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .parse_default_env()
+        .init();
+
+    let bus = std::env::args().nth(1);
+    let connection = match connect(bus.as_deref()) {
+        Ok(connection) => connection,
+        Err(error) => {
+            log::error!("Could not connect to the bus. {error}");
+            return glib::ExitCode::FAILURE;
+        }
+    };
+
+    let simulator = Simulator::new(connection.clone());
+    simulator.export_root();
+    simulator.export_adapter();
+    simulator.export_trainer();
+
+    let main_loop = glib::MainLoop::new(None, false);
+    let _owner_id = gio::bus_own_name_on_connection(
+        &connection,
+        BLUEZ_BUS_NAME,
+        BusNameOwnerFlags::NONE,
+        move |_, name| {
+            log::info!(
+                "Simulating BlueZ as {name}, start bike with BIKE_BLUEZ_BUS={}",
+                bus.as_deref().unwrap_or("session")
+            );
+        },
+        glib::clone!(
+            #[strong]
+            main_loop,
+            move |_, name| {
+                log::error!("Could not own {name}, is another BlueZ running on this bus?");
+                main_loop.quit();
+            }
+        ),
+    );
+
+    glib::timeout_add_seconds_local(1, move || {
+        simulator.tick();
+        ControlFlow::Continue
+    });
+    main_loop.run();
+    glib::ExitCode::FAILURE
+}
@@ -1,10 +1,14 @@
 mod imp {
-    use std::cell::RefCell;
+    use std::cell::{Cell, RefCell};
+    use std::sync::OnceLock;
 
     use adw::prelude::ObjectExt;
     use adw::subclass::prelude::{DerivedObjectProperties, ObjectImpl, ObjectSubclass};
     use gtk::gio::SignalSubscriptionId;
-    use gtk::glib::{self, Properties};
+    use gtk::glib::{self, Properties, subclass::Signal};
+
+    use crate::bluetooth::{LinkHealth, LinkStatus, Subscription};
+    use crate::protocol::{Advertisement, Decoder, Measurement, Trainer};
 
     #[derive(Debug, Default, Properties)]
     #[properties(wrapper_type = super::Device)]
@@ -21,10 +25,83 @@ mod imp {
         #[property(name = "rssi", get, set)]
         rssi: RefCell<i32>,
 
+        /// The RSSI, only following changes larger than the usual jitter.
+        /// Sort by it so that rows don't keep swapping places.
+        #[property(name = "stable-rssi", get, set)]
+        stable_rssi: RefCell<i32>,
+
         #[property(name = "object-path", get, set)]
         object_path: RefCell<String>,
 
-        pub rssi_sub_id: RefCell<Option<SignalSubscriptionId>>,
+        #[property(name = "address", get, set)]
+        address: RefCell<String>,
+
+        /// `public` or `random`, as BlueZ reports it.
+        #[property(name = "address-type", get, set)]
+        address_type: RefCell<String>,
+
+        #[property(name = "controllable", get, set)]
+        controllable: RefCell<bool>,
+
+        #[property(name = "power-meter", get, set)]
+        power_meter: RefCell<bool>,
+
+        /// The advertised brand, empty when unknown.
+        #[property(name = "brand", get, set)]
+        brand: RefCell<String>,
+
+        /// The advertised fitness services, ready to show.
+        #[property(name = "services", get, set)]
+        services: RefCell<String>,
+
+        /// Whether the trainer advertises that another app controls it.
+        #[property(name = "in-use", get, set)]
+        in_use: RefCell<bool>,
+
+        #[property(name = "link-status", get, set, builder(LinkStatus::default()))]
+        link_status: RefCell<LinkStatus>,
+
+        /// What most likely troubles the link, empty when nothing does.
+        #[property(name = "link-problem", get, set)]
+        link_problem: RefCell<String>,
+
+        /// Notifications per second, refreshed every second.
+        #[property(name = "link-rate", get, set)]
+        link_rate: RefCell<f64>,
+
+        /// What the trainer was last told to hold, empty until then.
+        #[property(name = "target", get, set)]
+        target: RefCell<String>,
+
+        /// The power the trainer holds in ERG mode, 0 in any other mode.
+        #[property(name = "target-power", get, set)]
+        target_power: RefCell<i32>,
+
+        /// The grade the trainer simulates, in percent.
+        #[property(name = "grade", get, set)]
+        grade: RefCell<f64>,
+
+        pub advertisement: RefCell<Advertisement>,
+
+        /// When the device was last heard from, see `glib::monotonic_time`.
+        pub last_seen: Cell<i64>,
+
+        pub health: RefCell<LinkHealth>,
+
+        pub decoder: RefCell<Decoder>,
+
+        /// The last measurement decoded from any of the characteristics.
+        pub measurement: Cell<Measurement>,
+
+        pub link_sub_ids: RefCell<Vec<(&'static str, SignalSubscriptionId)>>,
+
+        pub link_timer: RefCell<Option<glib::SourceId>>,
+
+        pub property_subscription: RefCell<Option<Subscription>>,
+
+        pub trainer: RefCell<Option<Trainer>>,
+
+        pub spin_down_sub_ids: RefCell<Vec<(&'static str, SignalSubscriptionId)>>,
     }
 
     #[glib::object_subclass]
@@ -34,14 +111,49 @@ mod imp {
     }
 
     #[glib::derived_properties]
-    impl ObjectImpl for DevicePrivate {}
+    impl ObjectImpl for DevicePrivate {
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: OnceLock<Vec<Signal>> = OnceLock::new();
+            SIGNALS.get_or_init(|| vec![Signal::builder("measurement").build()])
+        }
+    }
 }
 
 use adw::subclass::prelude::ObjectSubclassIsExt;
-use gtk::glib::{self, Object, clone};
-use std::fmt::Display;
+use gtk::{
+    gio::{IOErrorEnum, SignalSubscriptionId},
+    glib::{self, Object, SignalHandlerId, clone, object::ObjectExt},
+};
+use std::{
+    cell::{Ref, RefCell},
+    fmt::Display,
+    rc::Rc,
+    time::Duration,
+};
+
+use super::{LinkHealth, device_list, service::DeviceUpdate};
+use crate::{
+    BLUETOOTH,
+    protocol::{
+        AddressKind, Advertisement, CYCLING_POWER_CONTROL_POINT, CyclingPowerError,
+        CyclingPowerRequest, CyclingPowerResponse, Decoder, MEASUREMENT_CHARACTERISTICS,
+        Measurement, SENSOR_LOCATION, SensorLocation, SpinDownEvent, SpinDownFailure,
+        TrainerCapabilities, TrainerCommand, sequence_number,
+    },
+};
+
+/// RSSI changes smaller than this, in dB, don't move a device in the list.
+const RSSI_HYSTERESIS: i32 = 8;
 
-use crate::BLUETOOTH;
+/// The RSSI of a device BlueZ has not heard since it started discovering.
+pub(super) const UNKNOWN_RSSI: i16 = -200;
+
+/// A device that was not heard from for this long may have changed its
+/// rotating address.
+const ABSENT_AFTER: Duration = Duration::from_secs(30);
+
+/// Zeroing a power meter can take a few seconds.
+const CONTROL_POINT_TIMEOUT: Duration = Duration::from_secs(10);
 
 glib::wrapper! {
     pub struct Device(ObjectSubclass<imp::DevicePrivate>);
@@ -60,27 +172,476 @@ impl Device {
             .property("paired", paired)
             .property("connected", connected)
             .property("rssi", rssi)
+            .property("stable-rssi", rssi)
             .property("object_path", object_path)
             .build()
     }
 
     pub fn register_property_listener(&self) {
-        *self.imp().rssi_sub_id.borrow_mut() = BLUETOOTH.start_rssi_monitoring(
+        *self.imp().property_subscription.borrow_mut() = BLUETOOTH.start_property_monitoring(
             self.object_path(),
             clone!(
                 #[weak(rename_to=slf)]
                 self.clone(),
-                move |rssi| {
-                    slf.set_rssi(rssi as i32);
-                }
+                move |update| slf.update_properties(update)
             ),
         );
     }
 
     pub fn unregister_property_listener(&self) {
-        if let Some(sub_id) = self.imp().rssi_sub_id.borrow_mut().take() {
-            BLUETOOTH.stop_rssi_monitoring(sub_id);
+        self.imp().property_subscription.take();
+    }
+
+    /// See [`Advertisement::fitness_rank`].
+    pub fn fitness_rank(&self) -> usize {
+        self.imp().advertisement.borrow().fitness_rank()
+    }
+
+    /// Orders devices for the list: connected ones first, then paired ones,
+    /// then by how relevant they are for a ride and finally by signal.
+    pub fn list_order(&self, other: &Device) -> std::cmp::Ordering {
+        let connection_rank = |device: &Device| match (device.connected(), device.paired()) {
+            (true, _) => 0,
+            (false, true) => 1,
+            (false, false) => 2,
+        };
+        connection_rank(self)
+            .cmp(&connection_rank(other))
+            .then_with(|| self.fitness_rank().cmp(&other.fitness_rank()))
+            .then_with(|| other.stable_rssi().cmp(&self.stable_rssi()))
+            .then_with(|| self.name().cmp(&other.name()))
+    }
+
+    pub fn mark_seen(&self) {
+        self.imp().last_seen.set(glib::monotonic_time());
+    }
+
+    /// Microseconds since the device was last heard from.
+    pub fn unseen_for(&self) -> i64 {
+        glib::monotonic_time() - self.imp().last_seen.get()
+    }
+
+    /// Whether both objects are the same physical device seen under
+    /// different object paths. Stable addresses are trusted over what is
+    /// advertised. Two sensors of the same model advertise the same data, so
+    /// a rotating address is only taken for an old one when the device
+    /// behind the old address is gone.
+    pub fn is_same_device(&self, other: &Device) -> bool {
+        if self.object_path() == other.object_path() {
+            return true;
+        }
+        if self.address_kind().is_stable() && other.address_kind().is_stable() {
+            return self.address().eq_ignore_ascii_case(&other.address());
+        }
+        if !self.is_absent() && !other.is_absent() {
+            return false;
         }
+        let fingerprint = self.imp().advertisement.borrow().fingerprint(&self.name());
+        fingerprint
+            == other
+                .imp()
+                .advertisement
+                .borrow()
+                .fingerprint(&other.name())
+    }
+
+    /// Whether the device is neither connected nor advertising, as when
+    /// BlueZ only remembers it or it moved on to another address.
+    fn is_absent(&self) -> bool {
+        !self.connected()
+            && (self.rssi() == i32::from(UNKNOWN_RSSI)
+                || self.unseen_for() > ABSENT_AFTER.as_micros() as i64)
+    }
+
+    fn address_kind(&self) -> AddressKind {
+        AddressKind::new(&self.address(), &self.address_type())
+    }
+
+    pub fn update_advertisement(&self, update: Advertisement) {
+        let advertisement = {
+            let mut advertisement = self.imp().advertisement.borrow_mut();
+            advertisement.update(update);
+            advertisement.clone()
+        };
+        self.set_brand(advertisement.brand().unwrap_or_default());
+        self.set_services(advertisement.fitness_services().join(", "));
+        self.set_in_use(advertisement.in_use());
+    }
+
+    pub fn connect(&self) {
+        if self.connected() {
+            return;
+        }
+        BLUETOOTH.connect_device(
+            &self.object_path(),
+            clone!(
+                #[weak(rename_to=slf)]
+                self,
+                move |result| match result {
+                    Ok(()) => {
+                        slf.set_connected(true);
+                        slf.start_link_monitoring();
+                        slf.setup_trainer();
+                        slf.set_power_meter(
+                            BLUETOOTH.has_characteristic(
+                                &slf.object_path(),
+                                CYCLING_POWER_CONTROL_POINT,
+                            ),
+                        );
+                    }
+                    Err(error) => log::error!("Could not connect to {}. {error}", slf.name()),
+                }
+            ),
+        );
+    }
+
+    /// Follows every measurement notification of the device to keep its
+    /// [`LinkHealth`] current, for as long as the device stays around.
+    fn start_link_monitoring(&self) {
+        self.stop_link_notifications();
+        let object_path = self.object_path();
+        for uuid in MEASUREMENT_CHARACTERISTICS {
+            if !BLUETOOTH.has_characteristic(&object_path, uuid) {
+                continue;
+            }
+            let sub_id = BLUETOOTH.start_notifications(
+                &object_path,
+                uuid,
+                clone!(
+                    #[weak(rename_to = slf)]
+                    self,
+                    move |value| slf.receive(uuid, value)
+                ),
+            );
+            if let Some(sub_id) = sub_id {
+                self.imp().link_sub_ids.borrow_mut().push((uuid, sub_id));
+            }
+        }
+        self.start_link_timer();
+    }
+
+    /// Stands in for a device of a recorded trace, which is then fed the
+    /// recorded values through [`Device::receive`].
+    pub(super) fn replayed(object_path: &str) -> Self {
+        let name = object_path.rsplit('/').next().unwrap_or(object_path);
+        let device = Self::new(
+            format!("Replay of {name}"),
+            false,
+            true,
+            0,
+            object_path.to_string(),
+        );
+        device.start_link_timer();
+        device
+    }
+
+    /// Handles a value notified on one of the measurement characteristics.
+    pub(super) fn receive(&self, uuid: &str, value: &[u8]) {
+        self.imp().health.borrow_mut().record(
+            uuid,
+            value,
+            sequence_number(uuid, value),
+            glib::monotonic_time(),
+        );
+        let measurement = self.imp().decoder.borrow_mut().decode(uuid, value);
+        if let Some(measurement) = measurement {
+            self.imp().measurement.set(measurement);
+            self.emit_by_name::<()>("measurement", &[]);
+        }
+    }
+
+    fn start_link_timer(&self) {
+        if self.imp().link_timer.borrow().is_none() {
+            let timer = glib::timeout_add_local(
+                Duration::from_secs(1),
+                clone!(
+                    #[weak(rename_to = slf)]
+                    self,
+                    #[upgrade_or]
+                    glib::ControlFlow::Break,
+                    move || {
+                        slf.sample_link();
+                        glib::ControlFlow::Continue
+                    }
+                ),
+            );
+            self.imp().link_timer.replace(Some(timer));
+        }
+    }
+
+    fn update_properties(&self, update: DeviceUpdate) {
+        if update.rssi.is_some() || update.advertisement != Advertisement::default() {
+            self.mark_seen();
+            device_list::restore(self);
+        }
+        if let Some(rssi) = update.rssi {
+            self.set_rssi(rssi as i32);
+            if (self.stable_rssi() - i32::from(rssi)).abs() >= RSSI_HYSTERESIS {
+                self.set_stable_rssi(i32::from(rssi));
+            }
+            self.imp().health.borrow_mut().record_rssi(rssi);
+        }
+        if let Some(paired) = update.paired {
+            self.set_paired(paired);
+        }
+        let monitored = !self.imp().link_sub_ids.borrow().is_empty();
+        match update.connected {
+            Some(true) if !self.connected() && monitored => {
+                log::info!("{} reconnected", self.name());
+                self.imp().health.borrow_mut().record_reconnect();
+                self.set_connected(true);
+                // Notifications do not survive the link going down.
+                self.start_link_monitoring();
+            }
+            Some(connected) if connected != self.connected() => {
+                log::info!(
+                    "{} {}",
+                    self.name(),
+                    if connected {
+                        "connected"
+                    } else {
+                        "disconnected"
+                    }
+                );
+                self.set_connected(connected);
+            }
+            _ => (),
+        }
+        self.update_advertisement(update.advertisement);
+    }
+
+    fn sample_link(&self) {
+        let now = glib::monotonic_time();
+        let (status, problem, rate) = {
+            let mut health = self.imp().health.borrow_mut();
+            health.sample(now);
+            (health.status(now), health.problem(), health.rate(now))
+        };
+        self.set_link_status(status);
+        self.set_link_problem(
+            problem
+                .map(|problem| problem.to_string())
+                .unwrap_or_default(),
+        );
+        self.set_link_rate(rate);
+    }
+
+    pub fn measurement(&self) -> Measurement {
+        self.imp().measurement.get()
+    }
+
+    /// Called for every measurement the device sends while connected, read
+    /// it with [`Device::measurement`].
+    pub fn connect_measurement<F: Fn(&Self) + 'static>(&self, callback: F) -> SignalHandlerId {
+        self.connect_local("measurement", false, move |values| {
+            let device = values[0].get::<Self>().expect("the sender is a device");
+            callback(&device);
+            None
+        })
+    }
+
+    pub fn link_health(&self) -> Ref<'_, LinkHealth> {
+        self.imp().health.borrow()
+    }
+
+    fn stop_link_notifications(&self) {
+        let object_path = self.object_path();
+        self.imp()
+            .link_sub_ids
+            .borrow_mut()
+            .drain(..)
+            .for_each(|(uuid, sub_id)| BLUETOOTH.stop_notifications(&object_path, uuid, sub_id));
+    }
+
+    fn setup_trainer(&self) {
+        let Some(mut trainer) = BLUETOOTH.detect_trainer(&self.object_path()) else {
+            return;
+        };
+        log::info!("{} is a {:?} trainer", self.name(), trainer.protocol());
+        // Learn what the trainer supports before offering any mode, the
+        // optional characteristics are simply missing on simpler trainers.
+        for uuid in trainer.capability_characteristics() {
+            if !BLUETOOTH.has_characteristic(&self.object_path(), uuid) {
+                continue;
+            }
+            match BLUETOOTH.read_characteristic(&self.object_path(), uuid) {
+                Ok(data) => trainer.read_capability(uuid, &data),
+                Err(error) => log::warn!("Could not read {uuid} from {}. {error}", self.name()),
+            }
+        }
+        log::debug!("{} capabilities: {:?}", self.name(), trainer.capabilities());
+        if let Err(error) = BLUETOOTH.start_trainer_control(&self.object_path(), &trainer) {
+            log::error!("Could not take control of {}. {error}", self.name());
+        }
+        self.imp().trainer.replace(Some(trainer));
+        self.set_controllable(true);
+    }
+
+    pub fn trainer_capabilities(&self) -> Option<TrainerCapabilities> {
+        self.imp()
+            .trainer
+            .borrow()
+            .as_ref()
+            .map(|trainer| trainer.capabilities())
+    }
+
+    pub fn send_trainer_command(&self, command: &TrainerCommand) -> Result<(), glib::Error> {
+        let result = match self.imp().trainer.borrow_mut().as_mut() {
+            Some(trainer) => BLUETOOTH.send_trainer_command(&self.object_path(), trainer, command),
+            None => Err(glib::Error::new(
+                IOErrorEnum::NotSupported,
+                "The device is not a controllable trainer",
+            )),
+        };
+        if result.is_ok() {
+            match command {
+                TrainerCommand::TargetPower(power) => {
+                    self.set_target_power(i32::from(*power));
+                    self.set_target(format!("{power} W"))
+                }
+                TrainerCommand::Resistance(percent) => {
+                    self.set_target_power(0);
+                    self.set_target(format!("{percent:.0} % resistance"))
+                }
+                TrainerCommand::Simulation(simulation) => {
+                    self.set_target_power(0);
+                    self.set_grade(simulation.grade);
+                    self.set_target(format!("{:.1} % grade", simulation.grade))
+                }
+                TrainerCommand::UserConfiguration(_) => (),
+            }
+        }
+        result
+    }
+
+    /// Starts a spin down calibration. Progress goes to `on_event`, while the
+    /// trainer data keeps flowing to `on_measurement` so the rider can be
+    /// guided towards the target speed.
+    pub fn start_spin_down<F, G>(&self, on_event: F, on_measurement: G)
+    where
+        F: Fn(SpinDownEvent) + 'static,
+        G: Fn(Measurement) + 'static,
+    {
+        self.stop_spin_down();
+        let Some(trainer) = self.imp().trainer.borrow().clone() else {
+            on_event(SpinDownEvent::Failed(SpinDownFailure::Unsupported));
+            return;
+        };
+        let Some(request) = trainer.spin_down_request() else {
+            on_event(SpinDownEvent::Failed(SpinDownFailure::Unsupported));
+            return;
+        };
+
+        let on_event = Rc::new(on_event);
+        let on_measurement = Rc::new(on_measurement);
+        let trainer = Rc::new(trainer);
+        let decoder = Rc::new(RefCell::new(Decoder::default()));
+        let data_characteristic = trainer.protocol().data_characteristic();
+        let mut characteristics = trainer.spin_down_characteristics().to_vec();
+        if !characteristics.contains(&data_characteristic) {
+            characteristics.push(data_characteristic);
+        }
+
+        let object_path = self.object_path();
+        for uuid in characteristics {
+            let on_event = on_event.clone();
+            let on_measurement = on_measurement.clone();
+            let trainer = trainer.clone();
+            let decoder = decoder.clone();
+            if let Some(sub_id) = BLUETOOTH.start_notifications(&object_path, uuid, move |value| {
+                if let Some(event) = trainer.decode_spin_down(uuid, value) {
+                    on_event(event);
+                }
+                if let Some(measurement) = decoder.borrow_mut().decode(uuid, value) {
+                    on_measurement(measurement);
+                }
+            }) {
+                self.imp()
+                    .spin_down_sub_ids
+                    .borrow_mut()
+                    .push((uuid, sub_id));
+            }
+        }
+
+        let control_characteristic = trainer.protocol().control_characteristic();
+        if let Err(error) =
+            BLUETOOTH.write_characteristic(&object_path, control_characteristic, &request)
+        {
+            log::error!("Could not start the spin down of {}. {error}", self.name());
+            on_event(SpinDownEvent::Failed(SpinDownFailure::Rejected));
+        }
+    }
+
+    /// Runs a Cycling Power Control Point procedure, handing its outcome to
+    /// the callback once the power meter answers.
+    pub fn cycling_power_request<F>(&self, request: CyclingPowerRequest, callback: F)
+    where
+        F: FnOnce(Result<CyclingPowerResponse, CyclingPowerError>) + 'static,
+    {
+        let object_path = self.object_path();
+        let callback = Rc::new(RefCell::new(Some(callback)));
+        let sub_id = Rc::new(RefCell::new(None::<SignalSubscriptionId>));
+        let finish = Rc::new(clone!(
+            #[strong]
+            object_path,
+            #[strong]
+            sub_id,
+            move |result| {
+                if let Some(sub_id) = sub_id.borrow_mut().take() {
+                    BLUETOOTH.stop_notifications(&object_path, CYCLING_POWER_CONTROL_POINT, sub_id);
+                }
+                let callback = callback.borrow_mut().take();
+                if let Some(callback) = callback {
+                    callback(result);
+                }
+            }
+        ));
+
+        // The control point only answers through indications, they have to
+        // be on before writing to it.
+        *sub_id.borrow_mut() = BLUETOOTH.start_notifications(
+            &object_path,
+            CYCLING_POWER_CONTROL_POINT,
+            clone!(
+                #[strong]
+                finish,
+                move |value| {
+                    if let Some(result) = request.decode_response(value) {
+                        finish(result);
+                    }
+                }
+            ),
+        );
+        if let Err(error) = BLUETOOTH.write_characteristic(
+            &object_path,
+            CYCLING_POWER_CONTROL_POINT,
+            &request.encode(),
+        ) {
+            log::error!("Could not send {request:?} to {}. {error}", self.name());
+            finish(Err(CyclingPowerError::Failed));
+            return;
+        }
+        glib::timeout_add_local_once(CONTROL_POINT_TIMEOUT, move || {
+            finish(Err(CyclingPowerError::TimedOut))
+        });
+    }
+
+    pub fn sensor_location(&self) -> Option<SensorLocation> {
+        BLUETOOTH
+            .read_characteristic(&self.object_path(), SENSOR_LOCATION)
+            .inspect_err(|error| log::warn!("Could not read the sensor location. {error}"))
+            .ok()
+            .and_then(|value| value.first().copied())
+            .map(SensorLocation)
+    }
+
+    pub fn stop_spin_down(&self) {
+        let object_path = self.object_path();
+        self.imp()
+            .spin_down_sub_ids
+            .borrow_mut()
+            .drain(..)
+            .for_each(|(uuid, sub_id)| BLUETOOTH.stop_notifications(&object_path, uuid, sub_id));
     }
 }
 
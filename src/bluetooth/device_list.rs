@@ -0,0 +1,145 @@
+//! The one list of devices every view shows, mirroring what BlueZ knows.
+
+use std::{cell::RefCell, time::Duration};
+
+use gtk::{
+    gio::{ListStore, prelude::ListModelExt},
+    glib::{
+        self,
+        object::{Cast, CastNone},
+    },
+};
+
+use super::{Device, subscription::Subscription};
+
+thread_local! {
+    // GObjects can't be shared with the `Sync` service, the list lives on
+    // the main thread instead.
+    static DEVICES: ListStore = ListStore::new::<Device>();
+    static TRACKING: RefCell<Option<Subscription>> = const { RefCell::new(None) };
+    // Devices that stopped advertising. BlueZ may still hold on to them, so
+    // they keep listening and return as soon as they are heard again.
+    static EVICTED: RefCell<Vec<Device>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Devices that have not been heard from for this long leave the list.
+const EVICT_AFTER: Duration = Duration::from_secs(60);
+const EVICT_INTERVAL: Duration = Duration::from_secs(10);
+
+pub fn model() -> ListStore {
+    DEVICES.with(ListStore::clone)
+}
+
+pub fn is_tracking() -> bool {
+    TRACKING.with(|tracking| tracking.borrow().is_some())
+}
+
+/// Keeps the list following the device events for the rest of the run.
+pub fn track(subscription: Option<Subscription>) {
+    TRACKING.with(|tracking| tracking.replace(subscription));
+    glib::timeout_add_local(EVICT_INTERVAL, || {
+        evict_stale();
+        glib::ControlFlow::Continue
+    });
+}
+
+/// Moves the devices that went quiet out of the list. Paired and connected
+/// ones stay, the rider expects to find them there.
+fn evict_stale() {
+    let devices = model();
+    let mut evicted = vec![];
+    devices.retain(|device| match device.downcast_ref::<Device>() {
+        Some(device)
+            if !device.paired()
+                && !device.connected()
+                && device.unseen_for() > EVICT_AFTER.as_micros() as i64 =>
+        {
+            log::debug!("Device evicted: {device}");
+            evicted.push(device.clone());
+            false
+        }
+        _ => true,
+    });
+    EVICTED.with(|list| list.borrow_mut().extend(evicted));
+}
+
+/// Brings an evicted device back once it is heard from again.
+pub fn restore(device: &Device) {
+    let evicted = EVICTED.with(|list| {
+        let mut list = list.borrow_mut();
+        let position = list.iter().position(|evicted| evicted == device)?;
+        Some(list.remove(position))
+    });
+    if let Some(device) = evicted {
+        log::debug!("Device is back: {device}");
+        model().append(&device);
+    }
+}
+
+pub fn add(device: Device) {
+    device.mark_seen();
+    let devices = model();
+    let known = (0..devices.n_items()).find_map(|position| {
+        devices
+            .item(position)
+            .and_downcast::<Device>()
+            .filter(|known| known.is_same_device(&device))
+            .map(|known| (position, known))
+    });
+    let Some((position, known)) = known else {
+        log::debug!("Device found: {device}");
+        device.register_property_listener();
+        devices.append(&device);
+        return;
+    };
+    if known.object_path() == device.object_path() {
+        return;
+    }
+    // A device with a rotating address comes back under a new object path.
+    // Keep the entry that is set up, otherwise follow the newest address
+    // since the old one is no longer advertised.
+    if (known.paired() || known.connected()) && !device.connected() {
+        log::debug!("{device} is {known} under another address, keeping the known one");
+        return;
+    }
+    log::debug!("{device} replaces {known} which changed its address");
+    known.unregister_property_listener();
+    device.register_property_listener();
+    devices.splice(position, 1, &[device]);
+}
+
+pub fn remove(object_path: &str) {
+    EVICTED.with(|list| {
+        list.borrow_mut()
+            .retain(|device| !device.object_path().eq_ignore_ascii_case(object_path))
+    });
+    let devices = model();
+    devices.retain(|device| match device.downcast_ref::<Device>() {
+        Some(device) if device.object_path().eq_ignore_ascii_case(object_path) => {
+            log::debug!("Device removed: {device}");
+            device.unregister_property_listener();
+            false
+        }
+        _ => true,
+    });
+}
+
+/// The stand-in for a device of a replayed trace, added to the list the
+/// first time the trace mentions it.
+pub fn replayed(object_path: &str) -> Device {
+    let devices = model();
+    let known = (0..devices.n_items()).find_map(|position| {
+        devices
+            .item(position)
+            .and_downcast::<Device>()
+            .filter(|device| device.object_path() == object_path)
+    });
+    let device = known.unwrap_or_else(|| {
+        let device = Device::replayed(object_path);
+        log::debug!("Replaying device: {device}");
+        devices.append(&device);
+        device
+    });
+    device.mark_seen();
+    device
+}
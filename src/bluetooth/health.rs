@@ -0,0 +1,239 @@
+//! Link statistics of a connected sensor, to tell apart a weak radio link, a
+//! misbehaving sensor and an app that could not keep up.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Display;
+
+use gtk::glib;
+
+/// Timestamps are in microseconds, as `glib::monotonic_time` returns them.
+const SECOND: i64 = 1_000_000;
+/// The notification rate is averaged over this window.
+const RATE_WINDOW: i64 = 10 * SECOND;
+/// Problems older than this no longer count against the link.
+const RECENT: i64 = 60 * SECOND;
+/// How many one second samples of the rate and RSSI are kept.
+const HISTORY_LENGTH: usize = 120;
+/// A silence longer than this is a gap, whatever the usual rate is.
+const MIN_GAP: i64 = 1_500_000;
+/// A silence longer than this many usual intervals is a gap.
+const GAP_INTERVALS: i64 = 3;
+/// Below this the signal is too weak for a dependable link, in dBm.
+const WEAK_RSSI: i16 = -85;
+
+/// How a sensor link is doing overall.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, glib::Enum)]
+#[enum_type(name = "LinkStatus")]
+pub enum LinkStatus {
+    /// Nothing was received yet.
+    #[default]
+    Unknown,
+    Good,
+    /// Some packets were lost or mangled in the last minute.
+    Unstable,
+    /// The sensor went silent or keeps losing packets.
+    Poor,
+}
+
+impl Display for LinkStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkStatus::Unknown => write!(f, "No data yet"),
+            LinkStatus::Good => write!(f, "Good"),
+            LinkStatus::Unstable => write!(f, "Unstable"),
+            LinkStatus::Poor => write!(f, "Poor"),
+        }
+    }
+}
+
+/// The most likely culprit behind a degraded link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkProblem {
+    /// Packets are lost while the signal is weak.
+    Radio,
+    /// The sensor repeats or reorders its own data.
+    Sensor,
+    /// The app did not get to run on time.
+    App,
+}
+
+impl Display for LinkProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkProblem::Radio => write!(f, "The signal is weak, move the computer closer"),
+            LinkProblem::Sensor => write!(f, "The sensor sends repeated or out of order data"),
+            LinkProblem::App => write!(f, "The app was too busy to keep up"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Arrival {
+    time: i64,
+    data: Vec<u8>,
+    sequence: Option<u16>,
+}
+
+#[derive(Debug, Default)]
+pub struct LinkHealth {
+    last: HashMap<String, Arrival>,
+    /// Typical time between two notifications of each characteristic.
+    intervals: HashMap<String, i64>,
+    arrivals: VecDeque<i64>,
+    last_rssi: Option<i16>,
+    last_sample: Option<i64>,
+    gap_times: VecDeque<i64>,
+    sensor_fault_times: VecDeque<i64>,
+    stall_times: VecDeque<i64>,
+    /// One sample per second of the notification rate, oldest first.
+    pub rate_history: VecDeque<f64>,
+    /// One sample per second of the RSSI, oldest first.
+    pub rssi_history: VecDeque<Option<i16>>,
+    pub notifications: u64,
+    pub gaps: u32,
+    pub duplicates: u32,
+    pub out_of_order: u32,
+    pub reconnects: u32,
+    pub stalls: u32,
+}
+
+impl LinkHealth {
+    /// Accounts for a notification received at `now`.
+    pub fn record(&mut self, uuid: &str, data: &[u8], sequence: Option<u16>, now: i64) {
+        self.notifications += 1;
+        self.arrivals.push_back(now);
+        if let Some(last) = self.last.get(uuid) {
+            let elapsed = now - last.time;
+            let interval = self.intervals.get(uuid).copied();
+            if let Some(interval) = interval {
+                if elapsed > (interval * GAP_INTERVALS).max(MIN_GAP) {
+                    self.gaps += 1;
+                    self.gap_times.push_back(now);
+                } else if last.data == data && elapsed < interval / 4 {
+                    // The same value again long before the next one was due.
+                    self.duplicates += 1;
+                    self.sensor_fault_times.push_back(now);
+                }
+            }
+            if let (Some(previous), Some(sequence)) = (last.sequence, sequence)
+                && sequence.wrapping_sub(previous) > u16::MAX / 2
+            {
+                self.out_of_order += 1;
+                self.sensor_fault_times.push_back(now);
+            }
+            // Follow the usual interval slowly, so that gaps don't stretch it.
+            let interval = match interval {
+                Some(interval) => (interval * 7 + elapsed.min(interval * GAP_INTERVALS)) / 8,
+                None => elapsed,
+            };
+            self.intervals.insert(uuid.to_string(), interval);
+        }
+        self.last.insert(
+            uuid.to_string(),
+            Arrival {
+                time: now,
+                data: data.to_vec(),
+                sequence,
+            },
+        );
+        self.forget(now);
+    }
+
+    pub fn record_rssi(&mut self, rssi: i16) {
+        self.last_rssi = Some(rssi);
+    }
+
+    pub fn record_reconnect(&mut self) {
+        self.reconnects += 1;
+    }
+
+    /// Takes the once a second sample for the histories. A sample that comes
+    /// late means the main loop was blocked, so anything lost meanwhile is
+    /// on the app.
+    pub fn sample(&mut self, now: i64) {
+        if let Some(last_sample) = self.last_sample
+            && now - last_sample > MIN_GAP
+        {
+            self.stalls += 1;
+            self.stall_times.push_back(now);
+        }
+        self.last_sample = Some(now);
+        self.forget(now);
+        self.rate_history.push_back(self.rate(now));
+        self.rssi_history.push_back(self.last_rssi);
+        while self.rate_history.len() > HISTORY_LENGTH {
+            self.rate_history.pop_front();
+        }
+        while self.rssi_history.len() > HISTORY_LENGTH {
+            self.rssi_history.pop_front();
+        }
+    }
+
+    /// Notifications per second over the last few seconds.
+    pub fn rate(&self, now: i64) -> f64 {
+        let recent: Vec<i64> = self
+            .arrivals
+            .iter()
+            .copied()
+            .filter(|time| now - time <= RATE_WINDOW)
+            .collect();
+        // Right after connecting the window is not full yet.
+        let window = recent
+            .first()
+            .map(|first| (now - first).clamp(SECOND, RATE_WINDOW))
+            .unwrap_or(RATE_WINDOW);
+        recent.len() as f64 * SECOND as f64 / window as f64
+    }
+
+    pub fn status(&self, now: i64) -> LinkStatus {
+        let Some(last_arrival) = self.last.values().map(|arrival| arrival.time).max() else {
+            return LinkStatus::Unknown;
+        };
+        let silent = now - last_arrival > self.longest_interval().max(MIN_GAP) * GAP_INTERVALS;
+        let problems = self.gap_times.len() + self.sensor_fault_times.len();
+        if silent || self.gap_times.len() >= 3 {
+            LinkStatus::Poor
+        } else if problems > 0 {
+            LinkStatus::Unstable
+        } else {
+            LinkStatus::Good
+        }
+    }
+
+    /// What most likely caused the problems of the last minute.
+    pub fn problem(&self) -> Option<LinkProblem> {
+        if !self.stall_times.is_empty() {
+            Some(LinkProblem::App)
+        } else if !self.sensor_fault_times.is_empty() {
+            Some(LinkProblem::Sensor)
+        } else if !self.gap_times.is_empty() || self.last_rssi.is_some_and(|rssi| rssi < WEAK_RSSI)
+        {
+            Some(LinkProblem::Radio)
+        } else {
+            None
+        }
+    }
+
+    fn longest_interval(&self) -> i64 {
+        self.intervals.values().copied().max().unwrap_or(0)
+    }
+
+    fn forget(&mut self, now: i64) {
+        for times in [
+            &mut self.gap_times,
+            &mut self.sensor_fault_times,
+            &mut self.stall_times,
+        ] {
+            while times.front().is_some_and(|time| now - time > RECENT) {
+                times.pop_front();
+            }
+        }
+        while self
+            .arrivals
+            .front()
+            .is_some_and(|time| now - time > RATE_WINDOW)
+        {
+            self.arrivals.pop_front();
+        }
+    }
+}
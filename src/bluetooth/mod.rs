@@ -1,4 +1,12 @@
 mod service;
 mod device;
+mod device_list;
+mod health;
+mod scan_controller;
+mod subscription;
+mod traffic;
 pub use device::Device;
+pub use health::{LinkHealth, LinkStatus};
+pub use scan_controller::{ScanController, ScanState};
 pub use service::BluetoothService;
+pub use subscription::Subscription;
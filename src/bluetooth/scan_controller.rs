@@ -0,0 +1,155 @@
+mod imp {
+    use std::cell::{Cell, RefCell};
+
+    use adw::prelude::ObjectExt;
+    use adw::subclass::prelude::{DerivedObjectProperties, ObjectImpl, ObjectSubclass};
+    use gtk::glib::{self, Properties, SourceId};
+
+    use crate::bluetooth::Subscription;
+
+    use super::ScanState;
+
+    #[derive(Debug, Properties)]
+    #[properties(wrapper_type = super::ScanController)]
+    pub struct ScanControllerPrivate {
+        #[property(name = "state", get, set, builder(ScanState::default()))]
+        state: Cell<ScanState>,
+
+        /// How long a scan runs before stopping by itself, in seconds.
+        #[property(name = "timeout", get, set, minimum = 1, default = 30)]
+        timeout: Cell<u32>,
+
+        /// Whether the adapter is discovering, which other apps can cause
+        /// as well.
+        #[property(name = "discovering", get, set)]
+        discovering: Cell<bool>,
+
+        pub timeout_source: RefCell<Option<SourceId>>,
+
+        pub discovery_subscription: RefCell<Option<Subscription>>,
+    }
+
+    impl Default for ScanControllerPrivate {
+        fn default() -> Self {
+            Self {
+                state: Cell::default(),
+                timeout: Cell::new(30),
+                discovering: Cell::default(),
+                timeout_source: RefCell::default(),
+                discovery_subscription: RefCell::default(),
+            }
+        }
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for ScanControllerPrivate {
+        const NAME: &'static str = "ScanController";
+        type Type = super::ScanController;
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for ScanControllerPrivate {}
+}
+
+use adw::subclass::prelude::ObjectSubclassIsExt;
+use gtk::glib::{self, Object, clone};
+use std::time::Duration;
+
+use crate::BLUETOOTH;
+
+/// Where a [`ScanController`] is at.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, glib::Enum)]
+#[enum_type(name = "ScanState")]
+pub enum ScanState {
+    #[default]
+    Idle,
+    Scanning,
+    /// The scan ran its course, or was ended from outside the app.
+    Finished,
+}
+
+glib::wrapper! {
+    /// Runs discovery for a limited time on behalf of a view.
+    pub struct ScanController(ObjectSubclass<imp::ScanControllerPrivate>);
+}
+
+impl ScanController {
+    pub fn new() -> Self {
+        Object::builder().build()
+    }
+
+    pub fn start(&self) {
+        self.stop_timeout();
+        if self.imp().discovery_subscription.borrow().is_none() {
+            let subscription = BLUETOOTH.start_discovery_monitoring(clone!(
+                #[weak(rename_to = slf)]
+                self,
+                move |discovering| slf.discovery_changed(discovering)
+            ));
+            self.imp().discovery_subscription.replace(subscription);
+        }
+        log::debug!("Scanning for {} seconds", self.timeout());
+        BLUETOOTH.start_scanning_for_devices();
+        self.set_discovering(BLUETOOTH.is_discovering().unwrap_or(false));
+        self.set_state(ScanState::Scanning);
+        let source = glib::timeout_add_local_once(
+            Duration::from_secs(self.timeout().into()),
+            clone!(
+                #[weak(rename_to = slf)]
+                self,
+                move || {
+                    slf.imp().timeout_source.take();
+                    slf.finish();
+                }
+            ),
+        );
+        self.imp().timeout_source.replace(Some(source));
+    }
+
+    /// Ends the scan early, like when the view goes away.
+    pub fn stop(&self) {
+        self.stop_timeout();
+        if self.state() == ScanState::Scanning {
+            self.end_discovery();
+        }
+        self.imp().discovery_subscription.take();
+        self.set_state(ScanState::Idle);
+    }
+
+    fn finish(&self) {
+        self.stop_timeout();
+        if self.state() == ScanState::Scanning {
+            self.end_discovery();
+            self.set_state(ScanState::Finished);
+        }
+    }
+
+    fn end_discovery(&self) {
+        if BLUETOOTH.stop_scanning_for_devices().is_err() {
+            log::warn!("Could not stop scanning for devices");
+        }
+    }
+
+    /// Another app stopping discovery, or the adapter being reset, ends
+    /// the scan so the rider can start it again.
+    fn discovery_changed(&self, discovering: bool) {
+        self.set_discovering(discovering);
+        if !discovering && self.state() == ScanState::Scanning {
+            log::info!("Discovery was stopped from outside the app");
+            self.stop_timeout();
+            self.set_state(ScanState::Finished);
+        }
+    }
+
+    fn stop_timeout(&self) {
+        if let Some(source) = self.imp().timeout_source.take() {
+            source.remove();
+        }
+    }
+}
+
+impl Default for ScanController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,45 +1,125 @@
 use std::{
     collections::HashMap,
+    path::{Path, PathBuf},
     rc::Rc,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
 };
 
 use gtk::{
     gio::{
-        BusType, Cancellable, DBusCallFlags, DBusConnection, DBusError, DBusSignalFlags,
-        SignalSubscriptionId,
+        BusType, Cancellable, DBusCallFlags, DBusConnection, DBusConnectionFlags, DBusError,
+        DBusSignalFlags, ListModel, SignalSubscriptionId,
     },
     glib::{
-        Variant, VariantTy,
-        variant::{FromVariant, ObjectPath, ToVariant},
+        SourceId, Variant, VariantTy, clone,
+        object::Cast,
+        variant::{ObjectPath, ToVariant},
     },
 };
 
-use super::Device;
+use super::{
+    Device,
+    device::UNKNOWN_RSSI,
+    device_list,
+    subscription::Subscription,
+    traffic::{self, TrafficKind, TrafficRecorder},
+};
+use crate::protocol::{Advertisement, Trainer, TrainerCommand, TrainerProtocol};
 
 const BLUEZ_BUS_NAME: Option<&str> = Some("org.bluez");
 const ADAPTER_INTERFACE: &str = "org.bluez.Adapter1";
 const DEVICE_INTERFACE: &str = "org.bluez.Device1";
 const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
 const OBJECT_MANAGER_INTERFACE: &str = "org.freedesktop.DBus.ObjectManager";
+const GATT_CHARACTERISTIC_INTERFACE: &str = "org.bluez.GattCharacteristic1";
+
+type ManagedObjects = HashMap<ObjectPath, HashMap<String, HashMap<String, Variant>>>;
+
+/// The bus BlueZ is expected on. Anything but the system bus is only useful
+/// together with the `bike-simulator` binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BluezBus {
+    System,
+    Session,
+    Address(String),
+}
+
+impl BluezBus {
+    /// Reads `BIKE_BLUEZ_BUS`, which is either `system` (the default),
+    /// `session` or the address of a private bus.
+    pub fn from_env() -> Self {
+        match std::env::var("BIKE_BLUEZ_BUS").as_deref() {
+            Ok("session") => BluezBus::Session,
+            Ok("system") | Ok("") | Err(_) => BluezBus::System,
+            Ok(address) => BluezBus::Address(address.to_string()),
+        }
+    }
+
+    fn connect(&self) -> Result<DBusConnection, gtk::glib::Error> {
+        match self {
+            BluezBus::System => gtk::gio::bus_get_sync(BusType::System, Cancellable::NONE),
+            BluezBus::Session => gtk::gio::bus_get_sync(BusType::Session, Cancellable::NONE),
+            BluezBus::Address(address) => DBusConnection::for_address_sync(
+                address,
+                DBusConnectionFlags::AUTHENTICATION_CLIENT
+                    | DBusConnectionFlags::MESSAGE_BUS_CONNECTION,
+                None,
+                Cancellable::NONE,
+            ),
+        }
+    }
+}
+
+/// The `Device1` properties that changed, see
+/// [`BluetoothService::start_property_monitoring`].
+pub struct DeviceUpdate {
+    pub rssi: Option<i16>,
+    pub paired: Option<bool>,
+    pub connected: Option<bool>,
+    pub advertisement: Advertisement,
+}
+
+/// A change in the devices BlueZ knows about, see
+/// [`BluetoothService::subscribe_device_events`].
+pub enum DeviceEvent {
+    Added(Device),
+    /// The object path of the device that went away.
+    Removed(String),
+}
+
+/// A characteristic notifications were started on.
+struct Notifying {
+    device: String,
+    uuid: String,
+    subscribers: usize,
+}
 
 pub struct BluetoothService {
     connection: Result<DBusConnection, gtk::glib::Error>,
     adapter_index: usize,
     adapters: Vec<ObjectPath>,
-    interface_added_sub_id: Arc<Mutex<Option<SignalSubscriptionId>>>,
-    interface_removed_sub_id: Arc<Mutex<Option<SignalSubscriptionId>>>,
+    recorder: Arc<Mutex<Option<TrafficRecorder>>>,
+    /// The notifying characteristics by object path, with how many
+    /// subscribers each has. BlueZ allows a single `StartNotify` per client,
+    /// so it is only called for the first subscriber and `StopNotify` for
+    /// the last one.
+    notifying: Mutex<HashMap<String, Notifying>>,
 }
 
 impl BluetoothService {
     pub fn new() -> Self {
-        let connection = gtk::gio::bus_get_sync(BusType::System, Cancellable::NONE);
+        Self::with_bus(BluezBus::from_env())
+    }
+
+    pub fn with_bus(bus: BluezBus) -> Self {
+        log::debug!("Connecting to BlueZ on the {bus:?} bus");
+        let connection = bus.connect();
         let mut slf = Self {
             connection,
             adapter_index: 0,
             adapters: vec![],
-            interface_added_sub_id: Arc::new(Mutex::new(None)),
-            interface_removed_sub_id: Arc::new(Mutex::new(None)),
+            recorder: Arc::new(Mutex::new(None)),
+            notifying: Mutex::new(HashMap::new()),
         };
         slf.adapters.append(&mut slf.get_adapters());
         slf
@@ -50,6 +130,15 @@ impl BluetoothService {
     }
 
     pub fn is_adapter_powered(&self) -> Result<bool, gtk::glib::Error> {
+        self.adapter_flag("Powered")
+    }
+
+    /// Whether the adapter is discovering, for this or any other app.
+    pub fn is_discovering(&self) -> Result<bool, gtk::glib::Error> {
+        self.adapter_flag("Discovering")
+    }
+
+    fn adapter_flag(&self, name: &str) -> Result<bool, gtk::glib::Error> {
         if !self.is_valid() {
             return Err(DBusError::new_for_dbus_error(
                 "No bluetooth connection",
@@ -57,60 +146,419 @@ impl BluetoothService {
             ));
         }
 
-        let powered_variant = &self.connection.clone()?.call_sync(
+        let variant = &self.connection.clone()?.call_sync(
             BLUEZ_BUS_NAME,
             self.adapters
                 .get(self.adapter_index)
                 .expect("At least one adapter is needed"),
             PROPERTIES_INTERFACE,
             "Get",
-            Some(&(ADAPTER_INTERFACE, "Powered").to_variant()),
+            Some(&(ADAPTER_INTERFACE, name).to_variant()),
             Some(VariantTy::ANY),
             DBusCallFlags::NONE,
             300,
             Cancellable::NONE,
         )?;
-        powered_variant
+        variant
             .get::<(Variant,)>()
             .and_then(|(variant,)| variant.get::<bool>())
             .ok_or(DBusError::new_for_dbus_error(
                 "Invalid property",
-                "The 'Powered' property could not be read.",
+                &format!("The '{name}' property could not be read."),
             ))
     }
 
-    pub fn start_adapter_monitoring<F>(&self, closure: F)
+    pub fn start_adapter_monitoring<F>(&self, closure: F) -> Option<Subscription>
+    where
+        F: Fn(bool) + 'static,
+    {
+        self.monitor_adapter_flag("Powered", closure)
+    }
+
+    /// Follows the `Discovering` property, which other apps change too.
+    pub fn start_discovery_monitoring<F>(&self, closure: F) -> Option<Subscription>
+    where
+        F: Fn(bool) + 'static,
+    {
+        self.monitor_adapter_flag("Discovering", closure)
+    }
+
+    fn monitor_adapter_flag<F>(&self, name: &'static str, closure: F) -> Option<Subscription>
     where
         F: Fn(bool) + 'static,
     {
         if !self.is_valid() {
+            return None;
+        };
+        let connection = self.connection.as_ref().ok()?;
+        let sub_id = connection.signal_subscribe(
+            BLUEZ_BUS_NAME,
+            Some(PROPERTIES_INTERFACE),
+            Some("PropertiesChanged"),
+            self.adapters
+                .get(self.adapter_index)
+                .map(|object_path| object_path.as_str()),
+            Some(ADAPTER_INTERFACE),
+            DBusSignalFlags::NONE,
+            move |_, _, _, _, _, value| {
+                let _ = value
+                    .get::<(String, HashMap<String, Variant>, Vec<String>)>()
+                    .map(|(_, map, _)| map)
+                    .filter(|map| map.contains_key(name))
+                    .inspect(|map| {
+                        if let Some(value) = map.get(name).and_then(|p| p.get::<bool>()) {
+                            closure(value);
+                        }
+                    });
+            },
+        );
+        Some(Subscription::new(connection, vec![sub_id]))
+    }
+
+    pub fn start_capture(&self, directory: &Path) -> std::io::Result<PathBuf> {
+        let recorder = TrafficRecorder::create(directory)?;
+        let path = recorder.path().to_path_buf();
+        self.recorder
+            .lock()
+            .map_err(|_| std::io::Error::other("Could not acquire the capture lock"))?
+            .replace(recorder);
+        log::info!("Capturing bluetooth traffic to {}", path.display());
+        Ok(path)
+    }
+
+    fn capture(
+        recorder: &Mutex<Option<TrafficRecorder>>,
+        kind: TrafficKind,
+        device: &str,
+        uuid: &str,
+        value: &[u8],
+    ) {
+        if let Ok(mut recorder) = recorder.lock()
+            && let Some(active_recorder) = recorder.as_mut()
+            && let Err(error) = active_recorder.record(kind, device, uuid, value)
+        {
+            log::error!("Could not write to the traffic capture, stopping it. {error}");
+            recorder.take();
+        }
+    }
+
+    /// Plays a trace recorded with [`BluetoothService::start_capture`] back
+    /// through stand-ins for the recorded devices, so their measurements
+    /// reach everything following the device list. Writes are skipped.
+    pub fn start_replay(&self, trace: &Path, speed: f64) -> std::io::Result<SourceId> {
+        let events = traffic::load_trace(trace)?;
+        log::info!(
+            "Replaying {} events from {} at {speed}x",
+            events.len(),
+            trace.display()
+        );
+        Ok(traffic::replay(events, speed, |event| match event.kind {
+            TrafficKind::Write => log::debug!("Skipping a replayed write to {}", event.uuid),
+            TrafficKind::Notification | TrafficKind::Indication => {
+                device_list::replayed(&event.device).receive(&event.uuid, &event.value)
+            }
+        }))
+    }
+
+    fn managed_objects(connection: &DBusConnection) -> Option<ManagedObjects> {
+        connection
+            .call_sync(
+                BLUEZ_BUS_NAME,
+                "/",
+                OBJECT_MANAGER_INTERFACE,
+                "GetManagedObjects",
+                None,
+                Some(VariantTy::ANY),
+                DBusCallFlags::NONE,
+                3000,
+                Cancellable::NONE,
+            )
+            .ok()?
+            .get::<(ManagedObjects,)>()
+            .map(|(objects,)| objects)
+    }
+
+    fn find_characteristic(
+        &self,
+        connection: &DBusConnection,
+        device: &str,
+        uuid: &str,
+    ) -> Option<(String, Vec<String>)> {
+        let device_prefix = format!("{device}/");
+        BluetoothService::managed_objects(connection)?
+            .into_iter()
+            .filter(|(object_path, _)| object_path.as_str().starts_with(&device_prefix))
+            .find_map(|(object_path, mut interfaces)| {
+                let characteristic = interfaces.remove(GATT_CHARACTERISTIC_INTERFACE)?;
+                characteristic
+                    .get("UUID")
+                    .and_then(|variant| variant.get::<String>())
+                    .filter(|characteristic_uuid| characteristic_uuid.eq_ignore_ascii_case(uuid))?;
+                let flags = characteristic
+                    .get("Flags")
+                    .and_then(|variant| variant.get::<Vec<String>>())
+                    .unwrap_or_default();
+                Some((object_path.to_string(), flags))
+            })
+    }
+
+    /// Subscribes to the raw values notified or indicated on a characteristic.
+    pub fn start_notifications<F>(
+        &self,
+        device: &str,
+        uuid: &str,
+        callback: F,
+    ) -> Option<SignalSubscriptionId>
+    where
+        F: Fn(&[u8]) + 'static,
+    {
+        let connection = self.connection.as_ref().ok()?;
+        let Some((characteristic, flags)) = self.find_characteristic(connection, device, uuid)
+        else {
+            log::warn!("Characteristic {uuid} not found on {device}");
+            return None;
+        };
+        let kind = if flags.iter().any(|flag| flag == "notify") {
+            TrafficKind::Notification
+        } else {
+            TrafficKind::Indication
+        };
+
+        let subscribed = Notifying {
+            device: device.to_string(),
+            uuid: uuid.to_string(),
+            subscribers: 0,
+        };
+        let recorder = self.recorder.clone();
+        let device = device.to_string();
+        let uuid = uuid.to_string();
+        let sub_id = connection.signal_subscribe(
+            BLUEZ_BUS_NAME,
+            Some(PROPERTIES_INTERFACE),
+            Some("PropertiesChanged"),
+            Some(&characteristic),
+            Some(GATT_CHARACTERISTIC_INTERFACE),
+            DBusSignalFlags::NONE,
+            move |_, _, _, _, _, value| {
+                let Some((_, properties, _)) =
+                    value.get::<(String, HashMap<String, Variant>, Vec<String>)>()
+                else {
+                    return;
+                };
+                if let Some(value) = properties
+                    .get("Value")
+                    .and_then(|variant| variant.get::<Vec<u8>>())
+                {
+                    BluetoothService::capture(&recorder, kind, &device, &uuid, &value);
+                    callback(&value);
+                }
+            },
+        );
+
+        let mut notifying = self.notifying();
+        let entry = notifying
+            .entry(characteristic.clone())
+            .or_insert(subscribed);
+        if entry.subscribers == 0
+            && let Err(error) = connection.call_sync(
+                BLUEZ_BUS_NAME,
+                &characteristic,
+                GATT_CHARACTERISTIC_INTERFACE,
+                "StartNotify",
+                None,
+                None,
+                DBusCallFlags::NONE,
+                3000,
+                Cancellable::NONE,
+            )
+        {
+            log::error!("Could not start notifications for {characteristic}. {error}");
+            notifying.remove(&characteristic);
+            connection.signal_unsubscribe(sub_id);
+            return None;
+        }
+        entry.subscribers += 1;
+        Some(sub_id)
+    }
+
+    /// Ends a subscription made with [`BluetoothService::start_notifications`].
+    /// The characteristic is not looked up again, it is gone once the device
+    /// disconnected and its count has to go down all the same.
+    pub fn stop_notifications(&self, device: &str, uuid: &str, sub_id: SignalSubscriptionId) {
+        let Ok(connection) = &self.connection else {
             return;
         };
-        if let Ok(connection) = &self.connection {
-            connection.signal_subscribe(
+        connection.signal_unsubscribe(sub_id);
+        let mut notifying = self.notifying();
+        let Some(characteristic) = notifying
+            .iter_mut()
+            .find(|(_, notifying)| {
+                notifying.device == device && notifying.uuid.eq_ignore_ascii_case(uuid)
+            })
+            .and_then(|(characteristic, notifying)| {
+                notifying.subscribers = notifying.subscribers.saturating_sub(1);
+                (notifying.subscribers == 0).then(|| characteristic.clone())
+            })
+        else {
+            return;
+        };
+        notifying.remove(&characteristic);
+        // Fails when the device is gone, which stops the notifications too.
+        let _ = connection.call_sync(
+            BLUEZ_BUS_NAME,
+            &characteristic,
+            GATT_CHARACTERISTIC_INTERFACE,
+            "StopNotify",
+            None,
+            None,
+            DBusCallFlags::NONE,
+            3000,
+            Cancellable::NONE,
+        );
+    }
+
+    /// The counts stay consistent even if a panic poisoned the lock, they
+    /// are only changed together with the map.
+    fn notifying(&self) -> MutexGuard<'_, HashMap<String, Notifying>> {
+        self.notifying
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn has_characteristic(&self, device: &str, uuid: &str) -> bool {
+        self.connection
+            .as_ref()
+            .is_ok_and(|connection| self.find_characteristic(connection, device, uuid).is_some())
+    }
+
+    pub fn read_characteristic(
+        &self,
+        device: &str,
+        uuid: &str,
+    ) -> Result<Vec<u8>, gtk::glib::Error> {
+        let connection = self.connection.clone()?;
+        let (characteristic, _) = self.find_characteristic(&connection, device, uuid).ok_or(
+            DBusError::new_for_dbus_error(
+                "Missing characteristic",
+                "The characteristic could not be found on the device.",
+            ),
+        )?;
+        connection
+            .call_sync(
                 BLUEZ_BUS_NAME,
-                Some(PROPERTIES_INTERFACE),
-                Some("PropertiesChanged"),
-                self.adapters
-                    .get(self.adapter_index)
-                    .map(|object_path| object_path.as_str()),
-                Some(ADAPTER_INTERFACE),
-                DBusSignalFlags::NONE,
-                move |_, _, _, _, _, value| {
-                    let _ = value
-                        .get::<(String, HashMap<String, Variant>, Vec<String>)>()
-                        .map(|(_, map, _)| map)
-                        .filter(|map| map.contains_key("Powered"))
-                        .inspect(|map| {
-                            if let Some(value) = map.get("Powered").and_then(|p| p.get::<bool>()) {
-                                closure(value);
-                            }
-                        });
-                },
-            );
+                &characteristic,
+                GATT_CHARACTERISTIC_INTERFACE,
+                "ReadValue",
+                Some(&(HashMap::<String, Variant>::new(),).to_variant()),
+                Some(VariantTy::ANY),
+                DBusCallFlags::NONE,
+                3000,
+                Cancellable::NONE,
+            )?
+            .get::<(Vec<u8>,)>()
+            .map(|(value,)| value)
+            .ok_or(DBusError::new_for_dbus_error(
+                "Invalid value",
+                "The characteristic value could not be read.",
+            ))
+    }
+
+    pub fn write_characteristic(
+        &self,
+        device: &str,
+        uuid: &str,
+        value: &[u8],
+    ) -> Result<(), gtk::glib::Error> {
+        let connection = self.connection.clone()?;
+        let (characteristic, _) = self.find_characteristic(&connection, device, uuid).ok_or(
+            DBusError::new_for_dbus_error(
+                "Missing characteristic",
+                "The characteristic could not be found on the device.",
+            ),
+        )?;
+        BluetoothService::capture(&self.recorder, TrafficKind::Write, device, uuid, value);
+        connection.call_sync(
+            BLUEZ_BUS_NAME,
+            &characteristic,
+            GATT_CHARACTERISTIC_INTERFACE,
+            "WriteValue",
+            Some(&(value.to_vec(), HashMap::<String, Variant>::new()).to_variant()),
+            None,
+            DBusCallFlags::NONE,
+            3000,
+            Cancellable::NONE,
+        )?;
+        Ok(())
+    }
+
+    pub fn connect_device<F>(&self, device: &str, callback: F)
+    where
+        F: FnOnce(Result<(), gtk::glib::Error>) + 'static,
+    {
+        match &self.connection {
+            Ok(connection) => connection.call(
+                BLUEZ_BUS_NAME,
+                device,
+                DEVICE_INTERFACE,
+                "Connect",
+                None,
+                None,
+                DBusCallFlags::NONE,
+                30000,
+                Cancellable::NONE,
+                move |result| callback(result.map(|_| ())),
+            ),
+            Err(error) => callback(Err(error.clone())),
         }
     }
 
+    /// Looks for a way to control the device as a smart trainer.
+    pub fn detect_trainer(&self, device: &str) -> Option<Trainer> {
+        let connection = self.connection.as_ref().ok()?;
+        let device_prefix = format!("{device}/");
+        let characteristics = BluetoothService::managed_objects(connection)?
+            .into_iter()
+            .filter(|(object_path, _)| object_path.as_str().starts_with(&device_prefix))
+            .filter_map(|(_, mut interfaces)| {
+                interfaces
+                    .remove(GATT_CHARACTERISTIC_INTERFACE)?
+                    .get("UUID")?
+                    .get::<String>()
+            })
+            .collect::<Vec<String>>();
+        TrainerProtocol::from_characteristics(&characteristics).map(Trainer::new)
+    }
+
+    /// Takes control of a trainer so it accepts commands.
+    pub fn start_trainer_control(
+        &self,
+        device: &str,
+        trainer: &Trainer,
+    ) -> Result<(), gtk::glib::Error> {
+        let control_characteristic = trainer.protocol().control_characteristic();
+        trainer
+            .handshake()
+            .iter()
+            .try_for_each(|value| self.write_characteristic(device, control_characteristic, value))
+    }
+
+    pub fn send_trainer_command(
+        &self,
+        device: &str,
+        trainer: &mut Trainer,
+        command: &TrainerCommand,
+    ) -> Result<(), gtk::glib::Error> {
+        log::debug!(
+            "Sending {command:?} to {device} over {:?}",
+            trainer.protocol()
+        );
+        let control_characteristic = trainer.protocol().control_characteristic();
+        trainer
+            .encode(command)
+            .iter()
+            .try_for_each(|value| self.write_characteristic(device, control_characteristic, value))
+    }
+
     fn get_adapters(&self) -> Vec<ObjectPath> {
         if let Ok(connection) = &self.connection {
             connection
@@ -144,15 +592,15 @@ impl BluetoothService {
         }
     }
 
-    fn start_device_monitoring<F, G>(
-        &self,
-        connection: &DBusConnection,
-        add_device_callback: Rc<F>,
-        remove_device_callback: Rc<G>,
-    ) where
-        F: Fn(Device) + 'static,
-        G: Fn(ObjectPath) + 'static,
+    /// Reports devices appearing and disappearing on the bus, until the
+    /// returned subscription is dropped. Any number of listeners can
+    /// subscribe at once.
+    pub fn subscribe_device_events<F>(&self, callback: F) -> Option<Subscription>
+    where
+        F: Fn(DeviceEvent) + 'static,
     {
+        let connection = self.connection.as_ref().ok()?;
+        let callback = Rc::new(callback);
         let interface_added_sub_id = connection.signal_subscribe(
             BLUEZ_BUS_NAME,
             Some(OBJECT_MANAGER_INTERFACE),
@@ -160,37 +608,21 @@ impl BluetoothService {
             Some("/"),
             None,
             DBusSignalFlags::NONE,
-            move |_, _, _, _, _, value| {
-                let value = value
-                    .get::<(ObjectPath, HashMap<String, HashMap<String, Variant>>)>()
-                    .unwrap_or_else(|| {
-                        (
-                            ObjectPath::from_variant(&"/org/bluez".to_variant())
-                                .expect("Mock object path could not be created"),
-                            HashMap::new(),
-                        )
-                    });
-                if let Some(device_data) = value.1.get(DEVICE_INTERFACE) {
-                    if let Some(device) = BluetoothService::device_from_data(value.0, device_data) {
-                        //self.start_rssi_monitoring(device.object_path());
-                        add_device_callback(device);
+            clone!(
+                #[strong]
+                callback,
+                move |_, _, _, _, _, value| {
+                    if let Some((object_path, interfaces)) =
+                        value.get::<(ObjectPath, HashMap<String, HashMap<String, Variant>>)>()
+                        && let Some(device_data) = interfaces.get(DEVICE_INTERFACE)
+                        && let Some(device) =
+                            BluetoothService::device_from_data(object_path, device_data)
+                    {
+                        callback(DeviceEvent::Added(device));
                     }
                 }
-            },
+            ),
         );
-
-        match self.interface_added_sub_id.try_lock() {
-            Ok(mut value) => {
-                value.replace(interface_added_sub_id);
-            }
-            Err(error) => {
-                log::error!(
-                    "Could not acquire Interface Added lock. Unsubscribing from events. {error}",
-                );
-                connection.signal_unsubscribe(interface_added_sub_id);
-            }
-        };
-
         let interface_removed_sub_id = connection.signal_subscribe(
             BLUEZ_BUS_NAME,
             Some(OBJECT_MANAGER_INTERFACE),
@@ -199,69 +631,58 @@ impl BluetoothService {
             None,
             DBusSignalFlags::NONE,
             move |_, _, _, _, _, value| {
-                let (object_path, lost_interfaces) =
-                    value.get::<(ObjectPath, Vec<String>)>().unwrap_or_else(|| {
-                        (
-                            ObjectPath::from_variant(&"/org/bluez".to_variant())
-                                .expect("Mock object path could not created"),
-                            vec![],
-                        )
-                    });
-                if lost_interfaces.contains(&DEVICE_INTERFACE.to_string()) {
-                    remove_device_callback(object_path);
+                if let Some((object_path, lost_interfaces)) =
+                    value.get::<(ObjectPath, Vec<String>)>()
+                    && lost_interfaces
+                        .iter()
+                        .any(|interface| interface == DEVICE_INTERFACE)
+                {
+                    callback(DeviceEvent::Removed(object_path.to_string()));
                 }
             },
         );
-
-        match self.interface_removed_sub_id.try_lock() {
-            Ok(mut value) => {
-                value.replace(interface_removed_sub_id);
-            }
-            Err(error) => {
-                log::error!(
-                    "Could not acquire Interface Removed lock. Unsubscribing from events. {error}"
-                );
-                connection.signal_unsubscribe(interface_removed_sub_id);
-            }
-        }
+        Some(Subscription::new(
+            connection,
+            vec![interface_added_sub_id, interface_removed_sub_id],
+        ))
     }
 
-    pub fn start_rssi_monitoring<F>(
+    /// Follows the RSSI, connection state and advertising data of a device.
+    pub fn start_property_monitoring<F>(
         &self,
         device: String,
-        update_rssi_callback: F,
-    ) -> Option<SignalSubscriptionId>
+        update_callback: F,
+    ) -> Option<Subscription>
     where
-        F: Fn(i16) + 'static,
+        F: Fn(DeviceUpdate) + 'static,
     {
-        if let Ok(connection) = &self.connection {
-            Some(connection.signal_subscribe(
-                BLUEZ_BUS_NAME,
-                Some(PROPERTIES_INTERFACE),
-                Some("PropertiesChanged"),
-                Some(&device),
-                Some(DEVICE_INTERFACE),
-                DBusSignalFlags::NONE,
-                move |_, _, _, _, _, value| {
-                    let (_, properties, _) = value
-                        .get::<(String, HashMap<String, Variant>, Vec<String>)>()
-                        .unwrap();
-                    properties.get("RSSI").inspect(|rssi_variant| {
-                        rssi_variant.get::<i16>().inspect(|rssi| {
-                            update_rssi_callback(*rssi);
-                        });
-                    });
-                },
-            ))
-        } else {
-            None
-        }
-    }
-
-    pub fn stop_rssi_monitoring(&self, sub_id: SignalSubscriptionId) {
-        if let Ok(connection) = &self.connection {
-            connection.signal_unsubscribe(sub_id);
-        }
+        let connection = self.connection.as_ref().ok()?;
+        let sub_id = connection.signal_subscribe(
+            BLUEZ_BUS_NAME,
+            Some(PROPERTIES_INTERFACE),
+            Some("PropertiesChanged"),
+            Some(&device),
+            Some(DEVICE_INTERFACE),
+            DBusSignalFlags::NONE,
+            move |_, _, _, _, _, value| {
+                let (_, properties, _) = value
+                    .get::<(String, HashMap<String, Variant>, Vec<String>)>()
+                    .unwrap();
+                update_callback(DeviceUpdate {
+                    rssi: properties
+                        .get("RSSI")
+                        .and_then(|variant| variant.get::<i16>()),
+                    paired: properties
+                        .get("Paired")
+                        .and_then(|variant| variant.get::<bool>()),
+                    connected: properties
+                        .get("Connected")
+                        .and_then(|variant| variant.get::<bool>()),
+                    advertisement: Self::advertisement_from_data(&properties),
+                });
+            },
+        );
+        Some(Subscription::new(connection, vec![sub_id]))
     }
 
     fn find_known_devices<F>(&self, connection: &DBusConnection, callback: Rc<F>)
@@ -286,12 +707,11 @@ impl BluetoothService {
                 .into_iter()
                 .filter(|(_, v)| v.contains_key(DEVICE_INTERFACE))
                 .for_each(|(object_path, interfaces)| {
-                    if let Some(device_data) = interfaces.get(DEVICE_INTERFACE) {
-                        if let Some(device) =
+                    if let Some(device_data) = interfaces.get(DEVICE_INTERFACE)
+                        && let Some(device) =
                             BluetoothService::device_from_data(object_path, device_data)
-                        {
-                            callback(device);
-                        }
+                    {
+                        callback(device);
                     }
                 });
         }
@@ -308,7 +728,7 @@ impl BluetoothService {
                 let rssi = device_data
                     .get("RSSI")
                     .and_then(|variant| variant.get::<i16>())
-                    .unwrap_or(-200i16);
+                    .unwrap_or(UNKNOWN_RSSI);
                 let paired = device_data
                     .get("Paired")
                     .and_then(|variant| variant.get::<bool>())
@@ -317,31 +737,78 @@ impl BluetoothService {
                     .get("Connected")
                     .and_then(|variant| variant.get::<bool>())
                     .unwrap_or(false);
-                Device::new(
+                let device = Device::new(
                     name,
                     paired,
                     connected,
                     rssi.into(),
                     object_path.to_string(),
-                )
+                );
+                device.set_address(
+                    device_data
+                        .get("Address")
+                        .and_then(|variant| variant.get::<String>())
+                        .unwrap_or_default(),
+                );
+                device.set_address_type(
+                    device_data
+                        .get("AddressType")
+                        .and_then(|variant| variant.get::<String>())
+                        .unwrap_or_else(|| "public".to_string()),
+                );
+                device.update_advertisement(Self::advertisement_from_data(device_data));
+                device
             })
     }
 
-    pub fn start_scanning_for_devices<F, G>(
-        &self,
-        add_device_callback: Rc<F>,
-        remove_device_callback: Rc<G>,
-    ) where
-        F: Fn(Device) + 'static,
-        G: Fn(ObjectPath) + 'static,
-    {
+    /// Picks the advertising data out of `Device1` properties, leaving out
+    /// whatever is not there.
+    fn advertisement_from_data(device_data: &HashMap<String, Variant>) -> Advertisement {
+        let mut advertisement = Advertisement {
+            company_id: device_data
+                .get("ManufacturerData")
+                .and_then(|variant| variant.get::<HashMap<u16, Variant>>())
+                .and_then(|data| data.keys().min().copied()),
+            services: device_data
+                .get("UUIDs")
+                .and_then(|variant| variant.get::<Vec<String>>()),
+            tx_power: device_data
+                .get("TxPower")
+                .and_then(|variant| variant.get::<i16>()),
+            fitness_machine: None,
+        };
+        if let Some(service_data) = device_data
+            .get("ServiceData")
+            .and_then(|variant| variant.get::<HashMap<String, Variant>>())
+        {
+            for (uuid, data) in service_data {
+                if let Some(data) = data.get::<Vec<u8>>() {
+                    advertisement.read_service_data(&uuid, &data);
+                }
+            }
+        }
+        advertisement
+    }
+
+    /// The devices BlueZ knows about, kept current for as long as the app
+    /// runs. Only to be used from the main thread.
+    pub fn devices(&self) -> ListModel {
+        if let Ok(connection) = &self.connection
+            && !device_list::is_tracking()
+        {
+            device_list::track(self.subscribe_device_events(|event| match event {
+                DeviceEvent::Added(device) => device_list::add(device),
+                DeviceEvent::Removed(object_path) => device_list::remove(&object_path),
+            }));
+            self.find_known_devices(connection, Rc::new(device_list::add));
+        }
+        device_list::model().upcast()
+    }
+
+    pub fn start_scanning_for_devices(&self) {
+        // Discovered devices show up through the tracked list.
+        self.devices();
         if let Ok(connection) = &self.connection {
-            self.start_device_monitoring(
-                connection,
-                add_device_callback.clone(),
-                remove_device_callback,
-            );
-            self.find_known_devices(connection, add_device_callback);
             let _ = connection.call_sync(
                 BLUEZ_BUS_NAME,
                 self.adapters
@@ -360,7 +827,6 @@ impl BluetoothService {
 
     pub fn stop_scanning_for_devices(&self) -> Result<(), ()> {
         if let Ok(connection) = &self.connection {
-            self.unregister_interface_subscriptions()?;
             connection
                 .call_sync(
                     BLUEZ_BUS_NAME,
@@ -379,29 +845,6 @@ impl BluetoothService {
         }
         Ok(())
     }
-
-    fn unregister_interface_subscriptions(&self) -> Result<(), ()> {
-        if let Ok(connection) = &self.connection {
-            if let Some(id) = self
-                .interface_added_sub_id
-                .try_lock()
-                .map_err(|_| log::error!("Could not acquire lock for interface added sub is"))?
-                .take()
-            {
-                connection.signal_unsubscribe(id);
-            }
-
-            if let Some(id) = self
-                .interface_removed_sub_id
-                .try_lock()
-                .map_err(|_| log::error!("Could not acquire lock for interface removed sub id"))?
-                .take()
-            {
-                connection.signal_unsubscribe(id);
-            }
-        }
-        Ok(())
-    }
 }
 
 impl Default for BluetoothService {
@@ -0,0 +1,32 @@
+use gtk::gio::{DBusConnection, SignalSubscriptionId};
+
+/// Keeps D-Bus signal subscriptions alive for as long as it is held, and
+/// unsubscribes from them when dropped.
+#[must_use = "the subscription ends as soon as it is dropped"]
+pub struct Subscription {
+    connection: DBusConnection,
+    ids: Vec<SignalSubscriptionId>,
+}
+
+impl Subscription {
+    pub(super) fn new(connection: &DBusConnection, ids: Vec<SignalSubscriptionId>) -> Self {
+        Self {
+            connection: connection.clone(),
+            ids,
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        for id in self.ids.drain(..) {
+            self.connection.signal_unsubscribe(id);
+        }
+    }
+}
+
+impl std::fmt::Debug for Subscription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Subscription({} signals)", self.ids.len())
+    }
+}
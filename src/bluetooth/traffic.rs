@@ -0,0 +1,390 @@
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use gtk::glib::{self, ControlFlow, SourceId};
+
+const TRACE_HEADER: &str = "# bike trace";
+const REPLAY_TICK: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrafficKind {
+    Notification,
+    Indication,
+    Write,
+}
+
+impl TrafficKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TrafficKind::Notification => "notify",
+            TrafficKind::Indication => "indicate",
+            TrafficKind::Write => "write",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "notify" => Some(TrafficKind::Notification),
+            "indicate" => Some(TrafficKind::Indication),
+            "write" => Some(TrafficKind::Write),
+            _ => None,
+        }
+    }
+}
+
+/// One raw characteristic value exchanged with a device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrafficEvent {
+    /// Time elapsed since the capture started.
+    pub offset: Duration,
+    pub kind: TrafficKind,
+    /// Object path of the device the characteristic belongs to.
+    pub device: String,
+    /// Characteristic UUID, in BlueZ's 128-bit form.
+    pub uuid: String,
+    pub value: Vec<u8>,
+}
+
+impl TrafficEvent {
+    /// Traces are line based: `<micros> <kind> <device> <uuid> <hex value>`.
+    fn to_line(&self) -> String {
+        let mut line = format!(
+            "{} {} {} {} ",
+            self.offset.as_micros(),
+            self.kind.as_str(),
+            self.device,
+            self.uuid
+        );
+        self.value.iter().for_each(|byte| {
+            let _ = write!(line, "{byte:02x}");
+        });
+        line
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut parts = line.split_whitespace();
+        let offset = Duration::from_micros(parts.next()?.parse().ok()?);
+        let kind = TrafficKind::parse(parts.next()?)?;
+        let device = parts.next()?.to_string();
+        let uuid = parts.next()?.to_string();
+        let hex = parts.next().unwrap_or_default();
+        if !hex.len().is_multiple_of(2) {
+            return None;
+        }
+        let value = (0..hex.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        Some(Self {
+            offset,
+            kind,
+            device,
+            uuid,
+            value,
+        })
+    }
+}
+
+/// Writes every characteristic value seen by the bluetooth service to a
+/// timestamped trace file.
+pub struct TrafficRecorder {
+    writer: BufWriter<File>,
+    path: PathBuf,
+    started: Instant,
+}
+
+impl TrafficRecorder {
+    pub fn create(directory: &Path) -> std::io::Result<Self> {
+        let now = glib::DateTime::now_local().map_err(std::io::Error::other)?;
+        let name = now
+            .format("bike-%Y%m%d-%H%M%S.trace")
+            .map_err(std::io::Error::other)?;
+        let started_at = now.format_iso8601().map_err(std::io::Error::other)?;
+
+        std::fs::create_dir_all(directory)?;
+        let path = directory.join(name.as_str());
+        let mut writer = BufWriter::new(File::create(&path)?);
+        writeln!(writer, "{TRACE_HEADER} started {started_at}")?;
+        Ok(Self {
+            writer,
+            path,
+            started: Instant::now(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record(
+        &mut self,
+        kind: TrafficKind,
+        device: &str,
+        uuid: &str,
+        value: &[u8],
+    ) -> std::io::Result<()> {
+        let event = TrafficEvent {
+            offset: self.started.elapsed(),
+            kind,
+            device: device.to_string(),
+            uuid: uuid.to_string(),
+            value: value.to_vec(),
+        };
+        writeln!(self.writer, "{}", event.to_line())?;
+        // Flush right away, a trace is most useful when the app misbehaves.
+        self.writer.flush()
+    }
+}
+
+pub fn load_trace(path: &Path) -> std::io::Result<Vec<TrafficEvent>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut events = vec![];
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match TrafficEvent::from_line(&line) {
+            Some(event) => events.push(event),
+            None => log::warn!("Skipping malformed trace line {}: {line}", index + 1),
+        }
+    }
+    Ok(events)
+}
+
+/// Hands the events of a trace to the callback, keeping the original timing
+/// scaled by `speed` (2.0 replays twice as fast).
+///
+/// Returns the source driving the replay so it can be removed early.
+pub fn replay<F>(events: Vec<TrafficEvent>, speed: f64, callback: F) -> SourceId
+where
+    F: Fn(&TrafficEvent) + 'static,
+{
+    let speed = if speed.is_finite() && speed > 0.0 {
+        speed
+    } else {
+        1.0
+    };
+    let mut events = events.into_iter().peekable();
+    let started = Instant::now();
+
+    glib::timeout_add_local(REPLAY_TICK, move || {
+        let position = started.elapsed().mul_f64(speed);
+        while let Some(event) = events.next_if(|event| event.offset <= position) {
+            callback(&event);
+        }
+        if events.peek().is_some() {
+            ControlFlow::Continue
+        } else {
+            log::debug!("Trace replay finished");
+            ControlFlow::Break
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+    use super::*;
+    use crate::protocol::{Decoder, Measurement};
+
+    const TRAINER: &str = "/org/bluez/hci0/dev_F0_5E_11_0B_1C_01";
+    const STRAP: &str = "/org/bluez/hci0/dev_C4_11_9A_2F_60_02";
+    const FEC_TRAINER: &str = "/org/bluez/hci0/dev_E8_2A_44_90_1D_03";
+
+    fn fixture() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("data/traces/ride.trace")
+    }
+
+    #[test]
+    fn line_round_trip() {
+        let event = TrafficEvent {
+            offset: Duration::from_micros(1_250_000),
+            kind: TrafficKind::Indication,
+            device: TRAINER.to_string(),
+            uuid: crate::protocol::FITNESS_MACHINE_CONTROL_POINT.to_string(),
+            value: vec![0x80, 0x05, 0x01],
+        };
+        let line = event.to_line();
+        assert_eq!(
+            line,
+            format!("1250000 indicate {TRAINER} 00002ad9-0000-1000-8000-00805f9b34fb 800501")
+        );
+        assert_eq!(TrafficEvent::from_line(&line), Some(event));
+        assert_eq!(TrafficEvent::from_line("12 notify /dev uuid 0"), None);
+        assert_eq!(TrafficEvent::from_line("12 shout /dev uuid 00"), None);
+    }
+
+    #[test]
+    fn replay_fixture_through_decoders() {
+        let events = load_trace(&fixture()).unwrap();
+        assert_eq!(events.len(), 10);
+        let count = events.len();
+
+        let context = glib::MainContext::default();
+        let _guard = context.acquire().unwrap();
+        let seen = Rc::new(RefCell::new(0));
+        let decoded = Rc::new(RefCell::new(vec![]));
+        let decoders = RefCell::new(HashMap::<String, Decoder>::new());
+        replay(events, 100.0, {
+            let seen = seen.clone();
+            let decoded = decoded.clone();
+            move |event| {
+                *seen.borrow_mut() += 1;
+                // Writes are only part of the trace for context.
+                if event.kind == TrafficKind::Write {
+                    return;
+                }
+                let mut decoders = decoders.borrow_mut();
+                let decoder = decoders.entry(event.device.clone()).or_default();
+                if let Some(measurement) = decoder.decode(&event.uuid, &event.value) {
+                    decoded
+                        .borrow_mut()
+                        .push((event.device.clone(), measurement));
+                }
+            }
+        });
+        while *seen.borrow() < count {
+            context.iteration(true);
+        }
+
+        let expected = [
+            (
+                TRAINER,
+                Measurement {
+                    power: Some(200),
+                    cadence: Some(90.0),
+                    speed: Some(30.0),
+                    ..Default::default()
+                },
+            ),
+            (
+                TRAINER,
+                Measurement {
+                    power: Some(250),
+                    ..Default::default()
+                },
+            ),
+            (
+                STRAP,
+                Measurement {
+                    heart_rate: Some(72),
+                    ..Default::default()
+                },
+            ),
+            (
+                FEC_TRAINER,
+                Measurement {
+                    speed: Some(36.0),
+                    distance: Some(0),
+                    heart_rate: Some(140),
+                    ..Default::default()
+                },
+            ),
+            (
+                TRAINER,
+                Measurement {
+                    power: Some(250),
+                    cadence: Some(60.0 * 1024.0 / 683.0),
+                    ..Default::default()
+                },
+            ),
+            (
+                TRAINER,
+                Measurement {
+                    power: Some(205),
+                    cadence: Some(91.0),
+                    speed: Some(30.12),
+                    ..Default::default()
+                },
+            ),
+            (
+                STRAP,
+                Measurement {
+                    heart_rate: Some(150),
+                    ..Default::default()
+                },
+            ),
+            (
+                FEC_TRAINER,
+                Measurement {
+                    speed: Some(36.0),
+                    distance: Some(8),
+                    heart_rate: Some(141),
+                    ..Default::default()
+                },
+            ),
+            (
+                FEC_TRAINER,
+                Measurement {
+                    power: Some(230),
+                    cadence: Some(85.0),
+                    ..Default::default()
+                },
+            ),
+        ];
+        let decoded = decoded.borrow();
+        assert_eq!(decoded.len(), expected.len());
+        for ((device, measurement), (expected_device, expected)) in decoded.iter().zip(expected) {
+            assert_eq!(device, expected_device);
+            assert_eq!(*measurement, expected, "decoding a value from {device}");
+        }
+    }
+
+    #[test]
+    fn capture_round_trip() {
+        let directory = std::env::temp_dir().join(format!("bike-trace-{}", std::process::id()));
+        let mut recorder = TrafficRecorder::create(&directory).unwrap();
+        recorder
+            .record(
+                TrafficKind::Notification,
+                STRAP,
+                crate::protocol::HEART_RATE_MEASUREMENT,
+                &[0x00, 0x48],
+            )
+            .unwrap();
+        recorder
+            .record(
+                TrafficKind::Write,
+                TRAINER,
+                crate::protocol::FITNESS_MACHINE_CONTROL_POINT,
+                &[0x05, 0xc8, 0x00],
+            )
+            .unwrap();
+        recorder
+            .record(
+                TrafficKind::Indication,
+                TRAINER,
+                crate::protocol::FITNESS_MACHINE_CONTROL_POINT,
+                &[],
+            )
+            .unwrap();
+
+        let path = recorder.path().to_path_buf();
+        drop(recorder);
+        let header = std::fs::read_to_string(&path).unwrap();
+        assert!(header.starts_with(TRACE_HEADER));
+
+        let events = load_trace(&path).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        let summary = events
+            .iter()
+            .map(|event| (event.kind, event.device.as_str(), event.value.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                (TrafficKind::Notification, STRAP, vec![0x00, 0x48]),
+                (TrafficKind::Write, TRAINER, vec![0x05, 0xc8, 0x00]),
+                (TrafficKind::Indication, TRAINER, vec![]),
+            ]
+        );
+        assert!(events.is_sorted_by_key(|event| event.offset));
+    }
+}
@@ -5,7 +5,7 @@ mod imp {
         prelude::GtkWindowExt,
     };
 
-    use crate::components::window::Window;
+    use crate::{BLUETOOTH, components::window::Window};
 
     #[derive(Default)]
     pub struct AppPrivate;
@@ -23,8 +23,24 @@ mod imp {
         fn activate(&self) {
             let window = Window::new(&self.obj().clone().upcast::<adw::Application>());
             window.present();
+            start_replay_from_env();
         }
     }
+    /// `BIKE_REPLAY` points at a trace recorded with `BIKE_CAPTURE_DIR`, and
+    /// the optional `BIKE_REPLAY_SPEED` speeds it up.
+    fn start_replay_from_env() {
+        let Some(trace) = std::env::var_os("BIKE_REPLAY") else {
+            return;
+        };
+        let speed = std::env::var("BIKE_REPLAY_SPEED")
+            .ok()
+            .and_then(|speed| speed.parse::<f64>().ok())
+            .unwrap_or(1.0);
+        if let Err(error) = BLUETOOTH.start_replay(trace.as_ref(), speed) {
+            log::error!("Could not replay bluetooth trace. {error}");
+        }
+    }
+
     impl GtkApplicationImpl for AppPrivate {}
     impl AdwApplicationImpl for AppPrivate {}
 }
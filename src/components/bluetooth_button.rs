@@ -2,20 +2,25 @@ use gtk::glib::Object;
 use imp::State;
 
 mod imp {
-    use std::cell::Cell;
+    use std::cell::{Cell, RefCell};
 
     use adw::prelude::ObjectExt;
     use adw::subclass::prelude::*;
     use adw::{glib::subclass::InitializingObject, prelude::AdwDialogExt};
-    use gtk::glib::types::StaticType;
+    use gtk::gio::prelude::ListModelExt;
+    use gtk::glib::{object::CastNone, types::StaticType};
     use gtk::prelude::WidgetExt;
     use gtk::{
         CompositeTemplate,
-        glib::{self, Properties, clone},
+        glib::{self, Properties, SignalHandlerId, clone},
         subclass::widget::WidgetImpl,
     };
 
-    use crate::{BLUETOOTH, components::connect_dialog::ConnectDialog};
+    use crate::{
+        BLUETOOTH,
+        bluetooth::{Device, Subscription},
+        components::connect_dialog::ConnectDialog,
+    };
 
     use super::BluetoothButton;
 
@@ -25,6 +30,9 @@ mod imp {
     pub struct BluetoothButtonPrivate {
         #[property(name="state", get, set, type = State, builder(State::default()))]
         state: Cell<State>,
+        adapter_subscription: RefCell<Option<Subscription>>,
+        devices_handler: RefCell<Option<SignalHandlerId>>,
+        device_handlers: RefCell<Vec<(Device, SignalHandlerId)>>,
     }
 
     #[glib::object_subclass]
@@ -54,8 +62,11 @@ mod imp {
                         .present(slf.ancestor(adw::ApplicationWindow::static_type()).as_ref());
                 }
                 State::Connected => {
+                    let Some(device) = slf.imp().connected_device() else {
+                        return;
+                    };
                     let connect_dialog = ConnectDialog::new();
-                    connect_dialog.skip_to_device_details_page();
+                    connect_dialog.skip_to_device_details_page(&device);
                     connect_dialog
                         .present(slf.ancestor(adw::ApplicationWindow::static_type()).as_ref());
                 }
@@ -115,7 +126,7 @@ mod imp {
                     self.obj().set_state(State::Disabled);
                 }
             }
-            BLUETOOTH.start_adapter_monitoring(clone!(
+            let adapter_subscription = BLUETOOTH.start_adapter_monitoring(clone!(
                 #[strong(rename_to = obj)]
                 self.obj(),
                 move |value| {
@@ -123,8 +134,79 @@ mod imp {
                         true => obj.set_state(State::Disconnected),
                         false => obj.set_state(State::PoweredOff),
                     }
+                    obj.imp().refresh_connection_state();
+                }
+            ));
+            self.adapter_subscription.replace(adapter_subscription);
+
+            let devices = BLUETOOTH.devices();
+            let handler = devices.connect_items_changed(clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |devices, position, _, added| {
+                    (position..position + added)
+                        .filter_map(|position| devices.item(position).and_downcast::<Device>())
+                        .for_each(|device| imp.watch(&device));
+                    imp.refresh_connection_state();
                 }
             ));
+            self.devices_handler.replace(Some(handler));
+            (0..devices.n_items())
+                .filter_map(|position| devices.item(position).and_downcast::<Device>())
+                .for_each(|device| self.watch(&device));
+            self.refresh_connection_state();
+        }
+
+        fn dispose(&self) {
+            if let Some(handler) = self.devices_handler.take() {
+                BLUETOOTH.devices().disconnect(handler);
+            }
+            for (device, handler) in self.device_handlers.take() {
+                device.disconnect(handler);
+            }
+        }
+    }
+
+    impl BluetoothButtonPrivate {
+        pub fn connected_device(&self) -> Option<Device> {
+            let devices = BLUETOOTH.devices();
+            (0..devices.n_items())
+                .filter_map(|position| devices.item(position).and_downcast::<Device>())
+                .find(|device| device.connected())
+        }
+
+        /// Follows the connection of a device. Evicted devices come back to the
+        /// list as the same object, so each one is only watched once.
+        fn watch(&self, device: &Device) {
+            if self
+                .device_handlers
+                .borrow()
+                .iter()
+                .any(|(watched, _)| watched == device)
+            {
+                return;
+            }
+            let handler = device.connect_connected_notify(clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |_| imp.refresh_connection_state()
+            ));
+            self.device_handlers
+                .borrow_mut()
+                .push((device.clone(), handler));
+        }
+
+        /// Shows whether any device is connected, once the adapter is on.
+        fn refresh_connection_state(&self) {
+            let state = self.obj().state();
+            if state != State::Disconnected && state != State::Connected {
+                return;
+            }
+            self.obj().set_state(if self.connected_device().is_some() {
+                State::Connected
+            } else {
+                State::Disconnected
+            });
         }
     }
     impl WidgetImpl for BluetoothButtonPrivate {}
@@ -1,33 +1,58 @@
 use adw::subclass::prelude::ObjectSubclassIsExt;
 use gtk::glib::Object;
 
-mod imp {
+use crate::bluetooth::Device;
 
-    use std::rc::Rc;
+mod imp {
 
-    use crate::components::device_listing::DeviceListing;
-    use crate::{BLUETOOTH, bluetooth::Device};
+    use crate::components::{DeviceDetailsPage, SpinDownPage, device_listing::DeviceListing};
+    use crate::{
+        BLUETOOTH,
+        bluetooth::{Device, ScanController, ScanState},
+    };
     use adw::glib::subclass::InitializingObject;
+    use std::cell::RefCell;
+
+    use adw::prelude::PreferencesGroupExt;
     use adw::subclass::prelude::*;
-    use gtk::glib::clone;
-    use gtk::glib::variant::ObjectPath;
     use gtk::{
         CompositeTemplate,
-        gio::ListStore,
-        glib::{self, object::Cast},
+        gio::prelude::ListModelExt,
+        glib::{
+            self, SignalHandlerId, clone,
+            object::{Cast, CastNone, ObjectExt},
+            types::StaticType,
+        },
+        prelude::{SorterExt, WidgetExt},
         subclass::widget::WidgetImpl,
     };
 
     use super::ConnectDialog;
 
-    #[derive(CompositeTemplate)]
+    #[derive(CompositeTemplate, Default)]
     #[template(resource = "/io/github/andreibachim/bike/ui/connect_dialog.ui")]
     pub struct ConnectDialogPrivate {
         #[template_child]
         pub navigation_view: TemplateChild<adw::NavigationView>,
         #[template_child]
+        find_group: TemplateChild<adw::PreferencesGroup>,
+        #[template_child]
+        scan_spinner: TemplateChild<adw::Spinner>,
+        #[template_child]
+        rescan_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        search_entry: TemplateChild<gtk::SearchEntry>,
+        #[template_child]
         device_list: TemplateChild<gtk::ListBox>,
-        available_devices: ListStore,
+        #[template_child]
+        placeholder_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub device_details_page: TemplateChild<DeviceDetailsPage>,
+        #[template_child]
+        pub spin_down_page: TemplateChild<SpinDownPage>,
+        scan_controller: ScanController,
+        devices_handler: RefCell<Option<SignalHandlerId>>,
+        device_handlers: RefCell<Vec<(Device, SignalHandlerId)>>,
     }
 
     #[glib::object_subclass]
@@ -49,67 +74,114 @@ mod imp {
         #[template_callback]
         fn showing_find_page(slf: ConnectDialog) {
             log::debug!("Starting scan for new devices");
-            let add_device_callback = Rc::new(clone!(
-                #[weak(rename_to = imp)]
-                slf.imp(),
-                move |device| imp.add_new_device(device)
-            ));
-            let remove_device_callback = Rc::new(clone!(
-                #[weak(rename_to = imp)]
-                slf.imp(),
-                move |object_path| imp.remove_device(object_path)
-            ));
-            BLUETOOTH.start_scanning_for_devices(add_device_callback, remove_device_callback);
+            slf.imp().scan_controller.start();
         }
 
         #[template_callback]
-        fn hiding_find_page() {
+        fn hiding_find_page(slf: ConnectDialog) {
             log::debug!("Stopping scan for new devices");
-            if BLUETOOTH.stop_scanning_for_devices().is_err() {
-                todo!("Implement logic for error cases here")
-            }
+            slf.imp().scan_controller.stop();
         }
 
-        fn add_new_device(&self, device: Device) {
-            device.register_property_listener();
-            self.available_devices.append(&device);
+        #[template_callback]
+        fn rescan(slf: ConnectDialog) {
+            slf.imp().scan_controller.start();
         }
+    }
 
-        fn remove_device(&self, object_path: ObjectPath) {
-            log::debug!("Should remove device: {:#?}", object_path);
-            self.available_devices.retain(|device| {
-                if let Some(device) = device.downcast_ref::<Device>() {
-                    if device
-                        .object_path()
-                        .eq_ignore_ascii_case(object_path.as_str())
-                    {
-                        device.unregister_property_listener();
-                        false
-                    } else {
-                        true
-                    }
-                } else {
-                    true
+    impl ConnectDialogPrivate {
+        /// The devices matching the search, in the order they are listed.
+        fn listed_devices(&self) -> gtk::SortListModel {
+            let devices = BLUETOOTH.devices();
+            let name_filter = gtk::StringFilter::new(Some(gtk::PropertyExpression::new(
+                Device::static_type(),
+                gtk::Expression::NONE,
+                "name",
+            )));
+            name_filter.set_match_mode(gtk::StringFilterMatchMode::Substring);
+            name_filter.set_ignore_case(true);
+            self.search_entry
+                .bind_property("text", &name_filter, "search")
+                .build();
+            let sorter = gtk::CustomSorter::new(|first, second| {
+                match (
+                    first.downcast_ref::<Device>(),
+                    second.downcast_ref::<Device>(),
+                ) {
+                    (Some(first), Some(second)) => first.list_order(second).into(),
+                    _ => gtk::Ordering::Equal,
                 }
             });
+
+            (0..devices.n_items())
+                .filter_map(|position| devices.item(position).and_downcast::<Device>())
+                .for_each(|device| self.watch(&device, &sorter));
+            let handler = devices.connect_items_changed(clone!(
+                #[weak(rename_to = imp)]
+                self,
+                #[weak]
+                sorter,
+                move |devices, position, _, added| {
+                    (position..position + added)
+                        .filter_map(|position| devices.item(position).and_downcast::<Device>())
+                        .for_each(|device| imp.watch(&device, &sorter));
+                }
+            ));
+            self.devices_handler.replace(Some(handler));
+
+            let filtered = gtk::FilterListModel::new(Some(devices), Some(name_filter));
+            gtk::SortListModel::new(Some(filtered), Some(sorter))
         }
-    }
 
-    impl Default for ConnectDialogPrivate {
-        fn default() -> Self {
-            Self {
-                available_devices: ListStore::new::<Device>(),
-                device_list: Default::default(),
-                navigation_view: Default::default(),
+        /// Sort models don't notice their items changing, so re-sort when
+        /// anything the order depends on does. The devices outlive the
+        /// dialog, the handlers are disconnected when it goes away.
+        fn watch(&self, device: &Device, sorter: &gtk::CustomSorter) {
+            for property in ["connected", "paired", "stable-rssi", "services"] {
+                let handler = device.connect_notify_local(
+                    Some(property),
+                    clone!(
+                        #[weak]
+                        sorter,
+                        move |_, _| sorter.changed(gtk::SorterChange::Different)
+                    ),
+                );
+                self.device_handlers
+                    .borrow_mut()
+                    .push((device.clone(), handler));
             }
         }
+
+        fn refresh_scan_status(&self) {
+            let state = self.scan_controller.state();
+            let scanning = state == ScanState::Scanning;
+            self.scan_spinner.set_visible(scanning);
+            self.rescan_button.set_visible(state == ScanState::Finished);
+            self.find_group.set_description(Some(match state {
+                ScanState::Idle | ScanState::Scanning => "Looking for nearby smart trainers",
+                ScanState::Finished => "Scan finished",
+            }));
+            self.placeholder_label.set_label(if scanning {
+                "Searching…"
+            } else {
+                "No devices found. Wake the sensors up by pedalling or putting on the \
+                 heart rate strap, make sure they are not connected to another app, then \
+                 scan again."
+            });
+        }
     }
 
     impl ObjectImpl for ConnectDialogPrivate {
         fn constructed(&self) {
             self.parent_constructed();
+            self.scan_controller.connect_state_notify(clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |_| imp.refresh_scan_status()
+            ));
+            self.refresh_scan_status();
             self.device_list
-                .bind_model(Some(&self.available_devices), |device| {
+                .bind_model(Some(&self.listed_devices()), |device| {
                     match device.downcast_ref::<Device>() {
                         Some(device) => {
                             let device_listing = DeviceListing::new(device);
@@ -119,6 +191,15 @@ mod imp {
                     }
                 });
         }
+
+        fn dispose(&self) {
+            if let Some(handler) = self.devices_handler.take() {
+                BLUETOOTH.devices().disconnect(handler);
+            }
+            for (device, handler) in self.device_handlers.take() {
+                device.disconnect(handler);
+            }
+        }
     }
     impl WidgetImpl for ConnectDialogPrivate {}
     impl AdwDialogImpl for ConnectDialogPrivate {}
@@ -141,9 +222,19 @@ impl ConnectDialog {
             .push_by_tag("device-details-page");
     }
 
-    pub fn skip_to_device_details_page(&self) {
-        self.imp().navigation_view.set_animate_transitions(false);
+    pub fn show_device(&self, device: &Device) {
+        self.imp().device_details_page.set_device(Some(device));
         self.load_details();
+    }
+
+    pub fn show_spin_down(&self, device: &Device) {
+        self.imp().spin_down_page.set_device(Some(device));
+        self.imp().navigation_view.push_by_tag("spin-down-page");
+    }
+
+    pub fn skip_to_device_details_page(&self, device: &Device) {
+        self.imp().navigation_view.set_animate_transitions(false);
+        self.show_device(device);
         self.imp().navigation_view.set_animate_transitions(true);
     }
 }
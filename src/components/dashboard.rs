@@ -0,0 +1,369 @@
+mod imp {
+    use std::cell::RefCell;
+
+    use adw::subclass::prelude::*;
+    use gtk::{
+        CompositeTemplate,
+        glib::{self, Properties, subclass::InitializingObject},
+        prelude::ObjectExt,
+        subclass::widget::WidgetImpl,
+    };
+
+    use crate::{components::RideChart, session::Ride};
+
+    #[derive(Debug, Default, CompositeTemplate, Properties)]
+    #[template(resource = "/io/github/andreibachim/bike/ui/dashboard.ui")]
+    #[properties(wrapper_type = super::Dashboard)]
+    pub struct DashboardPrivate {
+        #[property(name = "ride", get, set = Self::set_ride)]
+        ride: RefCell<Option<Ride>>,
+
+        #[template_child]
+        pub state_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub power_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub power_stale_icon: TemplateChild<gtk::Image>,
+        #[template_child]
+        pub power_zone_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub cadence_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub cadence_stale_icon: TemplateChild<gtk::Image>,
+        #[template_child]
+        pub heart_rate_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub heart_rate_stale_icon: TemplateChild<gtk::Image>,
+        #[template_child]
+        pub heart_rate_zone_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub speed_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub speed_stale_icon: TemplateChild<gtk::Image>,
+        #[template_child]
+        pub elapsed_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub distance_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub lap_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub target_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub rolling_power_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub average_power_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub normalized_power_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub training_load_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub chart: TemplateChild<RideChart>,
+        #[template_child]
+        pub start_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub pause_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub lap_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub finish_button: TemplateChild<gtk::Button>,
+    }
+
+    impl DashboardPrivate {
+        fn set_ride(&self, ride: Option<Ride>) {
+            self.ride.replace(ride);
+            self.obj().bind_ride();
+        }
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for DashboardPrivate {
+        const NAME: &'static str = "Dashboard";
+        type Type = super::Dashboard;
+        type ParentType = adw::Bin;
+        fn class_init(klass: &mut Self::Class) {
+            klass.bind_template();
+        }
+        fn instance_init(obj: &InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for DashboardPrivate {}
+    impl WidgetImpl for DashboardPrivate {}
+    impl BinImpl for DashboardPrivate {}
+}
+
+use adw::subclass::prelude::ObjectSubclassIsExt;
+use gtk::{
+    ClosureExpression,
+    glib::{self, clone, closure},
+    prelude::GObjectPropertyExpressionExt,
+};
+
+use crate::{
+    components::{PowerBand, zone_color},
+    session::{MetricState, Ride, RideState, Zones},
+};
+
+glib::wrapper! {
+    /// The live view of the ride, with the controls to run it.
+    pub struct Dashboard(ObjectSubclass<imp::DashboardPrivate>)
+        @extends adw::Bin, gtk::Widget,
+        @implements gtk::Accessible, gtk::Buildable, gtk::ConstraintTarget;
+}
+
+impl Dashboard {
+    fn bind_ride(&self) {
+        let Some(ride) = self.ride() else {
+            return;
+        };
+        let imp = self.imp();
+
+        self.refresh_power_bands(&ride);
+        ride.connect_zones_changed(clone!(
+            #[weak(rename_to = slf)]
+            self,
+            move |ride| slf.refresh_power_bands(ride)
+        ));
+        ride.connect_elapsed_notify(clone!(
+            #[weak(rename_to = chart)]
+            imp.chart,
+            move |ride| {
+                let records = ride.records();
+                let span = chart.span() as usize;
+                chart.set_records(&records[records.len().saturating_sub(span + 1)..]);
+            }
+        ));
+
+        self.bind_metric(
+            &ride,
+            "power",
+            &imp.power_label,
+            &imp.power_stale_icon,
+            |power| format!("{power:.0} W"),
+        );
+        self.bind_metric(
+            &ride,
+            "cadence",
+            &imp.cadence_label,
+            &imp.cadence_stale_icon,
+            |cadence| format!("{cadence:.0} rpm"),
+        );
+        self.bind_metric(
+            &ride,
+            "heart-rate",
+            &imp.heart_rate_label,
+            &imp.heart_rate_stale_icon,
+            |heart_rate| format!("{heart_rate:.0} bpm"),
+        );
+        self.bind_metric(
+            &ride,
+            "speed",
+            &imp.speed_label,
+            &imp.speed_stale_icon,
+            |speed| format!("{speed:.1} km/h"),
+        );
+
+        ClosureExpression::new::<String>(
+            [&ride.property_expression("power-zone")],
+            closure!(|ride: Ride, zone: u32| zone_markup(&ride.power_zones(), zone)),
+        )
+        .bind(&imp.power_zone_label.get(), "label", Some(&ride));
+        ClosureExpression::new::<String>(
+            [&ride.property_expression("heart-rate-zone")],
+            closure!(|ride: Ride, zone: u32| zone_markup(&ride.heart_rate_zones(), zone)),
+        )
+        .bind(&imp.heart_rate_zone_label.get(), "label", Some(&ride));
+
+        ClosureExpression::new::<String>(
+            [&ride.property_expression("elapsed")],
+            closure!(|_: Ride, elapsed: f64| format_duration(elapsed)),
+        )
+        .bind(&imp.elapsed_label.get(), "label", Some(&ride));
+        ClosureExpression::new::<String>(
+            [&ride.property_expression("distance")],
+            closure!(|_: Ride, distance: f64| format!("{:.2} km", distance / 1000.0)),
+        )
+        .bind(&imp.distance_label.get(), "label", Some(&ride));
+        ClosureExpression::new::<String>(
+            [
+                &ride.property_expression("lap"),
+                &ride.property_expression("lap-time"),
+            ],
+            closure!(|_: Ride, lap: u32, lap_time: f64| match lap {
+                0 => "—".to_string(),
+                lap => format!("{lap} · {}", format_duration(lap_time)),
+            }),
+        )
+        .bind(&imp.lap_label.get(), "label", Some(&ride));
+        ClosureExpression::new::<String>(
+            [&ride.property_expression("target")],
+            closure!(|_: Ride, target: String| if target.is_empty() {
+                "—".to_string()
+            } else {
+                target
+            }),
+        )
+        .bind(&imp.target_label.get(), "label", Some(&ride));
+
+        ClosureExpression::new::<String>(
+            [
+                &ride.property_expression("power-3s"),
+                &ride.property_expression("power-10s"),
+                &ride.property_expression("power-30s"),
+            ],
+            closure!(|_: Ride, power_3s: f64, power_10s: f64, power_30s: f64| {
+                format!("{power_3s:.0} · {power_10s:.0} · {power_30s:.0} W")
+            }),
+        )
+        .bind(&imp.rolling_power_label.get(), "label", Some(&ride));
+        ClosureExpression::new::<String>(
+            [
+                &ride.property_expression("lap-power"),
+                &ride.property_expression("average-power"),
+            ],
+            closure!(|_: Ride, lap_power: f64, average_power: f64| {
+                format!("{lap_power:.0} · {average_power:.0} W")
+            }),
+        )
+        .bind(&imp.average_power_label.get(), "label", Some(&ride));
+        ClosureExpression::new::<String>(
+            [
+                &ride.property_expression("normalized-power"),
+                &ride.property_expression("variability-index"),
+            ],
+            closure!(
+                |_: Ride, normalized_power: f64, variability_index: f64| match normalized_power {
+                    0.0 => "—".to_string(),
+                    _ => format!("{normalized_power:.0} W · {variability_index:.2}"),
+                }
+            ),
+        )
+        .bind(&imp.normalized_power_label.get(), "label", Some(&ride));
+        ClosureExpression::new::<String>(
+            [
+                &ride.property_expression("intensity-factor"),
+                &ride.property_expression("training-stress-score"),
+                &ride.property_expression("kilojoules"),
+            ],
+            closure!(
+                |_: Ride, intensity_factor: f64, stress: f64, kilojoules: f64| {
+                    match intensity_factor {
+                        // No FTP to compare with.
+                        0.0 => format!("— · — · {kilojoules:.0}"),
+                        _ => format!("{intensity_factor:.2} · {stress:.0} · {kilojoules:.0}"),
+                    }
+                }
+            ),
+        )
+        .bind(&imp.training_load_label.get(), "label", Some(&ride));
+
+        ClosureExpression::new::<String>(
+            [&ride.property_expression("state")],
+            closure!(|_: Ride, state: RideState| match state {
+                RideState::Idle => "Ready to Ride",
+                RideState::Riding => "Riding",
+                RideState::AutoPaused => "Auto-Paused, Start Pedaling to Resume",
+                RideState::Paused => "Paused",
+                RideState::Finished => "Ride Finished",
+            }),
+        )
+        .bind(&imp.state_label.get(), "label", Some(&ride));
+
+        let started = ClosureExpression::new::<bool>(
+            [&ride.property_expression("state")],
+            closure!(|_: Ride, state: RideState| matches!(
+                state,
+                RideState::Riding | RideState::AutoPaused | RideState::Paused
+            )),
+        );
+        started.bind(&imp.pause_button.get(), "visible", Some(&ride));
+        started.bind(&imp.lap_button.get(), "visible", Some(&ride));
+        started.bind(&imp.finish_button.get(), "visible", Some(&ride));
+        ClosureExpression::new::<bool>(
+            [&ride.property_expression("state")],
+            closure!(|_: Ride, state: RideState| state == RideState::Idle),
+        )
+        .bind(&imp.start_button.get(), "visible", Some(&ride));
+        ClosureExpression::new::<String>(
+            [&ride.property_expression("state")],
+            closure!(|_: Ride, state: RideState| match state {
+                RideState::Paused => "Resume",
+                _ => "Pause",
+            }),
+        )
+        .bind(&imp.pause_button.get(), "label", Some(&ride));
+    }
+
+    fn refresh_power_bands(&self, ride: &Ride) {
+        self.imp()
+            .chart
+            .set_power_bands(PowerBand::from_zones(&ride.power_zones()));
+    }
+
+    /// Shows the value of a live metric, keeping the last one dimmed next to
+    /// a warning while the sensor is silent.
+    fn bind_metric(
+        &self,
+        ride: &Ride,
+        name: &str,
+        label: &gtk::Label,
+        stale_icon: &gtk::Image,
+        format: fn(f64) -> String,
+    ) {
+        let state = format!("{name}-state");
+        ClosureExpression::new::<String>(
+            [
+                &ride.property_expression(name),
+                &ride.property_expression(&state),
+            ],
+            closure!(move |_: Ride, value: f64, state: MetricState| match state {
+                MetricState::Missing => "—".to_string(),
+                _ => format(value),
+            }),
+        )
+        .bind(label, "label", Some(ride));
+        ClosureExpression::new::<bool>(
+            [&ride.property_expression(&state)],
+            closure!(|_: Ride, state: MetricState| state == MetricState::Stale),
+        )
+        .bind(stale_icon, "visible", Some(ride));
+        ClosureExpression::new::<f64>(
+            [&ride.property_expression(&state)],
+            closure!(|_: Ride, state: MetricState| match state {
+                MetricState::Stale => 0.5,
+                _ => 1.0,
+            }),
+        )
+        .bind(label, "opacity", Some(ride));
+    }
+}
+
+/// A dot in the color of the zone, followed by its name.
+fn zone_markup(zones: &Zones, zone: u32) -> String {
+    let Some(index) = (zone as usize).checked_sub(1) else {
+        return " ".to_string();
+    };
+    let Some(found) = zones.get(index) else {
+        return " ".to_string();
+    };
+    let color = zone_color(index, zones.len());
+    format!(
+        "<span foreground=\"#{:02x}{:02x}{:02x}\">●</span> Z{zone} {}",
+        (color.red() * 255.0) as u8,
+        (color.green() * 255.0) as u8,
+        (color.blue() * 255.0) as u8,
+        glib::markup_escape_text(&found.name)
+    )
+}
+
+pub(super) fn format_duration(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
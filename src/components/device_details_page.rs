@@ -3,11 +3,63 @@ use gtk::glib;
 
 mod imp {
     use super::*;
-    use gtk::{CompositeTemplate, glib::subclass::InitializingObject};
+    use std::cell::RefCell;
 
-    #[derive(Default, CompositeTemplate)]
+    use adw::prelude::{ActionRowExt, ComboRowExt, ObjectExt, PreferencesRowExt};
+    use gtk::{
+        ClosureExpression, CompositeTemplate, ExpressionWatch,
+        glib::{
+            Properties, clone, closure, object::CastNone, subclass::InitializingObject,
+            types::StaticType,
+        },
+        prelude::{
+            AdjustmentExt, DrawingAreaExtManual, GObjectPropertyExpressionExt, ListModelExt,
+            WidgetExt,
+        },
+    };
+
+    use crate::{
+        bluetooth::Device,
+        components::connect_dialog::ConnectDialog,
+        protocol::{
+            CyclingPowerError, CyclingPowerRequest, CyclingPowerResponse, Range, Simulation,
+            TrainerCapabilities, TrainerCommand, TrainerMode,
+        },
+    };
+
+    #[derive(Default, CompositeTemplate, Properties)]
+    #[properties(wrapper_type = super::DeviceDetailsPage)]
     #[template(resource = "/io/github/andreibachim/bike/ui/device_details_page.ui")]
-    pub struct DeviceDetailsPagePrivate {}
+    pub struct DeviceDetailsPagePrivate {
+        #[template_child]
+        status_row: TemplateChild<adw::ActionRow>,
+        #[template_child]
+        link_status_row: TemplateChild<adw::ActionRow>,
+        #[template_child]
+        link_counters_row: TemplateChild<adw::ActionRow>,
+        #[template_child]
+        link_graph: TemplateChild<gtk::DrawingArea>,
+        #[template_child]
+        zero_offset_row: TemplateChild<adw::ActionRow>,
+        #[template_child]
+        crank_length_row: TemplateChild<adw::SpinRow>,
+        #[template_child]
+        sensor_location_row: TemplateChild<adw::ActionRow>,
+        #[template_child]
+        mode_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        modes: TemplateChild<gtk::StringList>,
+        #[template_child]
+        target_row: TemplateChild<adw::SpinRow>,
+        #[property(name = "device", get, set, nullable)]
+        device: RefCell<Option<Device>>,
+        power_meter_watch: RefCell<Option<ExpressionWatch>>,
+        link_watch: RefCell<Option<ExpressionWatch>>,
+        trainer_watch: RefCell<Option<ExpressionWatch>>,
+        /// The modes offered in `mode_row`, in the same order.
+        trainer_modes: RefCell<Vec<TrainerMode>>,
+        capabilities: RefCell<TrainerCapabilities>,
+    }
 
     #[glib::object_subclass]
     impl ObjectSubclass for DeviceDetailsPagePrivate {
@@ -29,9 +81,354 @@ mod imp {
         fn showing() {}
         #[template_callback]
         fn hiding() {}
+        #[template_callback]
+        fn zero_offset(slf: super::DeviceDetailsPage) {
+            let Some(device) = slf.device() else {
+                return;
+            };
+            let imp = slf.imp();
+            imp.zero_offset_row
+                .set_subtitle("Zeroing, keep the cranks still…");
+            device.cycling_power_request(
+                CyclingPowerRequest::OffsetCompensation,
+                clone!(
+                    #[weak]
+                    imp,
+                    move |result| {
+                        let subtitle = match result {
+                            Ok(CyclingPowerResponse::Offset(Some(offset))) => {
+                                format!("Zeroed, the offset is {offset}")
+                            }
+                            Ok(_) => "Zeroed".to_string(),
+                            Err(error) => error.to_string(),
+                        };
+                        imp.zero_offset_row.set_subtitle(&subtitle);
+                    }
+                ),
+            );
+        }
+
+        #[template_callback]
+        fn set_crank_length(slf: super::DeviceDetailsPage) {
+            let Some(device) = slf.device() else {
+                return;
+            };
+            let imp = slf.imp();
+            device.cycling_power_request(
+                CyclingPowerRequest::SetCrankLength(imp.crank_length_row.value()),
+                clone!(
+                    #[weak]
+                    imp,
+                    move |result| imp.show_crank_length(result)
+                ),
+            );
+        }
+
+        #[template_callback]
+        fn mode_changed(slf: super::DeviceDetailsPage) {
+            slf.imp().refresh_target();
+        }
+
+        #[template_callback]
+        fn apply_target(slf: super::DeviceDetailsPage) {
+            let Some(device) = slf.device() else {
+                return;
+            };
+            let imp = slf.imp();
+            let Some(mode) = imp.selected_mode() else {
+                return;
+            };
+            let target = imp.target_row.value();
+            let command = match mode {
+                TrainerMode::Erg => TrainerCommand::TargetPower(target as i16),
+                TrainerMode::Resistance => TrainerCommand::Resistance(target),
+                TrainerMode::Simulation => TrainerCommand::Simulation(Simulation {
+                    grade: target,
+                    ..Simulation::default()
+                }),
+            };
+            match device.send_trainer_command(&command) {
+                Ok(()) => imp.target_row.set_subtitle(""),
+                Err(error) => imp.target_row.set_subtitle(&error.to_string()),
+            }
+        }
+
+        #[template_callback]
+        fn start_spin_down(slf: super::DeviceDetailsPage) {
+            if let (Some(device), Some(connect_dialog)) = (
+                slf.device(),
+                slf.ancestor(ConnectDialog::static_type())
+                    .and_downcast::<ConnectDialog>(),
+            ) {
+                connect_dialog.show_spin_down(&device);
+            }
+        }
     }
 
-    impl ObjectImpl for DeviceDetailsPagePrivate {}
+    impl DeviceDetailsPagePrivate {
+        fn refresh_power_meter(&self) {
+            let Some(device) = self.device.borrow().clone() else {
+                return;
+            };
+            if !device.power_meter() {
+                return;
+            }
+            self.zero_offset_row
+                .set_subtitle("Unclip and keep the cranks still while zeroing");
+            self.sensor_location_row.set_subtitle(
+                &device
+                    .sensor_location()
+                    .map(|location| location.to_string())
+                    .unwrap_or_else(|| "Unknown".to_string()),
+            );
+            device.cycling_power_request(
+                CyclingPowerRequest::RequestCrankLength,
+                clone!(
+                    #[weak(rename_to = imp)]
+                    self,
+                    move |result| imp.show_crank_length(result)
+                ),
+            );
+        }
+
+        fn refresh_trainer(&self) {
+            let Some(capabilities) = self
+                .device
+                .borrow()
+                .as_ref()
+                .and_then(|device| device.trainer_capabilities())
+            else {
+                return;
+            };
+            let modes = capabilities.modes();
+            let names: Vec<String> = modes.iter().map(|mode| mode.to_string()).collect();
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            self.capabilities.replace(capabilities);
+            self.trainer_modes.replace(modes);
+            // Replacing the model items resets the selection and refreshes
+            // the target through `mode_changed`.
+            self.modes.splice(0, self.modes.n_items(), &names);
+            self.mode_row.set_sensitive(!names.is_empty());
+            self.target_row.set_sensitive(!names.is_empty());
+            if names.is_empty() {
+                self.mode_row
+                    .set_subtitle("The trainer does not accept any targets");
+            }
+            self.refresh_target();
+        }
+
+        fn selected_mode(&self) -> Option<TrainerMode> {
+            self.trainer_modes
+                .borrow()
+                .get(self.mode_row.selected() as usize)
+                .copied()
+        }
+
+        /// Fits the target row to the selected mode and to the range the
+        /// trainer reported for it.
+        fn refresh_target(&self) {
+            let Some(mode) = self.selected_mode() else {
+                return;
+            };
+            let capabilities = *self.capabilities.borrow();
+            let (title, range, value, digits) = match mode {
+                TrainerMode::Erg => (
+                    "Target Power (W)",
+                    capabilities.power_range.unwrap_or(Range {
+                        min: 0.0,
+                        max: 2000.0,
+                        increment: 1.0,
+                    }),
+                    150.0,
+                    0,
+                ),
+                TrainerMode::Resistance => (
+                    "Resistance (%)",
+                    Range {
+                        min: 0.0,
+                        max: 100.0,
+                        increment: 1.0,
+                    },
+                    30.0,
+                    0,
+                ),
+                TrainerMode::Simulation => (
+                    "Grade (%)",
+                    capabilities.inclination_range.unwrap_or(Range {
+                        min: -20.0,
+                        max: 20.0,
+                        increment: 0.5,
+                    }),
+                    0.0,
+                    1,
+                ),
+            };
+            let step = if range.increment > 0.0 {
+                range.increment
+            } else {
+                1.0
+            };
+            self.target_row.set_title(title);
+            self.target_row.set_digits(digits);
+            self.target_row.set_subtitle("");
+            let adjustment = self.target_row.adjustment();
+            adjustment.configure(
+                range.snap(value),
+                range.min,
+                range.max,
+                step,
+                step * 10.0,
+                0.0,
+            );
+        }
+
+        fn refresh_link(&self) {
+            let Some(device) = self.device.borrow().clone() else {
+                return;
+            };
+            let problem = device.link_problem();
+            self.link_status_row.set_subtitle(&if problem.is_empty() {
+                device.link_status().to_string()
+            } else {
+                format!("{}. {problem}", device.link_status())
+            });
+            let health = device.link_health();
+            self.link_counters_row.set_subtitle(&format!(
+                "{:.1} notifications/s, {} gaps, {} duplicates, {} out of order, {} reconnects",
+                device.link_rate(),
+                health.gaps,
+                health.duplicates,
+                health.out_of_order,
+                health.reconnects
+            ));
+            self.link_graph.queue_draw();
+        }
+
+        /// Plots the notification rate and the RSSI of the last two minutes,
+        /// each scaled to the full height.
+        fn draw_link_graph(&self, context: &gtk::cairo::Context, width: f64, height: f64) {
+            let Some(device) = self.device.borrow().clone() else {
+                return;
+            };
+            let health = device.link_health();
+            let samples = health.rate_history.len().max(health.rssi_history.len());
+            if samples < 2 {
+                return;
+            }
+            let step = width / (samples - 1) as f64;
+            let max_rate = health.rate_history.iter().copied().fold(1.0, f64::max);
+            let color = self.obj().color();
+
+            context.set_line_width(2.0);
+            context.set_source_rgba(0.21, 0.52, 0.89, 1.0);
+            for (index, rate) in health.rate_history.iter().enumerate() {
+                let y = height - rate / max_rate * height;
+                context.line_to(index as f64 * step, y);
+            }
+            let _ = context.stroke();
+
+            // RSSI from -100 dBm at the bottom to -30 dBm at the top.
+            context.set_line_width(1.0);
+            context.set_source_rgba(
+                color.red().into(),
+                color.green().into(),
+                color.blue().into(),
+                0.5,
+            );
+            for (index, rssi) in health.rssi_history.iter().enumerate() {
+                match rssi {
+                    Some(rssi) => {
+                        let level = ((f64::from(*rssi) + 100.0) / 70.0).clamp(0.0, 1.0);
+                        context.line_to(index as f64 * step, height - level * height);
+                    }
+                    None => context.new_sub_path(),
+                }
+            }
+            let _ = context.stroke();
+        }
+
+        fn show_crank_length(&self, result: Result<CyclingPowerResponse, CyclingPowerError>) {
+            match result {
+                Ok(CyclingPowerResponse::CrankLength(length)) => {
+                    self.crank_length_row.set_value(length);
+                    self.crank_length_row.set_subtitle("");
+                }
+                Ok(_) => (),
+                Err(error) => self.crank_length_row.set_subtitle(&error.to_string()),
+            }
+        }
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for DeviceDetailsPagePrivate {
+        fn constructed(&self) {
+            self.parent_constructed();
+            let power_meter_watch = self
+                .obj()
+                .property_expression("device")
+                .chain_property::<Device>("power-meter")
+                .watch(
+                    Some(self.obj().as_ref()),
+                    clone!(
+                        #[weak(rename_to = imp)]
+                        self,
+                        move || imp.refresh_power_meter()
+                    ),
+                );
+            self.power_meter_watch.replace(Some(power_meter_watch));
+            let link_watch = self
+                .obj()
+                .property_expression("device")
+                .chain_property::<Device>("link-rate")
+                .watch(
+                    Some(self.obj().as_ref()),
+                    clone!(
+                        #[weak(rename_to = imp)]
+                        self,
+                        move || imp.refresh_link()
+                    ),
+                );
+            self.link_watch.replace(Some(link_watch));
+            self.link_graph.set_draw_func(clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |_, context, width, height| {
+                    imp.draw_link_graph(context, f64::from(width), f64::from(height))
+                }
+            ));
+            let trainer_watch = self
+                .obj()
+                .property_expression("device")
+                .chain_property::<Device>("controllable")
+                .watch(
+                    Some(self.obj().as_ref()),
+                    clone!(
+                        #[weak(rename_to = imp)]
+                        self,
+                        move || imp.refresh_trainer()
+                    ),
+                );
+            self.trainer_watch.replace(Some(trainer_watch));
+            ClosureExpression::new::<String>(
+                [&self
+                    .obj()
+                    .property_expression("device")
+                    .chain_property::<Device>("connected")],
+                closure!(|_: adw::ActionRow, connected: bool| {
+                    if connected {
+                        "Connected"
+                    } else {
+                        "Connecting…"
+                    }
+                }),
+            )
+            .bind(
+                &self.status_row.get(),
+                "subtitle",
+                Some(&self.status_row.get()),
+            );
+        }
+    }
     impl WidgetImpl for DeviceDetailsPagePrivate {}
     impl NavigationPageImpl for DeviceDetailsPagePrivate {}
 }
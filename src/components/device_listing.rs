@@ -1,7 +1,8 @@
 mod imp {
 
     use adw::subclass::prelude::{
-        ActionRowImpl, ObjectImpl, ObjectSubclass, PreferencesRowImpl, WidgetClassExt,
+        ActionRowImpl, ObjectImpl, ObjectSubclass, ObjectSubclassIsExt, PreferencesRowImpl,
+        WidgetClassExt,
     };
     use gtk::glib::object::CastNone;
     use gtk::glib::subclass::InitializingObject;
@@ -16,6 +17,9 @@ mod imp {
         subclass::{prelude::ListBoxRowImpl, widget::WidgetImpl},
     };
 
+    use std::cell::RefCell;
+
+    use crate::bluetooth::Device;
     use crate::components::connect_dialog::ConnectDialog;
 
     use super::DeviceListing;
@@ -23,8 +27,11 @@ mod imp {
     #[derive(Debug, Default, CompositeTemplate)]
     #[template(resource = "/io/github/andreibachim/bike/ui/device_listing.ui")]
     pub struct DeviceListingPrivate {
+        #[template_child]
+        pub link_icon: TemplateChild<gtk::Image>,
         #[template_child]
         pub signal_icon: TemplateChild<gtk::Image>,
+        pub device: RefCell<Option<Device>>,
     }
 
     #[glib::object_subclass]
@@ -45,9 +52,13 @@ mod imp {
     impl DeviceListingPrivate {
         #[template_callback]
         fn connect(slf: DeviceListing) {
+            let Some(device) = slf.imp().device.borrow().clone() else {
+                return;
+            };
+            device.connect();
             slf.ancestor(ConnectDialog::static_type())
                 .and_downcast()
-                .inspect(|connect_dialog: &ConnectDialog| connect_dialog.load_details());
+                .inspect(|connect_dialog: &ConnectDialog| connect_dialog.show_device(&device));
         }
     }
 
@@ -65,7 +76,7 @@ use gtk::{
     prelude::GObjectPropertyExpressionExt,
 };
 
-use crate::bluetooth::Device;
+use crate::bluetooth::{Device, LinkStatus};
 
 glib::wrapper! {
     pub struct DeviceListing(ObjectSubclass<imp::DeviceListingPrivate>)
@@ -77,6 +88,7 @@ impl DeviceListing {
     pub fn new(device: &Device) -> Self {
         log::debug!("Device found: {device}");
         let slf: Self = Object::builder().build();
+        slf.imp().device.replace(Some(device.clone()));
 
         //Bind title
         device
@@ -89,17 +101,30 @@ impl DeviceListing {
             [
                 &device.property_expression("paired"),
                 &device.property_expression("connected"),
+                &device.property_expression("in-use"),
+                &device.property_expression("brand"),
+                &device.property_expression("services"),
             ],
             closure!(|_: <imp::DeviceListingPrivate as ObjectSubclass>::Type,
                       paired: bool,
-                      connected: bool| {
-                if connected {
+                      connected: bool,
+                      in_use: bool,
+                      brand: String,
+                      services: String| {
+                let status = if connected {
                     "Connected"
+                } else if in_use {
+                    "In Use by Another App"
                 } else if paired {
                     "Disconnected"
                 } else {
                     "Not Set Up"
-                }
+                };
+                [status, &brand, &services]
+                    .into_iter()
+                    .filter(|part| !part.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" · ")
             }),
         )
         .bind(&slf, "subtitle", Some(&slf));
@@ -138,6 +163,46 @@ impl DeviceListing {
             Some(&slf.imp().signal_icon.get()),
         );
 
+        //Bind link health
+        device
+            .bind_property("connected", &slf.imp().link_icon.get(), "visible")
+            .sync_create()
+            .build();
+        ClosureExpression::new::<String>(
+            [&device.property_expression("link-status")],
+            closure!(|_: gtk::Image, status: LinkStatus| {
+                match status {
+                    LinkStatus::Unknown => "content-loading-symbolic",
+                    LinkStatus::Good => "emblem-ok-symbolic",
+                    LinkStatus::Unstable => "dialog-warning-symbolic",
+                    LinkStatus::Poor => "dialog-error-symbolic",
+                }
+            }),
+        )
+        .bind(
+            &slf.imp().link_icon.get(),
+            "icon-name",
+            Some(&slf.imp().link_icon.get()),
+        );
+        ClosureExpression::new::<String>(
+            [
+                &device.property_expression("link-status"),
+                &device.property_expression("link-problem"),
+            ],
+            closure!(|_: gtk::Image, status: LinkStatus, problem: String| {
+                if problem.is_empty() {
+                    format!("Link: {status}")
+                } else {
+                    format!("Link: {status}. {problem}")
+                }
+            }),
+        )
+        .bind(
+            &slf.imp().link_icon.get(),
+            "tooltip-text",
+            Some(&slf.imp().link_icon.get()),
+        );
+
         slf
     }
 }
@@ -0,0 +1,278 @@
+mod imp {
+    use adw::glib::subclass::InitializingObject;
+    use adw::subclass::prelude::*;
+    use gtk::{CompositeTemplate, glib, subclass::widget::WidgetImpl};
+
+    use crate::components::RidePage;
+
+    #[derive(CompositeTemplate, Default)]
+    #[template(resource = "/io/github/andreibachim/bike/ui/history_dialog.ui")]
+    pub struct HistoryDialogPrivate {
+        #[template_child]
+        pub navigation_view: TemplateChild<adw::NavigationView>,
+        #[template_child]
+        pub ride_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub ride_page: TemplateChild<RidePage>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for HistoryDialogPrivate {
+        const NAME: &str = "HistoryDialog";
+        type Type = super::HistoryDialog;
+        type ParentType = adw::Dialog;
+        fn class_init(klass: &mut Self::Class) {
+            klass.bind_template();
+        }
+        fn instance_init(obj: &InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for HistoryDialogPrivate {}
+    impl WidgetImpl for HistoryDialogPrivate {}
+    impl AdwDialogImpl for HistoryDialogPrivate {}
+}
+
+use std::{cell::RefCell, rc::Rc};
+
+use adw::{
+    prelude::{ActionRowExt, AlertDialogExt, AlertDialogExtManual, PreferencesRowExt},
+    subclass::prelude::ObjectSubclassIsExt,
+};
+use gtk::{
+    gio::{self, Cancellable, prelude::ActionMapExtManual},
+    glib::{self, Object, VariantTy, clone, object::CastNone},
+    prelude::{EditableExt, WidgetExt},
+};
+
+use super::{dashboard::format_duration, ride_page::format_start};
+use crate::{
+    export::{self, Format},
+    history::{self, SavedRide},
+};
+
+glib::wrapper! {
+    /// Lists the saved rides, and opens, renames, deletes or exports them.
+    pub struct HistoryDialog(ObjectSubclass<imp::HistoryDialogPrivate>)
+        @extends adw::Dialog, gtk::Widget,
+        @implements gtk::Accessible, gtk::Buildable, gtk::ConstraintTarget, gtk::ShortcutManager;
+}
+
+/// Sums up a ride on one line, leaving out what it didn't record.
+fn describe(ride: &SavedRide) -> String {
+    let mut parts = vec![
+        format_start(ride.start),
+        format_duration(ride.totals.elapsed),
+        format!("{:.2} km", ride.totals.distance / 1000.0),
+    ];
+    if let Some(power) = ride.average_power {
+        parts.push(format!("{power:.0} W"));
+    }
+    if let Some(power) = ride.normalized_power {
+        parts.push(format!("NP {power:.0} W"));
+    }
+    if let Some(stress) = ride.training_stress_score {
+        parts.push(format!("TSS {stress:.0}"));
+    }
+    parts.join(" · ")
+}
+
+fn ride_menu() -> gio::Menu {
+    let menu = gio::Menu::new();
+    menu.append(Some("Rename…"), Some("saved-ride.rename"));
+    let formats = gio::Menu::new();
+    for (label, format) in [
+        ("Export as FIT", "fit"),
+        ("Export as TCX", "tcx"),
+        ("Export as CSV", "csv"),
+    ] {
+        let item = gio::MenuItem::new(Some(label), None);
+        item.set_action_and_target_value(Some("saved-ride.export"), Some(&format.into()));
+        formats.append_item(&item);
+    }
+    menu.append_section(None, &formats);
+    let danger = gio::Menu::new();
+    danger.append(Some("Delete"), Some("saved-ride.delete"));
+    menu.append_section(None, &danger);
+    menu
+}
+
+impl HistoryDialog {
+    pub fn new() -> Self {
+        let slf: Self = Object::builder().build();
+        slf.refresh();
+        slf
+    }
+
+    fn refresh(&self) {
+        let list = &self.imp().ride_list;
+        list.remove_all();
+        for ride in history::rides() {
+            list.append(&self.ride_row(ride));
+        }
+    }
+
+    fn ride_row(&self, ride: SavedRide) -> adw::ActionRow {
+        let row = adw::ActionRow::builder()
+            .title(&ride.name)
+            .use_markup(false)
+            .subtitle(describe(&ride))
+            .activatable(true)
+            .build();
+        row.add_suffix(
+            &gtk::MenuButton::builder()
+                .icon_name("view-more-symbolic")
+                .tooltip_text("Ride Options")
+                .menu_model(&ride_menu())
+                .valign(gtk::Align::Center)
+                .css_classes(["flat"])
+                .build(),
+        );
+        let ride = Rc::new(RefCell::new(ride));
+        row.connect_activated(clone!(
+            #[weak(rename_to = slf)]
+            self,
+            #[strong]
+            ride,
+            move |_| slf.open(&ride.borrow())
+        ));
+
+        let actions = gio::SimpleActionGroup::new();
+        actions.add_action_entries([
+            gio::ActionEntry::builder("rename")
+                .activate(clone!(
+                    #[weak(rename_to = slf)]
+                    self,
+                    #[weak]
+                    row,
+                    #[strong]
+                    ride,
+                    move |_, _, _| slf.rename(&row, ride.clone())
+                ))
+                .build(),
+            gio::ActionEntry::builder("export")
+                .parameter_type(Some(VariantTy::STRING))
+                .activate(clone!(
+                    #[weak(rename_to = slf)]
+                    self,
+                    #[strong]
+                    ride,
+                    move |_, _, format| {
+                        if let Some(format) = format
+                            .and_then(|format| format.str())
+                            .and_then(Format::from_name)
+                        {
+                            slf.export(&ride.borrow(), format);
+                        }
+                    }
+                ))
+                .build(),
+            gio::ActionEntry::builder("delete")
+                .activate(clone!(
+                    #[weak(rename_to = slf)]
+                    self,
+                    #[weak]
+                    row,
+                    #[strong]
+                    ride,
+                    move |_, _, _| slf.delete(&row, ride.clone())
+                ))
+                .build(),
+        ]);
+        row.insert_action_group("saved-ride", Some(&actions));
+        row
+    }
+
+    fn open(&self, ride: &SavedRide) {
+        match ride.load() {
+            Ok(activity) => {
+                let imp = self.imp();
+                imp.ride_page.set_activity(&ride.name, activity);
+                imp.navigation_view.push_by_tag("ride-page");
+            }
+            Err(error) => log::error!("Could not open {}. {error}", ride.path.display()),
+        }
+    }
+
+    fn export(&self, ride: &SavedRide, format: Format) {
+        match ride.load() {
+            Ok(activity) => export::save_as(
+                self.root().and_downcast_ref::<gtk::Window>(),
+                activity,
+                format,
+            ),
+            Err(error) => log::error!("Could not open {}. {error}", ride.path.display()),
+        }
+    }
+
+    fn rename(&self, row: &adw::ActionRow, ride: Rc<RefCell<SavedRide>>) {
+        let entry = gtk::Entry::builder()
+            .text(ride.borrow().name.as_str())
+            .activates_default(true)
+            .build();
+        let dialog = adw::AlertDialog::new(Some("Rename Ride"), None);
+        dialog.set_extra_child(Some(&entry));
+        dialog.add_responses(&[("cancel", "Cancel"), ("rename", "Rename")]);
+        dialog.set_response_appearance("rename", adw::ResponseAppearance::Suggested);
+        dialog.set_default_response(Some("rename"));
+        dialog.set_close_response("cancel");
+        dialog.choose(
+            self,
+            None::<&Cancellable>,
+            clone!(
+                #[weak]
+                row,
+                move |response| {
+                    let name = entry.text();
+                    let name = name.trim();
+                    if response != "rename" || name.is_empty() {
+                        return;
+                    }
+                    match ride.borrow_mut().rename(name) {
+                        Ok(()) => row.set_title(name),
+                        Err(error) => log::error!("Could not rename the ride. {error}"),
+                    }
+                }
+            ),
+        );
+    }
+
+    fn delete(&self, row: &adw::ActionRow, ride: Rc<RefCell<SavedRide>>) {
+        let dialog = adw::AlertDialog::new(
+            Some("Delete Ride?"),
+            Some(&format!(
+                "“{}” will be gone for good, unless it was exported.",
+                ride.borrow().name
+            )),
+        );
+        dialog.add_responses(&[("cancel", "Cancel"), ("delete", "Delete")]);
+        dialog.set_response_appearance("delete", adw::ResponseAppearance::Destructive);
+        dialog.set_close_response("cancel");
+        dialog.choose(
+            self,
+            None::<&Cancellable>,
+            clone!(
+                #[weak(rename_to = slf)]
+                self,
+                #[weak]
+                row,
+                move |response| {
+                    if response != "delete" {
+                        return;
+                    }
+                    match ride.borrow().delete() {
+                        Ok(()) => slf.imp().ride_list.remove(&row),
+                        Err(error) => log::error!("Could not delete the ride. {error}"),
+                    }
+                }
+            ),
+        );
+    }
+}
+
+impl Default for HistoryDialog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
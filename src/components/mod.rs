@@ -8,3 +8,15 @@ pub use bluetooth_button::BluetoothButton;
 mod device_listing;
 mod device_details_page;
 pub use device_details_page::DeviceDetailsPage;
+mod spin_down_page;
+pub use spin_down_page::SpinDownPage;
+mod dashboard;
+pub use dashboard::Dashboard;
+mod ride_chart;
+pub use ride_chart::{PowerBand, RideChart, zone_color};
+mod zones_dialog;
+pub use zones_dialog::ZonesDialog;
+mod ride_page;
+pub use ride_page::RidePage;
+mod history_dialog;
+pub use history_dialog::HistoryDialog;
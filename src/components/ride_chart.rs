@@ -0,0 +1,259 @@
+mod imp {
+    use std::cell::{Cell, RefCell};
+
+    use adw::subclass::prelude::*;
+    use gtk::{
+        glib::{self, Properties},
+        prelude::{ObjectExt, WidgetExt},
+        subclass::widget::WidgetImpl,
+    };
+
+    use crate::session::Record;
+
+    use super::{ChartMetrics, PowerBand};
+
+    #[derive(Debug, Default, Properties)]
+    #[properties(wrapper_type = super::RideChart)]
+    pub struct RideChartPrivate {
+        /// How many seconds to show, ending with the last record. 0 shows
+        /// the whole ride.
+        #[property(name = "span", get, set = Self::set_span)]
+        span: Cell<u32>,
+
+        #[property(name = "metrics", get, set = Self::set_metrics, builder(ChartMetrics::default()))]
+        metrics: Cell<ChartMetrics>,
+
+        pub records: RefCell<Vec<Record>>,
+
+        pub power_bands: RefCell<Vec<PowerBand>>,
+    }
+
+    impl RideChartPrivate {
+        fn set_span(&self, span: u32) {
+            self.span.set(span);
+            self.obj().queue_draw();
+        }
+
+        fn set_metrics(&self, metrics: ChartMetrics) {
+            self.metrics.set(metrics);
+            self.obj().queue_draw();
+        }
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for RideChartPrivate {
+        const NAME: &'static str = "RideChart";
+        type Type = super::RideChart;
+        type ParentType = gtk::Widget;
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for RideChartPrivate {}
+
+    impl WidgetImpl for RideChartPrivate {
+        fn snapshot(&self, snapshot: &gtk::Snapshot) {
+            self.obj().draw(snapshot);
+        }
+    }
+}
+
+use adw::subclass::prelude::ObjectSubclassIsExt;
+use gtk::{
+    gdk::RGBA,
+    glib,
+    graphene::Rect,
+    gsk::{PathBuilder, Stroke},
+    prelude::{SnapshotExt, WidgetExt},
+};
+
+use crate::session::{Record, Zones};
+
+/// Records further apart than this, in seconds, are not joined by a line.
+const MAX_GAP: f64 = 2.0;
+/// Heart rate and cadence share a scale going up to this.
+const PULSE_SCALE: f64 = 220.0;
+/// The power scale reaches at least this, in watts.
+const MIN_POWER_SCALE: f64 = 200.0;
+
+const POWER_COLOR: RGBA = RGBA::new(0.96, 0.76, 0.07, 1.0);
+const HEART_RATE_COLOR: RGBA = RGBA::new(0.88, 0.11, 0.14, 1.0);
+const CADENCE_COLOR: RGBA = RGBA::new(0.21, 0.52, 0.89, 1.0);
+const BAND_ALPHA: f32 = 0.15;
+
+/// From easy to all out, the colors of seven training zones.
+const ZONE_COLORS: [RGBA; 7] = [
+    RGBA::new(0.6, 0.6, 0.6, 1.0),
+    RGBA::new(0.21, 0.52, 0.89, 1.0),
+    RGBA::new(0.2, 0.82, 0.48, 1.0),
+    RGBA::new(0.96, 0.83, 0.18, 1.0),
+    RGBA::new(1.0, 0.47, 0.0, 1.0),
+    RGBA::new(0.88, 0.11, 0.14, 1.0),
+    RGBA::new(0.57, 0.25, 0.67, 1.0),
+];
+
+/// The color of zone `index` out of `count`, spreading fewer zones over the
+/// whole range so that the top zone is always the hottest.
+pub fn zone_color(index: usize, count: usize) -> RGBA {
+    let last = ZONE_COLORS.len() - 1;
+    match count {
+        0 | 1 => ZONE_COLORS[0],
+        count if count > ZONE_COLORS.len() => ZONE_COLORS[index.min(last)],
+        count => ZONE_COLORS[index.min(count - 1) * last / (count - 1)],
+    }
+}
+
+/// Which lines a chart draws.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, glib::Enum)]
+#[enum_type(name = "ChartMetrics")]
+pub enum ChartMetrics {
+    /// Power, its target and bands, heart rate and cadence.
+    #[default]
+    All,
+    /// Power, its target and bands.
+    Power,
+    HeartRate,
+}
+
+/// A stretch of power painted behind the lines, like a training zone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PowerBand {
+    /// Watts.
+    pub from: f64,
+    /// Watts, `f64::INFINITY` for the top band.
+    pub to: f64,
+    pub color: RGBA,
+}
+
+impl PowerBand {
+    /// One band per zone, in the zone colors.
+    pub fn from_zones(zones: &Zones) -> Vec<Self> {
+        zones
+            .iter()
+            .enumerate()
+            .map(|(index, zone)| PowerBand {
+                from: zone.from,
+                to: zone.to,
+                color: zone_color(index, zones.len()),
+            })
+            .collect()
+    }
+}
+
+glib::wrapper! {
+    /// Plots power, heart rate and cadence over time, along with the power
+    /// target and bands.
+    pub struct RideChart(ObjectSubclass<imp::RideChartPrivate>)
+        @extends gtk::Widget,
+        @implements gtk::Accessible, gtk::Buildable, gtk::ConstraintTarget;
+}
+
+impl RideChart {
+    pub fn set_records(&self, records: &[Record]) {
+        self.imp().records.replace(records.to_vec());
+        self.queue_draw();
+    }
+
+    pub fn set_power_bands(&self, bands: Vec<PowerBand>) {
+        self.imp().power_bands.replace(bands);
+        self.queue_draw();
+    }
+
+    fn draw(&self, snapshot: &gtk::Snapshot) {
+        let width = self.width() as f32;
+        let height = self.height() as f32;
+        let records = self.imp().records.borrow();
+        let (Some(first), Some(last)) = (records.first(), records.last()) else {
+            return;
+        };
+        let end = last.elapsed;
+        let start = match self.span() {
+            0 => first.elapsed,
+            span => end - f64::from(span),
+        };
+        let duration = (end - start).max(1.0);
+        let visible = &records[records.partition_point(|record| record.elapsed < start)..];
+
+        let power_scale = visible
+            .iter()
+            .flat_map(|record| [record.power, record.target_power])
+            .flatten()
+            .map(f64::from)
+            .fold(MIN_POWER_SCALE, f64::max)
+            * 1.1;
+        let x = |elapsed: f64| ((elapsed - start) / duration) as f32 * width;
+        let y = |value: f64, scale: f64| height - (value / scale).clamp(0.0, 1.0) as f32 * height;
+
+        let metrics = self.metrics();
+        let power = metrics != ChartMetrics::HeartRate;
+        let heart_rate = metrics != ChartMetrics::Power;
+        let bands = self.imp().power_bands.borrow();
+        for band in bands.iter().filter(|_| power) {
+            if band.from >= power_scale {
+                continue;
+            }
+            let top = y(band.to.min(power_scale), power_scale);
+            let bottom = y(band.from, power_scale);
+            let mut color = band.color;
+            color.set_alpha(BAND_ALPHA);
+            snapshot.append_color(&color, &Rect::new(0.0, top, width, bottom - top));
+        }
+
+        let line = |value: fn(&Record) -> Option<f64>, scale: f64, stroke: &Stroke, color| {
+            let path = PathBuilder::new();
+            let mut previous: Option<f64> = None;
+            for record in visible {
+                let Some(value) = value(record) else {
+                    previous = None;
+                    continue;
+                };
+                let point = (x(record.elapsed), y(value, scale));
+                match previous {
+                    Some(elapsed) if record.elapsed - elapsed <= MAX_GAP => {
+                        path.line_to(point.0, point.1)
+                    }
+                    _ => path.move_to(point.0, point.1),
+                }
+                previous = Some(record.elapsed);
+            }
+            snapshot.append_stroke(&path.to_path(), stroke, color);
+        };
+
+        let target_stroke = Stroke::new(2.0);
+        target_stroke.set_dash(&[6.0, 4.0]);
+        let mut target_color = self.color();
+        target_color.set_alpha(0.6);
+        let stroke = Stroke::new(1.5);
+        if power {
+            line(
+                |record| record.target_power.map(f64::from),
+                power_scale,
+                &target_stroke,
+                &target_color,
+            );
+        }
+        if metrics == ChartMetrics::All {
+            line(
+                |record| record.cadence,
+                PULSE_SCALE,
+                &stroke,
+                &CADENCE_COLOR,
+            );
+        }
+        if heart_rate {
+            line(
+                |record| record.heart_rate.map(f64::from),
+                PULSE_SCALE,
+                &stroke,
+                &HEART_RATE_COLOR,
+            );
+        }
+        if power {
+            line(
+                |record| record.power.map(f64::from),
+                power_scale,
+                &Stroke::new(2.0),
+                &POWER_COLOR,
+            );
+        }
+    }
+}
@@ -0,0 +1,511 @@
+mod imp {
+    use std::{cell::RefCell, sync::OnceLock};
+
+    use adw::glib::subclass::InitializingObject;
+    use adw::subclass::prelude::*;
+    use gtk::{
+        CompositeTemplate,
+        glib::{self, VariantTy, subclass::Signal},
+        subclass::widget::WidgetImpl,
+    };
+
+    use crate::{components::RideChart, export::Format, session::Activity};
+
+    #[derive(CompositeTemplate, Default)]
+    #[template(resource = "/io/github/andreibachim/bike/ui/ride_page.ui")]
+    pub struct RidePagePrivate {
+        #[template_child]
+        pub save_bar: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub name_row: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub start_row: TemplateChild<adw::ActionRow>,
+        #[template_child]
+        pub elapsed_row: TemplateChild<adw::ActionRow>,
+        #[template_child]
+        pub moving_time_row: TemplateChild<adw::ActionRow>,
+        #[template_child]
+        pub distance_row: TemplateChild<adw::ActionRow>,
+        #[template_child]
+        pub energy_row: TemplateChild<adw::ActionRow>,
+        #[template_child]
+        pub power_group: TemplateChild<adw::PreferencesGroup>,
+        #[template_child]
+        pub average_power_row: TemplateChild<adw::ActionRow>,
+        #[template_child]
+        pub normalized_power_row: TemplateChild<adw::ActionRow>,
+        #[template_child]
+        pub training_load_row: TemplateChild<adw::ActionRow>,
+        #[template_child]
+        pub best_efforts_group: TemplateChild<adw::PreferencesGroup>,
+        #[template_child]
+        pub lap_grid: TemplateChild<gtk::Grid>,
+        #[template_child]
+        pub power_zones_group: TemplateChild<adw::PreferencesGroup>,
+        #[template_child]
+        pub heart_rate_zones_group: TemplateChild<adw::PreferencesGroup>,
+        #[template_child]
+        pub power_chart_group: TemplateChild<adw::PreferencesGroup>,
+        #[template_child]
+        pub power_chart: TemplateChild<RideChart>,
+        #[template_child]
+        pub heart_rate_chart_group: TemplateChild<adw::PreferencesGroup>,
+        #[template_child]
+        pub heart_rate_chart: TemplateChild<RideChart>,
+        pub activity: RefCell<Option<Activity>>,
+        /// Rows filled in for the ride shown, removed before showing another.
+        pub rows: RefCell<Vec<(adw::PreferencesGroup, adw::ActionRow)>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for RidePagePrivate {
+        const NAME: &str = "RidePage";
+        type Type = super::RidePage;
+        type ParentType = adw::NavigationPage;
+        fn class_init(klass: &mut Self::Class) {
+            klass.bind_template();
+            klass.install_action(
+                "ride-page.export",
+                Some(VariantTy::STRING),
+                |page, _, format| {
+                    if let Some(format) = format
+                        .and_then(|format| format.str())
+                        .and_then(Format::from_name)
+                    {
+                        page.export(format);
+                    }
+                },
+            );
+            klass.install_action("ride-page.save", None, |page, _, _| {
+                page.save();
+            });
+            klass.install_action("ride-page.discard", None, |page, _, _| {
+                page.discard();
+            });
+        }
+        fn instance_init(obj: &InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for RidePagePrivate {
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: OnceLock<Vec<Signal>> = OnceLock::new();
+            SIGNALS.get_or_init(|| {
+                vec![
+                    Signal::builder("saved").build(),
+                    Signal::builder("discarded").build(),
+                ]
+            })
+        }
+    }
+    impl WidgetImpl for RidePagePrivate {}
+    impl NavigationPageImpl for RidePagePrivate {}
+}
+
+use adw::{
+    prelude::{
+        ActionRowExt, AlertDialogExt, AlertDialogExtManual, EditableExt, NavigationPageExt,
+        PreferencesGroupExt,
+    },
+    subclass::prelude::ObjectSubclassIsExt,
+};
+use gtk::{
+    gio::Cancellable,
+    glib::{
+        self, Object, SignalHandlerId, clone,
+        object::{Cast, CastNone},
+        prelude::ObjectExt,
+    },
+    prelude::{DrawingAreaExtManual, GridExt, WidgetExt},
+};
+
+use super::{PowerBand, dashboard::format_duration, zone_color};
+use crate::{
+    export::{self, Format},
+    history,
+    session::{
+        Activity, BEST_EFFORTS, LapTrigger, Stat, TimeInZones, ZoneSettings, Zones, best_power,
+    },
+};
+
+glib::wrapper! {
+    /// Everything about a ride that is over: totals, laps, zones, charts and
+    /// best efforts.
+    pub struct RidePage(ObjectSubclass<imp::RidePagePrivate>)
+        @extends adw::NavigationPage, gtk::Widget,
+        @implements gtk::Accessible, gtk::Buildable, gtk::ConstraintTarget;
+}
+
+/// Seconds since the Unix epoch, as a local date and time.
+pub fn format_start(start: i64) -> String {
+    glib::DateTime::from_unix_local(start)
+        .and_then(|start| start.format("%a %e %b %Y, %H:%M"))
+        .map(String::from)
+        .unwrap_or_default()
+}
+
+/// Like "5 s" or "20 min".
+fn format_effort(seconds: usize) -> String {
+    match seconds {
+        seconds if seconds < 60 => format!("{seconds} s"),
+        seconds => format!("{} min", seconds / 60),
+    }
+}
+
+fn format_average(stat: Stat, unit: &str) -> String {
+    stat.average
+        .map_or_else(|| "—".to_string(), |average| format!("{average:.0} {unit}"))
+}
+
+/// A bar split by the time spent in each zone, from the coolest to the
+/// hottest, detailed in its tooltip.
+fn zone_bar(zones: &Zones, times: &[f64]) -> gtk::Widget {
+    let total = times.iter().sum::<f64>();
+    if total <= 0.0 {
+        return gtk::Label::builder()
+            .label("—")
+            .xalign(0.0)
+            .build()
+            .upcast();
+    }
+    let tooltip = zones
+        .iter()
+        .zip(times)
+        .enumerate()
+        .filter(|(_, (_, time))| **time > 0.0)
+        .map(|(index, (zone, time))| {
+            format!("Z{} {} · {}", index + 1, zone.name, format_duration(*time))
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let bar = gtk::DrawingArea::builder()
+        .content_width(96)
+        .content_height(12)
+        .valign(gtk::Align::Center)
+        .tooltip_text(tooltip)
+        .build();
+    let times = times.to_vec();
+    bar.set_draw_func(move |_, context, width, height| {
+        let mut x = 0.0;
+        for (index, time) in times.iter().enumerate() {
+            let color = zone_color(index, times.len());
+            let width = time / total * f64::from(width);
+            context.set_source_rgba(
+                color.red().into(),
+                color.green().into(),
+                color.blue().into(),
+                color.alpha().into(),
+            );
+            context.rectangle(x, 0.0, width, f64::from(height));
+            let _ = context.fill();
+            x += width;
+        }
+    });
+    bar.upcast()
+}
+
+fn format_trigger(trigger: LapTrigger) -> &'static str {
+    match trigger {
+        LapTrigger::Manual => "Manual",
+        LapTrigger::Distance => "Distance",
+        LapTrigger::Time => "Time",
+        LapTrigger::SessionEnd => "Finish",
+    }
+}
+
+impl RidePage {
+    pub fn new() -> Self {
+        Object::builder().build()
+    }
+
+    /// Shows a ride from the history.
+    pub fn set_activity(&self, name: &str, activity: Activity) {
+        self.show(name, activity, true);
+    }
+
+    /// Shows a ride that was just finished, for the rider to save or discard.
+    pub fn set_finished(&self, activity: Activity) {
+        let name = history::default_name(activity.start);
+        self.show(&name, activity, false);
+    }
+
+    /// Called once the finished ride is in the history.
+    pub fn connect_saved<F: Fn(&Self) + 'static>(&self, callback: F) -> SignalHandlerId {
+        self.connect_local("saved", false, move |values| {
+            let page = values[0].get::<Self>().expect("the sender is a ride page");
+            callback(&page);
+            None
+        })
+    }
+
+    /// Called once the rider threw the finished ride away.
+    pub fn connect_discarded<F: Fn(&Self) + 'static>(&self, callback: F) -> SignalHandlerId {
+        self.connect_local("discarded", false, move |values| {
+            let page = values[0].get::<Self>().expect("the sender is a ride page");
+            callback(&page);
+            None
+        })
+    }
+
+    fn show(&self, name: &str, activity: Activity, saved: bool) {
+        let imp = self.imp();
+        self.set_title(name);
+        imp.name_row.set_text(name);
+        imp.name_row.set_visible(!saved);
+        imp.save_bar.set_visible(!saved);
+        for (group, row) in imp.rows.take() {
+            group.remove(&row);
+        }
+        // The zones as they were, as far as the saved FTP tells.
+        let zones = history::zone_settings(activity.ftp);
+        self.show_totals(&activity);
+        self.show_power(&activity);
+        self.show_laps(&activity, &zones);
+
+        let records = &activity.records;
+        let time_in_zones = TimeInZones::new(records, &zones);
+        self.show_zones(
+            &imp.power_zones_group,
+            &zones.power_zones(),
+            &time_in_zones.power,
+        );
+        self.show_zones(
+            &imp.heart_rate_zones_group,
+            &zones.heart_rate_zones(),
+            &time_in_zones.heart_rate,
+        );
+
+        imp.power_chart_group
+            .set_visible(records.iter().any(|record| record.power.is_some()));
+        imp.power_chart
+            .set_power_bands(PowerBand::from_zones(&zones.power_zones()));
+        imp.power_chart.set_records(records);
+        imp.heart_rate_chart_group
+            .set_visible(records.iter().any(|record| record.heart_rate.is_some()));
+        imp.heart_rate_chart.set_records(records);
+        imp.activity.replace(Some(activity));
+    }
+
+    fn add_row(&self, group: &adw::PreferencesGroup, row: &adw::ActionRow) {
+        group.add(row);
+        self.imp()
+            .rows
+            .borrow_mut()
+            .push((group.clone(), row.clone()));
+    }
+
+    fn show_totals(&self, activity: &Activity) {
+        let imp = self.imp();
+        let totals = &activity.totals;
+        imp.start_row.set_subtitle(&format_start(activity.start));
+        imp.elapsed_row
+            .set_subtitle(&format_duration(totals.elapsed));
+        imp.moving_time_row
+            .set_subtitle(&format_duration(totals.moving_time));
+        imp.distance_row
+            .set_subtitle(&format!("{:.2} km", totals.distance / 1000.0));
+        imp.energy_row.set_subtitle(&format!(
+            "{:.0} kJ · {:.0} kcal",
+            totals.kilojoules(),
+            totals.calories()
+        ));
+    }
+
+    fn show_power(&self, activity: &Activity) {
+        let imp = self.imp();
+        let summary = activity.power_summary();
+        imp.power_group.set_visible(summary.is_some());
+        if let Some(summary) = summary {
+            imp.average_power_row.set_subtitle(&format!(
+                "{:.0} W · max {:.0} W",
+                summary.average, summary.maximum
+            ));
+            imp.normalized_power_row
+                .set_subtitle(&match summary.normalized {
+                    Some(normalized) => format!(
+                        "{normalized:.0} W · VI {:.2}",
+                        summary.variability_index.unwrap_or_default()
+                    ),
+                    None => "—".to_string(),
+                });
+            imp.training_load_row.set_subtitle(&match (
+                summary.intensity_factor,
+                summary.training_stress_score,
+            ) {
+                (Some(intensity_factor), Some(stress)) => {
+                    format!("IF {intensity_factor:.2} · TSS {stress:.0}")
+                }
+                _ => "—".to_string(),
+            });
+        }
+
+        let group = &imp.best_efforts_group;
+        let efforts = BEST_EFFORTS
+            .iter()
+            .filter_map(|seconds| Some((*seconds, best_power(&activity.records, *seconds)?)))
+            .collect::<Vec<_>>();
+        group.set_visible(!efforts.is_empty());
+        for (seconds, power) in efforts {
+            let row = adw::ActionRow::builder()
+                .title(format_effort(seconds))
+                .subtitle(match activity.ftp {
+                    Some(ftp) => format!("{power:.0} W · {:.0} % FTP", power / ftp * 100.0),
+                    None => format!("{power:.0} W"),
+                })
+                .css_classes(["property"])
+                .build();
+            self.add_row(group, &row);
+        }
+    }
+
+    fn show_laps(&self, activity: &Activity, zones: &ZoneSettings) {
+        let grid = &self.imp().lap_grid;
+        while let Some(child) = grid.first_child() {
+            grid.remove(&child);
+        }
+        let header = [
+            "Lap",
+            "Time",
+            "Distance",
+            "Power",
+            "Max Power",
+            "Heart Rate",
+            "Cadence",
+            "Speed",
+            "Power Zones",
+            "Heart Rate Zones",
+            "Ended By",
+        ];
+        for (column, title) in header.into_iter().enumerate() {
+            let label = gtk::Label::builder()
+                .label(title)
+                .xalign(0.0)
+                .css_classes(["caption-heading", "dim-label"])
+                .build();
+            grid.attach(&label, column as i32, 0, 1, 1);
+        }
+        let (power_zones, heart_rate_zones) = (zones.power_zones(), zones.heart_rate_zones());
+        for (index, lap) in activity.laps.iter().enumerate() {
+            let numbers = [
+                (index + 1).to_string(),
+                format_duration(lap.totals.elapsed),
+                format!("{:.2} km", lap.totals.distance / 1000.0),
+                format_average(lap.power, "W"),
+                lap.power
+                    .maximum
+                    .map_or_else(|| "—".to_string(), |maximum| format!("{maximum:.0} W")),
+                format_average(lap.heart_rate, "bpm"),
+                format_average(lap.cadence, "rpm"),
+                lap.speed
+                    .average
+                    .map_or_else(|| "—".to_string(), |speed| format!("{speed:.1} km/h")),
+            ];
+            let cells = numbers
+                .into_iter()
+                .map(|number| {
+                    gtk::Label::builder()
+                        .label(number)
+                        .xalign(0.0)
+                        .css_classes(["numeric"])
+                        .build()
+                        .upcast()
+                })
+                .chain([
+                    zone_bar(&power_zones, &lap.time_in_zones.power),
+                    zone_bar(&heart_rate_zones, &lap.time_in_zones.heart_rate),
+                    gtk::Label::builder()
+                        .label(format_trigger(lap.trigger))
+                        .xalign(0.0)
+                        .build()
+                        .upcast(),
+                ]);
+            for (column, cell) in cells.enumerate() {
+                grid.attach(&cell, column as i32, index as i32 + 1, 1, 1);
+            }
+        }
+    }
+
+    fn show_zones(&self, group: &adw::PreferencesGroup, zones: &Zones, times: &[f64]) {
+        let total = times.iter().sum::<f64>();
+        group.set_visible(total > 0.0);
+        for (index, (zone, time)) in zones.iter().zip(times).enumerate() {
+            let share = time / total.max(1.0);
+            let row = adw::ActionRow::builder()
+                .title(format!("Z{} {}", index + 1, zone.name))
+                .subtitle(format!(
+                    "{} · {:.0} %",
+                    format_duration(*time),
+                    share * 100.0
+                ))
+                .build();
+            row.add_suffix(
+                &gtk::LevelBar::builder()
+                    .value(share)
+                    .width_request(160)
+                    .valign(gtk::Align::Center)
+                    .build(),
+            );
+            self.add_row(group, &row);
+        }
+    }
+
+    fn export(&self, format: Format) {
+        let Some(activity) = self.imp().activity.borrow().clone() else {
+            return;
+        };
+        export::save_as(
+            self.root().and_downcast_ref::<gtk::Window>(),
+            activity,
+            format,
+        );
+    }
+
+    fn save(&self) {
+        let imp = self.imp();
+        let Some(activity) = imp.activity.borrow().clone() else {
+            return;
+        };
+        let name = imp.name_row.text();
+        let name = match name.trim() {
+            "" => history::default_name(activity.start),
+            name => name.to_string(),
+        };
+        match history::save(&activity, &name) {
+            Ok(saved) => {
+                log::info!("Ride saved to {}", saved.path.display());
+                self.emit_by_name::<()>("saved", &[]);
+            }
+            Err(error) => log::error!("Could not save the ride. {error}"),
+        }
+    }
+
+    fn discard(&self) {
+        let dialog = adw::AlertDialog::new(
+            Some("Discard Ride?"),
+            Some("The ride will be gone for good, unless it was exported."),
+        );
+        dialog.add_responses(&[("cancel", "Cancel"), ("discard", "Discard")]);
+        dialog.set_response_appearance("discard", adw::ResponseAppearance::Destructive);
+        dialog.set_close_response("cancel");
+        dialog.choose(
+            self,
+            None::<&Cancellable>,
+            clone!(
+                #[weak(rename_to = slf)]
+                self,
+                move |response| {
+                    if response == "discard" {
+                        slf.emit_by_name::<()>("discarded", &[]);
+                    }
+                }
+            ),
+        );
+    }
+}
+
+impl Default for RidePage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
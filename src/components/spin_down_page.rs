@@ -0,0 +1,175 @@
+use adw::subclass::prelude::*;
+use gtk::glib;
+
+mod imp {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+
+    use adw::prelude::ObjectExt;
+    use gtk::{
+        CompositeTemplate,
+        glib::{Properties, clone, subclass::InitializingObject},
+        prelude::{ButtonExt, WidgetExt},
+    };
+
+    use crate::{
+        bluetooth::Device,
+        protocol::{Measurement, SpinDownEvent},
+    };
+
+    #[derive(Default, CompositeTemplate, Properties)]
+    #[properties(wrapper_type = super::SpinDownPage)]
+    #[template(resource = "/io/github/andreibachim/bike/ui/spin_down_page.ui")]
+    pub struct SpinDownPagePrivate {
+        #[template_child]
+        status_page: TemplateChild<adw::StatusPage>,
+        #[template_child]
+        speed_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        action_button: TemplateChild<gtk::Button>,
+        #[property(name = "device", get, set, nullable)]
+        device: RefCell<Option<Device>>,
+        finished: Cell<bool>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for SpinDownPagePrivate {
+        const NAME: &'static str = "SpinDownPage";
+        type Type = super::SpinDownPage;
+        type ParentType = adw::NavigationPage;
+        fn class_init(klass: &mut Self::Class) {
+            klass.bind_template();
+            klass.bind_template_callbacks();
+        }
+        fn instance_init(obj: &InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    #[gtk::template_callbacks]
+    impl SpinDownPagePrivate {
+        #[template_callback]
+        fn showing(slf: super::SpinDownPage) {
+            slf.imp().show_introduction();
+        }
+
+        #[template_callback]
+        fn hiding(slf: super::SpinDownPage) {
+            if let Some(device) = slf.device() {
+                device.stop_spin_down();
+            }
+        }
+
+        #[template_callback]
+        fn action(slf: super::SpinDownPage) {
+            let imp = slf.imp();
+            if imp.finished.get() {
+                let _ = slf.activate_action("navigation.pop", None);
+                return;
+            }
+            let Some(device) = slf.device() else {
+                return;
+            };
+            imp.show_step(
+                "emblem-synchronizing-symbolic",
+                "Starting Calibration",
+                "Waiting for the trainer to respond…",
+            );
+            imp.action_button.set_visible(false);
+            device.start_spin_down(
+                clone!(
+                    #[weak]
+                    imp,
+                    move |event| imp.handle_event(event)
+                ),
+                clone!(
+                    #[weak]
+                    imp,
+                    move |measurement| imp.update_speed(measurement)
+                ),
+            );
+        }
+
+        fn show_step(&self, icon_name: &str, title: &str, description: &str) {
+            self.status_page.set_icon_name(Some(icon_name));
+            self.status_page.set_title(title);
+            self.status_page.set_description(Some(description));
+        }
+
+        fn show_introduction(&self) {
+            self.finished.set(false);
+            self.speed_label.set_visible(false);
+            self.action_button.set_visible(true);
+            self.action_button.set_label("Start");
+            self.show_step(
+                "emblem-system-symbolic",
+                "Spin Down Calibration",
+                "Warm up for about ten minutes first, so the tyre and the trainer are at their \
+                 riding temperature. Start when you are pedalling easily.",
+            );
+        }
+
+        fn handle_event(&self, event: SpinDownEvent) {
+            log::debug!("Spin down progress: {event:?}");
+            match event {
+                SpinDownEvent::TargetSpeed { low, high } => {
+                    self.speed_label.set_visible(true);
+                    self.show_step(
+                        "go-up-symbolic",
+                        "Speed Up",
+                        &format!("Pedal until you reach between {low:.0} and {high:.0} km/h."),
+                    );
+                }
+                SpinDownEvent::StopPedalling => self.show_step(
+                    "media-playback-pause-symbolic",
+                    "Stop Pedalling",
+                    "Let the wheel coast to a stop without touching the pedals.",
+                ),
+                SpinDownEvent::Succeeded => {
+                    self.finish();
+                    self.show_step(
+                        "emblem-ok-symbolic",
+                        "Calibration Complete",
+                        "The trainer is calibrated and ready to ride.",
+                    );
+                    self.action_button.set_label("Done");
+                    self.finished.set(true);
+                }
+                SpinDownEvent::Failed(failure) => {
+                    self.finish();
+                    self.show_step(
+                        "dialog-warning-symbolic",
+                        "Calibration Failed",
+                        &failure.to_string(),
+                    );
+                    self.action_button.set_label("Try Again");
+                }
+            }
+        }
+
+        fn finish(&self) {
+            if let Some(device) = self.device.borrow().as_ref() {
+                device.stop_spin_down();
+            }
+            self.speed_label.set_visible(false);
+            self.action_button.set_visible(true);
+        }
+
+        fn update_speed(&self, measurement: Measurement) {
+            if let Some(speed) = measurement.speed {
+                self.speed_label.set_label(&format!("{speed:.1} km/h"));
+            }
+        }
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for SpinDownPagePrivate {}
+    impl WidgetImpl for SpinDownPagePrivate {}
+    impl NavigationPageImpl for SpinDownPagePrivate {}
+}
+
+glib::wrapper! {
+    pub struct SpinDownPage(ObjectSubclass<imp::SpinDownPagePrivate>)
+        @extends adw::NavigationPage, gtk::Widget,
+        @implements gtk::Accessible, gtk::Buildable, gtk::ConstraintTarget;
+}
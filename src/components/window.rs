@@ -1,11 +1,18 @@
-use adw::subclass::prelude::ObjectSubclassIsExt;
-use gtk::glib::Object;
+use adw::{prelude::AdwDialogExt, subclass::prelude::ObjectSubclassIsExt};
+use gtk::glib::{self, Object, clone};
+
+use crate::components::RidePage;
 
 mod imp {
-    use crate::{BLUETOOTH, components::BluetoothButton};
-    use adw::subclass::prelude::*;
+    use crate::{
+        BLUETOOTH,
+        components::{BluetoothButton, Dashboard, HistoryDialog, ZonesDialog},
+        session::Ride,
+    };
+    use adw::{prelude::AdwDialogExt, subclass::prelude::*};
     use gtk::{
         CompositeTemplate,
+        gdk::{Key, ModifierType},
         glib::{self, subclass::InitializingObject},
         subclass::{prelude::ApplicationWindowImpl, widget::WidgetImpl, window::WindowImpl},
     };
@@ -17,6 +24,9 @@ mod imp {
         missing_bluetooth_banner: TemplateChild<adw::Banner>,
         #[template_child]
         pub bluetooth_button: TemplateChild<BluetoothButton>,
+        #[template_child]
+        dashboard: TemplateChild<Dashboard>,
+        pub ride: Ride,
     }
 
     #[glib::object_subclass]
@@ -26,6 +36,25 @@ mod imp {
         type ParentType = adw::ApplicationWindow;
         fn class_init(klass: &mut Self::Class) {
             klass.bind_template();
+            klass.install_action("ride.start", None, |window, _, _| {
+                window.imp().ride.start();
+            });
+            klass.install_action("ride.pause", None, |window, _, _| {
+                window.imp().ride.toggle_pause();
+            });
+            klass.install_action("ride.lap", None, |window, _, _| {
+                window.imp().ride.new_lap();
+            });
+            klass.add_binding_action(Key::l, ModifierType::CONTROL_MASK, "ride.lap");
+            klass.install_action("ride.preferences", None, |window, _, _| {
+                ZonesDialog::new(&window.imp().ride).present(Some(window));
+            });
+            klass.install_action("ride.history", None, |window, _, _| {
+                HistoryDialog::new().present(Some(window));
+            });
+            klass.install_action("ride.finish", None, |window, _, _| {
+                window.ride_finished();
+            });
         }
         fn instance_init(obj: &InitializingObject<Self>) {
             obj.init_template();
@@ -37,6 +66,7 @@ mod imp {
             self.parent_constructed();
             self.missing_bluetooth_banner
                 .set_revealed(!BLUETOOTH.is_valid());
+            self.dashboard.set_ride(&self.ride);
         }
     }
     impl WidgetImpl for WindowPrivate {}
@@ -57,6 +87,45 @@ impl Window {
         Object::builder().property("application", app).build()
     }
 
+    fn ride_finished(&self) {
+        let ride = &self.imp().ride;
+        ride.finish();
+        log::info!(
+            "Ride finished after {:.0} s with {} records, {:.0} m and {:.0} kJ",
+            ride.elapsed(),
+            ride.records().len(),
+            ride.distance(),
+            ride.kilojoules()
+        );
+        let Some(activity) = ride.activity() else {
+            return;
+        };
+        let page = RidePage::new();
+        page.set_finished(activity);
+        let navigation_view = adw::NavigationView::new();
+        navigation_view.add(&page);
+        // The rider has to either save or discard the ride.
+        let dialog = adw::Dialog::builder()
+            .child(&navigation_view)
+            .content_width(800)
+            .content_height(700)
+            .can_close(false)
+            .build();
+        let done = clone!(
+            #[weak(rename_to = window)]
+            self,
+            #[weak]
+            dialog,
+            move |_: &RidePage| {
+                dialog.force_close();
+                window.imp().ride.reset();
+            }
+        );
+        page.connect_saved(done.clone());
+        page.connect_discarded(done);
+        dialog.present(Some(self));
+    }
+
     pub fn set_connected(&self) {
         self.imp().bluetooth_button.set_connected();
     }
@@ -0,0 +1,306 @@
+mod imp {
+    use std::cell::RefCell;
+
+    use adw::glib::subclass::InitializingObject;
+    use adw::subclass::prelude::*;
+    use gtk::{CompositeTemplate, glib, subclass::widget::WidgetImpl};
+
+    use crate::session::Ride;
+
+    use super::ZonesDialog;
+
+    #[derive(CompositeTemplate, Default)]
+    #[template(resource = "/io/github/andreibachim/bike/ui/zones_dialog.ui")]
+    pub struct ZonesDialogPrivate {
+        #[template_child]
+        pub ftp_row: TemplateChild<adw::SpinRow>,
+        #[template_child]
+        pub max_heart_rate_row: TemplateChild<adw::SpinRow>,
+        #[template_child]
+        pub threshold_heart_rate_row: TemplateChild<adw::SpinRow>,
+        #[template_child]
+        pub power_group: TemplateChild<adw::PreferencesGroup>,
+        #[template_child]
+        pub power_model_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        pub custom_power_row: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub heart_rate_group: TemplateChild<adw::PreferencesGroup>,
+        #[template_child]
+        pub heart_rate_model_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        pub custom_heart_rate_row: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub auto_lap_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        pub auto_lap_distance_row: TemplateChild<adw::SpinRow>,
+        #[template_child]
+        pub auto_lap_time_row: TemplateChild<adw::SpinRow>,
+        #[template_child]
+        pub speed_source_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        pub rider_mass_row: TemplateChild<adw::SpinRow>,
+        #[template_child]
+        pub bike_mass_row: TemplateChild<adw::SpinRow>,
+        #[template_child]
+        pub cda_row: TemplateChild<adw::SpinRow>,
+        #[template_child]
+        pub crr_row: TemplateChild<adw::SpinRow>,
+        #[template_child]
+        pub drivetrain_loss_row: TemplateChild<adw::SpinRow>,
+        #[template_child]
+        pub air_density_row: TemplateChild<adw::SpinRow>,
+        pub ride: RefCell<Option<Ride>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for ZonesDialogPrivate {
+        const NAME: &str = "ZonesDialog";
+        type Type = super::ZonesDialog;
+        type ParentType = adw::PreferencesDialog;
+        fn class_init(klass: &mut Self::Class) {
+            klass.bind_template();
+            klass.bind_template_callbacks();
+        }
+        fn instance_init(obj: &InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    #[gtk::template_callbacks]
+    impl ZonesDialogPrivate {
+        #[template_callback]
+        fn refresh_zones(slf: ZonesDialog) {
+            slf.refresh_zones();
+        }
+
+        #[template_callback]
+        fn refresh_auto_lap(slf: ZonesDialog) {
+            slf.refresh_auto_lap();
+        }
+
+        #[template_callback]
+        fn save(slf: ZonesDialog) {
+            slf.save();
+        }
+    }
+
+    impl ObjectImpl for ZonesDialogPrivate {}
+    impl WidgetImpl for ZonesDialogPrivate {}
+    impl AdwDialogImpl for ZonesDialogPrivate {}
+    impl PreferencesDialogImpl for ZonesDialogPrivate {}
+}
+
+use adw::{
+    prelude::{ComboRowExt, EditableExt, PreferencesGroupExt},
+    subclass::prelude::ObjectSubclassIsExt,
+};
+use gtk::{glib::Object, prelude::WidgetExt};
+
+use crate::{
+    session::{AutoLap, Ride, RiderModel, SpeedSource, ZoneModel, ZoneSettings, Zones},
+    settings::Settings,
+};
+
+gtk::glib::wrapper! {
+    /// Where the rider sets the thresholds and zones bike works with, and
+    /// how it works out speed.
+    pub struct ZonesDialog(ObjectSubclass<imp::ZonesDialogPrivate>)
+        @extends adw::PreferencesDialog, adw::Dialog, gtk::Widget,
+        @implements gtk::Accessible, gtk::Buildable, gtk::ConstraintTarget, gtk::ShortcutManager;
+}
+
+fn model_index(model: ZoneModel) -> u32 {
+    match model {
+        ZoneModel::Standard => 0,
+        ZoneModel::Custom => 1,
+    }
+}
+
+fn model_from_index(index: u32) -> ZoneModel {
+    match index {
+        1 => ZoneModel::Custom,
+        _ => ZoneModel::Standard,
+    }
+}
+
+fn speed_source_index(speed_source: SpeedSource) -> u32 {
+    match speed_source {
+        SpeedSource::Auto => 0,
+        SpeedSource::Sensor => 1,
+        SpeedSource::Virtual => 2,
+    }
+}
+
+fn speed_source_from_index(index: u32) -> SpeedSource {
+    match index {
+        1 => SpeedSource::Sensor,
+        2 => SpeedSource::Virtual,
+        _ => SpeedSource::Auto,
+    }
+}
+
+fn auto_lap_index(auto_lap: AutoLap) -> u32 {
+    match auto_lap {
+        AutoLap::Off => 0,
+        AutoLap::Distance(_) => 1,
+        AutoLap::Time(_) => 2,
+    }
+}
+
+fn format_boundaries(boundaries: &[f64]) -> String {
+    boundaries
+        .iter()
+        .map(|boundary| format!("{boundary:.0}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Reads boundaries typed by the rider, skipping whatever isn't a number.
+fn parse_boundaries(text: &str) -> Vec<f64> {
+    text.split([',', ';', ' '])
+        .filter_map(|boundary| boundary.trim().parse().ok())
+        .collect()
+}
+
+/// Lists the zones, like "Z1 Recovery 0–120 bpm".
+fn describe(zones: &Zones, unit: &str) -> String {
+    if zones.is_empty() {
+        return format!("Set the thresholds above, or custom boundaries in {unit}.");
+    }
+    zones
+        .iter()
+        .enumerate()
+        .map(|(index, zone)| {
+            let range = if zone.to.is_finite() {
+                format!("{:.0}–{:.0}", zone.from, zone.to - 1.0)
+            } else {
+                format!("{:.0}+", zone.from)
+            };
+            format!("Z{} {} {range} {unit}", index + 1, zone.name)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl ZonesDialog {
+    pub fn new(ride: &Ride) -> Self {
+        let slf: Self = Object::builder().build();
+        let imp = slf.imp();
+        let zones = ride.zone_settings();
+        imp.ftp_row.set_value(zones.ftp.unwrap_or_default());
+        imp.max_heart_rate_row
+            .set_value(zones.max_heart_rate.unwrap_or_default());
+        imp.threshold_heart_rate_row
+            .set_value(zones.threshold_heart_rate.unwrap_or_default());
+        imp.power_model_row
+            .set_selected(model_index(zones.power_model));
+        imp.custom_power_row
+            .set_text(&format_boundaries(&zones.custom_power_zones));
+        imp.heart_rate_model_row
+            .set_selected(model_index(zones.heart_rate_model));
+        imp.custom_heart_rate_row
+            .set_text(&format_boundaries(&zones.custom_heart_rate_zones));
+        let settings = Settings::load();
+        imp.speed_source_row
+            .set_selected(speed_source_index(settings.speed_source()));
+        let model = settings.rider_model();
+        imp.rider_mass_row.set_value(model.rider_mass);
+        imp.bike_mass_row.set_value(model.bike_mass);
+        imp.cda_row.set_value(model.cda);
+        imp.crr_row.set_value(model.crr);
+        imp.drivetrain_loss_row
+            .set_value(model.drivetrain_loss * 100.0);
+        imp.air_density_row.set_value(model.air_density);
+        let auto_lap = settings.auto_lap();
+        imp.auto_lap_row.set_selected(auto_lap_index(auto_lap));
+        match auto_lap {
+            AutoLap::Distance(distance) => imp.auto_lap_distance_row.set_value(distance / 1000.0),
+            AutoLap::Time(time) => imp.auto_lap_time_row.set_value(time / 60.0),
+            AutoLap::Off => (),
+        }
+        slf.refresh_auto_lap();
+        imp.ride.replace(Some(ride.clone()));
+        slf.refresh_zones();
+        slf
+    }
+
+    fn zone_settings(&self) -> ZoneSettings {
+        let imp = self.imp();
+        let optional = |row: &adw::SpinRow| Some(row.value()).filter(|value| *value > 0.0);
+        ZoneSettings {
+            ftp: optional(&imp.ftp_row),
+            max_heart_rate: optional(&imp.max_heart_rate_row),
+            threshold_heart_rate: optional(&imp.threshold_heart_rate_row),
+            power_model: model_from_index(imp.power_model_row.selected()),
+            custom_power_zones: parse_boundaries(&imp.custom_power_row.text()),
+            heart_rate_model: model_from_index(imp.heart_rate_model_row.selected()),
+            custom_heart_rate_zones: parse_boundaries(&imp.custom_heart_rate_row.text()),
+        }
+    }
+
+    fn rider_model(&self) -> RiderModel {
+        let imp = self.imp();
+        RiderModel {
+            rider_mass: imp.rider_mass_row.value(),
+            bike_mass: imp.bike_mass_row.value(),
+            cda: imp.cda_row.value(),
+            crr: imp.crr_row.value(),
+            drivetrain_loss: imp.drivetrain_loss_row.value() / 100.0,
+            air_density: imp.air_density_row.value(),
+            ..Default::default()
+        }
+    }
+
+    fn auto_lap(&self) -> AutoLap {
+        let imp = self.imp();
+        match imp.auto_lap_row.selected() {
+            1 => AutoLap::Distance(imp.auto_lap_distance_row.value() * 1000.0),
+            2 => AutoLap::Time(imp.auto_lap_time_row.value() * 60.0),
+            _ => AutoLap::Off,
+        }
+    }
+
+    fn refresh_auto_lap(&self) {
+        let imp = self.imp();
+        let auto_lap = self.auto_lap();
+        imp.auto_lap_distance_row
+            .set_visible(matches!(auto_lap, AutoLap::Distance(_)));
+        imp.auto_lap_time_row
+            .set_visible(matches!(auto_lap, AutoLap::Time(_)));
+    }
+
+    fn refresh_zones(&self) {
+        let imp = self.imp();
+        let zones = self.zone_settings();
+        imp.custom_power_row
+            .set_visible(zones.power_model == ZoneModel::Custom);
+        imp.custom_heart_rate_row
+            .set_visible(zones.heart_rate_model == ZoneModel::Custom);
+        imp.power_group
+            .set_description(Some(&describe(&zones.power_zones(), "W")));
+        imp.heart_rate_group
+            .set_description(Some(&describe(&zones.heart_rate_zones(), "bpm")));
+    }
+
+    fn save(&self) {
+        let zones = self.zone_settings();
+        let model = self.rider_model();
+        let speed_source = speed_source_from_index(self.imp().speed_source_row.selected());
+        let auto_lap = self.auto_lap();
+        let settings = Settings::load();
+        settings.set_zone_settings(&zones);
+        settings.set_rider_model(&model);
+        settings.set_speed_source(speed_source);
+        settings.set_auto_lap(auto_lap);
+        if let Err(error) = settings.save() {
+            log::error!("Could not save the preferences. {error}");
+        }
+        if let Some(ride) = self.imp().ride.borrow().as_ref() {
+            ride.set_zone_settings(zones);
+            ride.set_rider_model(model);
+            ride.set_speed_source(speed_source);
+            ride.set_auto_lap(auto_lap);
+        }
+    }
+}
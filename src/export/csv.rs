@@ -0,0 +1,80 @@
+//! One line per recorded second, for spreadsheets and quick scripts.
+
+use std::fmt::{self, Write};
+
+use super::time;
+use crate::session::{Activity, Record};
+
+const HEADER: &str =
+    "time,elapsed_s,lap,power_w,cadence_rpm,heart_rate_bpm,speed_kmh,distance_m,target_power_w\n";
+
+/// Missing values are left empty.
+fn optional<T: fmt::Display>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+fn record(csv: &mut String, record: &Record, lap: usize) -> fmt::Result {
+    writeln!(
+        csv,
+        "{},{:.0},{lap},{},{},{},{},{:.2},{}",
+        time(record.timestamp),
+        record.elapsed,
+        optional(record.power),
+        optional(record.cadence.map(|cadence| format!("{cadence:.0}"))),
+        optional(record.heart_rate),
+        optional(record.speed.map(|speed| format!("{speed:.2}"))),
+        record.distance,
+        optional(record.target_power),
+    )
+}
+
+/// Encodes every record of `activity`, with the lap it belongs to.
+pub fn encode(activity: &Activity) -> Vec<u8> {
+    let mut csv = String::from(HEADER);
+    for record in &activity.records {
+        // Laps start right after the last record of the one before.
+        let lap = activity
+            .laps
+            .partition_point(|lap| lap.start_elapsed < record.elapsed)
+            .max(1);
+        self::record(&mut csv, record, lap).expect("writing to a string never fails");
+    }
+    csv.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::tests::two_laps;
+
+    #[test]
+    fn one_line_per_record() {
+        let csv = String::from_utf8(encode(&two_laps())).unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(format!("{}\n", lines[0]), HEADER);
+        assert_eq!(
+            lines[1],
+            "2023-11-14T22:13:21Z,1,1,100,81,121,30.00,8.50,150"
+        );
+        // Missing values are left empty.
+        assert_eq!(lines[5], "2023-11-14T22:13:25Z,5,2,,85,125,30.00,42.50,");
+    }
+
+    #[test]
+    fn lap_column() {
+        let mut activity = two_laps();
+        let laps = |activity: &Activity| {
+            String::from_utf8(encode(activity))
+                .unwrap()
+                .lines()
+                .skip(1)
+                .map(|line| line.split(',').nth(2).unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        // The last record of a lap is right at its end.
+        assert_eq!(laps(&activity), ["1", "1", "1", "2", "2", "2"]);
+        activity.laps.clear();
+        assert_eq!(laps(&activity), ["1"; 6]);
+    }
+}
@@ -0,0 +1,490 @@
+//! Garmin FIT activity files, see the FIT SDK for the message profiles.
+
+use crate::session::{Activity, Lap, LapTrigger, Record, SensorKind, Stat, Totals};
+
+/// Seconds between the Unix epoch and the FIT one, 1989-12-31 00:00 UTC.
+const FIT_EPOCH: i64 = 631_065_600;
+const PROTOCOL_VERSION: u8 = 0x20;
+const PROFILE_VERSION: u16 = 2132;
+const HEADER_SIZE: u8 = 14;
+
+/// Manufacturer id for apps without one of their own.
+const MANUFACTURER_DEVELOPMENT: u16 = 255;
+const SPORT_CYCLING: u8 = 2;
+const SUB_SPORT_INDOOR_CYCLING: u8 = 6;
+
+mod message {
+    pub const FILE_ID: u16 = 0;
+    pub const SESSION: u16 = 18;
+    pub const LAP: u16 = 19;
+    pub const RECORD: u16 = 20;
+    pub const EVENT: u16 = 21;
+    pub const DEVICE_INFO: u16 = 23;
+    pub const ACTIVITY: u16 = 34;
+}
+
+mod field {
+    pub const TIMESTAMP: u8 = 253;
+    pub const MESSAGE_INDEX: u8 = 254;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Enum(u8),
+    U8(Option<u8>),
+    U16(Option<u16>),
+    U32(Option<u32>),
+    U32z(u32),
+    String(String),
+}
+
+impl Value {
+    fn base_type(&self) -> u8 {
+        match self {
+            Value::Enum(_) => 0x00,
+            Value::U8(_) => 0x02,
+            Value::U16(_) => 0x84,
+            Value::U32(_) => 0x86,
+            Value::U32z(_) => 0x8c,
+            Value::String(_) => 0x07,
+        }
+    }
+
+    fn size(&self) -> u8 {
+        match self {
+            Value::Enum(_) | Value::U8(_) => 1,
+            Value::U16(_) => 2,
+            Value::U32(_) | Value::U32z(_) => 4,
+            // Null terminated, and no longer than a field can be.
+            Value::String(string) => (string.len().min(254) + 1) as u8,
+        }
+    }
+
+    /// Missing values are written as the invalid value of their type.
+    fn write(&self, data: &mut Vec<u8>) {
+        match self {
+            Value::Enum(value) => data.push(*value),
+            Value::U8(value) => data.push(value.unwrap_or(u8::MAX)),
+            Value::U16(value) => data.extend(value.unwrap_or(u16::MAX).to_le_bytes()),
+            Value::U32(value) => data.extend(value.unwrap_or(u32::MAX).to_le_bytes()),
+            Value::U32z(value) => data.extend(value.to_le_bytes()),
+            Value::String(string) => {
+                let bytes = &string.as_bytes()[..usize::from(self.size()) - 1];
+                data.extend(bytes);
+                data.push(0);
+            }
+        }
+    }
+}
+
+fn timestamp(unix: i64) -> Value {
+    Value::U32(u32::try_from(unix - FIT_EPOCH).ok())
+}
+
+/// Scales a value into an integer field, `None` when it doesn't fit.
+fn scaled<T: TryFrom<i64>>(value: Option<f64>, scale: f64) -> Option<T> {
+    value
+        .filter(|value| value.is_finite() && *value >= 0.0)
+        .and_then(|value| T::try_from((value * scale).round() as i64).ok())
+}
+
+fn u8_field(value: Option<f64>) -> Value {
+    Value::U8(scaled(value, 1.0))
+}
+
+fn u16_field(value: Option<f64>, scale: f64) -> Value {
+    Value::U16(scaled(value, scale))
+}
+
+fn u32_field(value: f64, scale: f64) -> Value {
+    Value::U32(scaled(Some(value), scale))
+}
+
+/// km/h to the m/s × 1000 FIT stores.
+fn speed_field(speed: Option<f64>) -> Value {
+    u16_field(speed.map(|speed| speed / 3.6), 1000.0)
+}
+
+/// The CRC FIT uses for the header and the file.
+fn crc(data: &[u8]) -> u16 {
+    const TABLE: [u16; 16] = [
+        0x0000, 0xcc01, 0xd801, 0x1400, 0xf001, 0x3c00, 0x2800, 0xe401, 0xa001, 0x6c00, 0x7800,
+        0xb401, 0x5000, 0x9c01, 0x8801, 0x4400,
+    ];
+    data.iter().fold(0, |crc, byte| {
+        let crc =
+            (crc >> 4) & 0x0fff ^ TABLE[usize::from(crc & 0xf)] ^ TABLE[usize::from(byte & 0xf)];
+        (crc >> 4) & 0x0fff ^ TABLE[usize::from(crc & 0xf)] ^ TABLE[usize::from(byte >> 4)]
+    })
+}
+
+/// The number, size and base type of each field of a message.
+type Layout = Vec<(u8, u8, u8)>;
+
+/// Writes messages, defining them again only when their layout changes.
+#[derive(Default)]
+struct Writer {
+    data: Vec<u8>,
+    /// The layout local message 0 currently has.
+    definition: Option<(u16, Layout)>,
+}
+
+impl Writer {
+    fn message(&mut self, global: u16, fields: &[(u8, Value)]) {
+        let layout = fields
+            .iter()
+            .map(|(number, value)| (*number, value.size(), value.base_type()))
+            .collect::<Layout>();
+        if self.definition.as_ref() != Some(&(global, layout.clone())) {
+            // Definition header for local message 0, little endian.
+            self.data.extend([0x40, 0, 0]);
+            self.data.extend(global.to_le_bytes());
+            self.data.push(layout.len() as u8);
+            for (number, size, base_type) in &layout {
+                self.data.extend([*number, *size, *base_type]);
+            }
+            self.definition = Some((global, layout));
+        }
+        self.data.push(0);
+        for (_, value) in fields {
+            value.write(&mut self.data);
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let mut file = Vec::with_capacity(usize::from(HEADER_SIZE) + self.data.len() + 2);
+        file.extend([HEADER_SIZE, PROTOCOL_VERSION]);
+        file.extend(PROFILE_VERSION.to_le_bytes());
+        file.extend((self.data.len() as u32).to_le_bytes());
+        file.extend(b".FIT");
+        file.extend(crc(&file).to_le_bytes());
+        file.extend(self.data);
+        file.extend(crc(&file).to_le_bytes());
+        file
+    }
+}
+
+fn device_type(kind: SensorKind) -> u8 {
+    // ANT+ device types.
+    match kind {
+        SensorKind::BikePower => 11,
+        SensorKind::FitnessEquipment => 17,
+        SensorKind::HeartRate => 120,
+    }
+}
+
+fn lap_trigger(trigger: LapTrigger) -> u8 {
+    match trigger {
+        LapTrigger::Manual => 0,
+        LapTrigger::Time => 1,
+        LapTrigger::Distance => 2,
+        LapTrigger::SessionEnd => 7,
+    }
+}
+
+fn record(record: &Record) -> Vec<(u8, Value)> {
+    vec![
+        (field::TIMESTAMP, timestamp(record.timestamp)),
+        (3, u8_field(record.heart_rate.map(f64::from))),
+        (4, u8_field(record.cadence)),
+        (5, u32_field(record.distance, 100.0)),
+        (6, speed_field(record.speed)),
+        (7, u16_field(record.power.map(f64::from), 1.0)),
+    ]
+}
+
+/// The fields laps and sessions share, under different numbers. `numbers`
+/// lists them for elapsed time, timer time, distance, calories, average and
+/// maximum speed, heart rate, cadence and power, then work.
+fn summary(
+    numbers: [u8; 12],
+    totals: &Totals,
+    speed: Stat,
+    heart_rate: Stat,
+    cadence: Stat,
+    power: Stat,
+) -> Vec<(u8, Value)> {
+    let values = [
+        u32_field(totals.elapsed, 1000.0),
+        u32_field(totals.moving_time, 1000.0),
+        u32_field(totals.distance, 100.0),
+        u16_field(Some(totals.calories()), 1.0),
+        speed_field(speed.average),
+        speed_field(speed.maximum),
+        u8_field(heart_rate.average),
+        u8_field(heart_rate.maximum),
+        u8_field(cadence.average),
+        u8_field(cadence.maximum),
+        u16_field(power.average, 1.0),
+        u16_field(power.maximum, 1.0),
+    ];
+    numbers.into_iter().zip(values).collect()
+}
+
+fn lap(index: usize, lap: &Lap) -> Vec<(u8, Value)> {
+    let end = lap.start_timestamp + lap.totals.elapsed.round() as i64;
+    let mut fields = vec![
+        (field::TIMESTAMP, timestamp(end)),
+        (field::MESSAGE_INDEX, Value::U16(u16::try_from(index).ok())),
+        (0, Value::Enum(9)), // Lap event.
+        (1, Value::Enum(1)), // Stop.
+        (2, timestamp(lap.start_timestamp)),
+    ];
+    fields.extend(summary(
+        [7, 8, 9, 11, 13, 14, 15, 16, 17, 18, 19, 20],
+        &lap.totals,
+        lap.speed,
+        lap.heart_rate,
+        lap.cadence,
+        lap.power,
+    ));
+    fields.extend([
+        (24, Value::Enum(lap_trigger(lap.trigger))),
+        (25, Value::Enum(SPORT_CYCLING)),
+        (41, u32_field(lap.totals.work, 1.0)),
+    ]);
+    fields
+}
+
+fn session(activity: &Activity) -> Vec<(u8, Value)> {
+    let records = &activity.records;
+    let power = activity.power_summary().unwrap_or_default();
+    let mut fields = vec![
+        (field::TIMESTAMP, timestamp(activity.end())),
+        (0, Value::Enum(8)), // Session event.
+        (1, Value::Enum(1)), // Stop.
+        (2, timestamp(activity.start)),
+        (5, Value::Enum(SPORT_CYCLING)),
+        (6, Value::Enum(SUB_SPORT_INDOOR_CYCLING)),
+    ];
+    fields.extend(summary(
+        [7, 8, 9, 11, 14, 15, 16, 17, 18, 19, 20, 21],
+        &activity.totals,
+        Stat::new(records, |record| record.speed),
+        Stat::new(records, |record| record.heart_rate.map(f64::from)),
+        Stat::new(records, |record| record.cadence),
+        Stat::new(records, |record| record.power.map(f64::from)),
+    ));
+    fields.extend([
+        (25, Value::U16(Some(0))),
+        (26, Value::U16(u16::try_from(activity.laps.len()).ok())),
+        (28, Value::Enum(0)), // Ended with the activity.
+        (34, u16_field(power.normalized, 1.0)),
+        (35, u16_field(power.training_stress_score, 10.0)),
+        (36, u16_field(power.intensity_factor, 1000.0)),
+        (45, u16_field(activity.ftp, 1.0)),
+        (48, u32_field(activity.totals.work, 1.0)),
+    ]);
+    fields
+}
+
+/// Encodes `activity` as a FIT activity file.
+pub fn encode(activity: &Activity) -> Vec<u8> {
+    let mut writer = Writer::default();
+    let start = timestamp(activity.start);
+    writer.message(
+        message::FILE_ID,
+        &[
+            (0, Value::Enum(4)), // Activity file.
+            (1, Value::U16(Some(MANUFACTURER_DEVELOPMENT))),
+            (2, Value::U16(Some(0))),
+            (3, Value::U32z(activity.start as u32)),
+            (4, start.clone()),
+        ],
+    );
+    // The app itself is the creator, device 0.
+    writer.message(
+        message::DEVICE_INFO,
+        &[
+            (field::TIMESTAMP, start.clone()),
+            (0, Value::U8(Some(0))),
+            (2, Value::U16(Some(MANUFACTURER_DEVELOPMENT))),
+            (27, Value::String("Bike".to_string())),
+        ],
+    );
+    for (index, sensor) in activity.sensors.iter().enumerate() {
+        let name = match sensor.brand.as_str() {
+            "" => sensor.name.clone(),
+            brand => format!("{brand} {}", sensor.name),
+        };
+        writer.message(
+            message::DEVICE_INFO,
+            &[
+                (field::TIMESTAMP, start.clone()),
+                (0, Value::U8(u8::try_from(index + 1).ok())),
+                (1, Value::U8(Some(device_type(sensor.kind)))),
+                (2, Value::U16(Some(MANUFACTURER_DEVELOPMENT))),
+                (27, Value::String(name)),
+            ],
+        );
+    }
+    writer.message(
+        message::EVENT,
+        &[
+            (field::TIMESTAMP, start),
+            (0, Value::Enum(0)), // Timer.
+            (1, Value::Enum(0)), // Start.
+        ],
+    );
+    for record in &activity.records {
+        writer.message(message::RECORD, &self::record(record));
+    }
+    writer.message(
+        message::EVENT,
+        &[
+            (field::TIMESTAMP, timestamp(activity.end())),
+            (0, Value::Enum(0)), // Timer.
+            (1, Value::Enum(4)), // Stop all.
+        ],
+    );
+    for (index, lap) in activity.laps.iter().enumerate() {
+        writer.message(message::LAP, &self::lap(index, lap));
+    }
+    writer.message(message::SESSION, &session(activity));
+    writer.message(
+        message::ACTIVITY,
+        &[
+            (field::TIMESTAMP, timestamp(activity.end())),
+            (0, u32_field(activity.totals.moving_time, 1000.0)),
+            (1, Value::U16(Some(1))),
+            (2, Value::Enum(0)),  // Manual.
+            (3, Value::Enum(26)), // Activity event.
+            (4, Value::Enum(1)),  // Stop.
+        ],
+    );
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::export::tests::{START, two_laps};
+
+    /// The data messages of a file, as their global number and fields.
+    fn messages(data: &[u8]) -> Vec<(u16, HashMap<u8, Vec<u8>>)> {
+        let mut messages = vec![];
+        let mut layout = (0, vec![]);
+        let mut position = 0;
+        while position < data.len() {
+            let header = data[position];
+            position += 1;
+            // Only local message 0 is ever used.
+            assert_eq!(header & 0x0f, 0);
+            if header == 0x40 {
+                let global = u16::from_le_bytes([data[position + 2], data[position + 3]]);
+                let count = usize::from(data[position + 4]);
+                position += 5;
+                let fields = data[position..position + count * 3]
+                    .chunks(3)
+                    .map(|field| (field[0], usize::from(field[1])))
+                    .collect::<Vec<_>>();
+                position += count * 3;
+                layout = (global, fields);
+            } else {
+                assert_eq!(header, 0);
+                let mut fields = HashMap::new();
+                for (number, size) in &layout.1 {
+                    fields.insert(*number, data[position..position + size].to_vec());
+                    position += size;
+                }
+                messages.push((layout.0, fields));
+            }
+        }
+        messages
+    }
+
+    fn u32_value(bytes: &[u8]) -> u32 {
+        u32::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    #[test]
+    fn crc_check_value() {
+        // The check value of CRC-16/ARC, which FIT uses.
+        assert_eq!(crc(b"123456789"), 0xbb3d);
+        assert_eq!(crc(&[]), 0);
+    }
+
+    #[test]
+    fn header_and_crc() {
+        let file = encode(&two_laps());
+        let size = u32_value(&file[4..8]) as usize;
+        assert_eq!(file.len(), usize::from(HEADER_SIZE) + size + 2);
+        assert_eq!(file[..4], [14, 0x20, 0x54, 0x08]);
+        assert_eq!(&file[8..12], b".FIT");
+        assert_eq!(file[12..14], crc(&file[..12]).to_le_bytes());
+        let (content, trailer) = file.split_at(file.len() - 2);
+        assert_eq!(trailer, crc(content).to_le_bytes());
+        // A file that ends with its CRC checks out to zero.
+        assert_eq!(crc(&file), 0);
+    }
+
+    #[test]
+    fn messages_of_two_laps() {
+        let file = encode(&two_laps());
+        let messages = messages(&file[usize::from(HEADER_SIZE)..file.len() - 2]);
+        let globals = messages
+            .iter()
+            .map(|(global, _)| *global)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            globals,
+            [
+                message::FILE_ID,
+                message::DEVICE_INFO,
+                message::DEVICE_INFO,
+                message::EVENT,
+                message::RECORD,
+                message::RECORD,
+                message::RECORD,
+                message::RECORD,
+                message::RECORD,
+                message::RECORD,
+                message::EVENT,
+                message::LAP,
+                message::LAP,
+                message::SESSION,
+                message::ACTIVITY,
+            ]
+        );
+
+        let records = messages
+            .iter()
+            .filter(|(global, _)| *global == message::RECORD)
+            .map(|(_, fields)| fields)
+            .collect::<Vec<_>>();
+        let first = records[0];
+        assert_eq!(
+            u32_value(&first[&field::TIMESTAMP]),
+            (START + 1 - FIT_EPOCH) as u32
+        );
+        assert_eq!(first[&3], [121]);
+        assert_eq!(first[&4], [81]);
+        assert_eq!(u32_value(&first[&5]), 850);
+        // 30 km/h is 8.333 m/s.
+        assert_eq!(first[&6], 8333u16.to_le_bytes());
+        assert_eq!(first[&7], 100u16.to_le_bytes());
+        // Missing power is written as invalid.
+        assert_eq!(records[4][&7], [0xff, 0xff]);
+
+        let laps = messages
+            .iter()
+            .filter(|(global, _)| *global == message::LAP)
+            .map(|(_, fields)| fields)
+            .collect::<Vec<_>>();
+        for (index, (lap, start)) in laps.iter().zip([START, START + 3]).enumerate() {
+            assert_eq!(lap[&field::MESSAGE_INDEX], (index as u16).to_le_bytes());
+            assert_eq!(u32_value(&lap[&2]), (start - FIT_EPOCH) as u32);
+            assert_eq!(u32_value(&lap[&7]), 3000);
+        }
+        assert_eq!(laps[0][&24], [1]);
+        assert_eq!(laps[1][&24], [7]);
+        assert_eq!(laps[0][&19], 110u16.to_le_bytes());
+        assert_eq!(laps[1][&20], 220u16.to_le_bytes());
+
+        let (_, session) = &messages[13];
+        assert_eq!(session[&26], 2u16.to_le_bytes());
+        assert_eq!(u32_value(&session[&48]), 750);
+    }
+}
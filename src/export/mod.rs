@@ -0,0 +1,179 @@
+//! Writes finished rides in the formats other training apps read.
+
+mod csv;
+mod fit;
+mod tcx;
+
+use gtk::{
+    gio::{self, Cancellable, FileCreateFlags, prelude::FileExt},
+    glib::{self, object::IsA},
+};
+
+use crate::session::Activity;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Garmin's binary activity files.
+    Fit,
+    /// Garmin Training Center XML.
+    Tcx,
+    /// Every record, one per line.
+    Csv,
+}
+
+impl Format {
+    /// Parses the name actions refer to the format by, like "fit".
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "fit" => Some(Format::Fit),
+            "tcx" => Some(Format::Tcx),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Fit => "fit",
+            Format::Tcx => "tcx",
+            Format::Csv => "csv",
+        }
+    }
+
+    /// Describes the files, for file choosers.
+    pub fn title(self) -> &'static str {
+        match self {
+            Format::Fit => "FIT Activity",
+            Format::Tcx => "TCX Activity",
+            Format::Csv => "CSV Table",
+        }
+    }
+
+    pub fn encode(self, activity: &Activity) -> Vec<u8> {
+        match self {
+            Format::Fit => fit::encode(activity),
+            Format::Tcx => tcx::encode(activity),
+            Format::Csv => csv::encode(activity),
+        }
+    }
+
+    /// A file name from the start of the ride, like "ride-2024-05-01-1830.fit".
+    pub fn file_name(self, activity: &Activity) -> String {
+        let start = glib::DateTime::from_unix_local(activity.start)
+            .and_then(|start| start.format("%Y-%m-%d-%H%M"))
+            .map(String::from)
+            .unwrap_or_else(|_| activity.start.to_string());
+        format!("ride-{start}.{}", self.extension())
+    }
+}
+
+/// Seconds since the Unix epoch, as an ISO 8601 time in UTC.
+fn time(timestamp: i64) -> String {
+    glib::DateTime::from_unix_utc(timestamp)
+        .and_then(|time| time.format_iso8601())
+        .map(String::from)
+        .unwrap_or_default()
+}
+
+/// Asks where to save `activity`, then writes it as `format`.
+pub fn save_as(parent: Option<&impl IsA<gtk::Window>>, activity: Activity, format: Format) {
+    let filter = gtk::FileFilter::new();
+    filter.set_name(Some(format.title()));
+    filter.add_suffix(format.extension());
+    let filters = gio::ListStore::new::<gtk::FileFilter>();
+    filters.append(&filter);
+    let dialog = gtk::FileDialog::builder()
+        .title("Export Ride")
+        .initial_name(format.file_name(&activity))
+        .filters(&filters)
+        .build();
+    dialog.save(parent, None::<&Cancellable>, move |file| {
+        let Ok(file) = file else {
+            return;
+        };
+        if let Err(error) = file.replace_contents(
+            &format.encode(&activity),
+            None,
+            false,
+            FileCreateFlags::REPLACE_DESTINATION,
+            None::<&Cancellable>,
+        ) {
+            log::error!("Could not export the ride. {error}");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::session::{
+        Activity, Lap, LapTrigger, Record, Sensor, SensorKind, Totals, ZoneSettings,
+    };
+
+    /// 2023-11-14T22:13:20Z.
+    pub const START: i64 = 1_700_000_000;
+
+    /// Six seconds of riding in two laps of three, the fifth second without
+    /// power.
+    pub fn two_laps() -> Activity {
+        let powers = [Some(100), Some(110), Some(120), Some(200), None, Some(220)];
+        let records = (1..=6)
+            .zip(powers)
+            .map(|(second, power)| Record {
+                timestamp: START + second,
+                elapsed: second as f64,
+                power,
+                cadence: Some(80.0 + second as f64),
+                heart_rate: Some(120 + second as u16),
+                speed: Some(30.0),
+                distance: second as f64 * 8.5,
+                target_power: (second <= 3).then_some(150),
+            })
+            .collect::<Vec<_>>();
+        let zones = ZoneSettings::default();
+        let laps = vec![
+            Lap::restore(
+                START,
+                0.0,
+                Totals {
+                    elapsed: 3.0,
+                    moving_time: 3.0,
+                    distance: 25.5,
+                    work: 330.0,
+                },
+                LapTrigger::Time,
+                &records,
+                &zones,
+            ),
+            Lap::restore(
+                START + 3,
+                3.0,
+                Totals {
+                    elapsed: 3.0,
+                    moving_time: 3.0,
+                    distance: 25.5,
+                    work: 420.0,
+                },
+                LapTrigger::SessionEnd,
+                &records,
+                &zones,
+            ),
+        ];
+        Activity {
+            start: START,
+            totals: Totals {
+                elapsed: 6.0,
+                moving_time: 6.0,
+                distance: 51.0,
+                work: 750.0,
+            },
+            records,
+            laps,
+            sensors: vec![Sensor {
+                name: "KICKR".to_string(),
+                brand: "Wahoo".to_string(),
+                kind: SensorKind::FitnessEquipment,
+            }],
+            ftp: Some(250.0),
+        }
+    }
+}
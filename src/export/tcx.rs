@@ -0,0 +1,212 @@
+//! Garmin Training Center XML, with power in the ActivityExtension.
+
+use std::fmt::{self, Write};
+
+use super::time;
+use crate::session::{Activity, Lap, LapTrigger, Record};
+
+const HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<TrainingCenterDatabase xmlns="http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2" xmlns:ns3="http://www.garmin.com/xmlschemas/ActivityExtension/v2">
+"#;
+
+fn trigger_method(trigger: LapTrigger) -> &'static str {
+    match trigger {
+        LapTrigger::Manual | LapTrigger::SessionEnd => "Manual",
+        LapTrigger::Distance => "Distance",
+        LapTrigger::Time => "Time",
+    }
+}
+
+fn trackpoint(xml: &mut String, record: &Record) -> fmt::Result {
+    writeln!(xml, "          <Trackpoint>")?;
+    writeln!(xml, "            <Time>{}</Time>", time(record.timestamp))?;
+    writeln!(
+        xml,
+        "            <DistanceMeters>{:.2}</DistanceMeters>",
+        record.distance
+    )?;
+    if let Some(heart_rate) = record.heart_rate {
+        writeln!(
+            xml,
+            "            <HeartRateBpm><Value>{heart_rate}</Value></HeartRateBpm>"
+        )?;
+    }
+    if let Some(cadence) = record.cadence {
+        writeln!(
+            xml,
+            "            <Cadence>{:.0}</Cadence>",
+            cadence.min(254.0)
+        )?;
+    }
+    if record.speed.is_some() || record.power.is_some() {
+        writeln!(xml, "            <Extensions>")?;
+        writeln!(xml, "              <ns3:TPX>")?;
+        if let Some(speed) = record.speed {
+            writeln!(
+                xml,
+                "                <ns3:Speed>{:.3}</ns3:Speed>",
+                speed / 3.6
+            )?;
+        }
+        if let Some(power) = record.power {
+            writeln!(
+                xml,
+                "                <ns3:Watts>{}</ns3:Watts>",
+                power.max(0)
+            )?;
+        }
+        writeln!(xml, "              </ns3:TPX>")?;
+        writeln!(xml, "            </Extensions>")?;
+    }
+    writeln!(xml, "          </Trackpoint>")
+}
+
+fn lap(xml: &mut String, lap: &Lap, records: &[Record]) -> fmt::Result {
+    writeln!(
+        xml,
+        r#"      <Lap StartTime="{}">"#,
+        time(lap.start_timestamp)
+    )?;
+    writeln!(
+        xml,
+        "        <TotalTimeSeconds>{:.1}</TotalTimeSeconds>",
+        lap.totals.moving_time
+    )?;
+    writeln!(
+        xml,
+        "        <DistanceMeters>{:.2}</DistanceMeters>",
+        lap.totals.distance
+    )?;
+    if let Some(speed) = lap.speed.maximum {
+        writeln!(
+            xml,
+            "        <MaximumSpeed>{:.3}</MaximumSpeed>",
+            speed / 3.6
+        )?;
+    }
+    writeln!(
+        xml,
+        "        <Calories>{:.0}</Calories>",
+        lap.totals.calories()
+    )?;
+    if let Some(heart_rate) = lap.heart_rate.average {
+        writeln!(
+            xml,
+            "        <AverageHeartRateBpm><Value>{heart_rate:.0}</Value></AverageHeartRateBpm>"
+        )?;
+    }
+    if let Some(heart_rate) = lap.heart_rate.maximum {
+        writeln!(
+            xml,
+            "        <MaximumHeartRateBpm><Value>{heart_rate:.0}</Value></MaximumHeartRateBpm>"
+        )?;
+    }
+    writeln!(xml, "        <Intensity>Active</Intensity>")?;
+    if let Some(cadence) = lap.cadence.average {
+        writeln!(xml, "        <Cadence>{:.0}</Cadence>", cadence.min(254.0))?;
+    }
+    writeln!(
+        xml,
+        "        <TriggerMethod>{}</TriggerMethod>",
+        trigger_method(lap.trigger)
+    )?;
+    let records = lap.records(records);
+    if !records.is_empty() {
+        writeln!(xml, "        <Track>")?;
+        for record in records {
+            trackpoint(xml, record)?;
+        }
+        writeln!(xml, "        </Track>")?;
+    }
+    writeln!(xml, "        <Extensions>")?;
+    writeln!(xml, "          <ns3:LX>")?;
+    if let Some(speed) = lap.speed.average {
+        writeln!(
+            xml,
+            "            <ns3:AvgSpeed>{:.3}</ns3:AvgSpeed>",
+            speed / 3.6
+        )?;
+    }
+    if let Some(cadence) = lap.cadence.maximum {
+        writeln!(
+            xml,
+            "            <ns3:MaxBikeCadence>{:.0}</ns3:MaxBikeCadence>",
+            cadence.min(254.0)
+        )?;
+    }
+    if let Some(power) = lap.power.average {
+        writeln!(xml, "            <ns3:AvgWatts>{power:.0}</ns3:AvgWatts>")?;
+    }
+    if let Some(power) = lap.power.maximum {
+        writeln!(xml, "            <ns3:MaxWatts>{power:.0}</ns3:MaxWatts>")?;
+    }
+    writeln!(xml, "          </ns3:LX>")?;
+    writeln!(xml, "        </Extensions>")?;
+    writeln!(xml, "      </Lap>")
+}
+
+/// Encodes `activity` as a TCX file, one `Lap` per lap of the ride.
+pub fn encode(activity: &Activity) -> Vec<u8> {
+    let mut xml = String::from(HEADER);
+    activity_xml(&mut xml, activity).expect("writing to a string never fails");
+    xml.into_bytes()
+}
+
+fn activity_xml(xml: &mut String, activity: &Activity) -> fmt::Result {
+    writeln!(xml, "  <Activities>")?;
+    writeln!(xml, r#"    <Activity Sport="Biking">"#)?;
+    writeln!(xml, "      <Id>{}</Id>", time(activity.start))?;
+    for lap in &activity.laps {
+        self::lap(xml, lap, &activity.records)?;
+    }
+    writeln!(xml, "    </Activity>")?;
+    writeln!(xml, "  </Activities>")?;
+    writeln!(xml, "</TrainingCenterDatabase>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::tests::two_laps;
+
+    #[test]
+    fn laps_and_trackpoints() {
+        let xml = String::from_utf8(encode(&two_laps())).unwrap();
+        assert!(xml.starts_with(HEADER));
+        assert!(xml.contains("      <Id>2023-11-14T22:13:20Z</Id>\n"));
+        assert!(xml.ends_with("  </Activities>\n</TrainingCenterDatabase>\n"));
+
+        let laps = xml.split("<Lap ").skip(1).collect::<Vec<_>>();
+        assert_eq!(laps.len(), 2);
+        assert!(laps[0].starts_with(r#"StartTime="2023-11-14T22:13:20Z">"#));
+        assert!(laps[1].starts_with(r#"StartTime="2023-11-14T22:13:23Z">"#));
+        assert!(laps[0].contains("<TriggerMethod>Time</TriggerMethod>"));
+        assert!(laps[1].contains("<TriggerMethod>Manual</TriggerMethod>"));
+        assert!(laps[0].contains("<TotalTimeSeconds>3.0</TotalTimeSeconds>"));
+        assert!(laps[1].contains("<ns3:AvgWatts>210</ns3:AvgWatts>"));
+        for lap in &laps {
+            assert_eq!(lap.matches("<Trackpoint>").count(), 3);
+            assert_eq!(lap.matches("</Lap>").count(), 1);
+        }
+
+        let trackpoints = xml.split("<Trackpoint>").skip(1).collect::<Vec<_>>();
+        assert_eq!(
+            trackpoints[0].split("</Trackpoint>").next().unwrap(),
+            "
+            <Time>2023-11-14T22:13:21Z</Time>
+            <DistanceMeters>8.50</DistanceMeters>
+            <HeartRateBpm><Value>121</Value></HeartRateBpm>
+            <Cadence>81</Cadence>
+            <Extensions>
+              <ns3:TPX>
+                <ns3:Speed>8.333</ns3:Speed>
+                <ns3:Watts>100</ns3:Watts>
+              </ns3:TPX>
+            </Extensions>
+          "
+        );
+        // The second without power.
+        assert!(trackpoints[4].contains("<Time>2023-11-14T22:13:25Z</Time>"));
+        assert!(!trackpoints[4].contains("<ns3:Watts>"));
+    }
+}
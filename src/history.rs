@@ -0,0 +1,507 @@
+//! Finished rides, kept under the user data dir as two key files each: one
+//! with what the history lists, and one with the records.
+
+use std::{fmt::Display, path::PathBuf, str::FromStr};
+
+use gtk::{
+    gio::{self, Cancellable, prelude::FileExt},
+    glib::{self, KeyFile, KeyFileFlags},
+};
+
+use crate::{
+    session::{Activity, Lap, LapTrigger, Record, Sensor, SensorKind, Totals, ZoneSettings},
+    settings::Settings,
+};
+
+const RIDE: &str = "Ride";
+const RECORDS: &str = "Records";
+const EXTENSION: &str = "ride";
+const RECORDS_EXTENSION: &str = "records";
+
+/// A ride in the history, with what the list shows about it. The records
+/// are in a file of their own, only read by [`SavedRide::load`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedRide {
+    pub path: PathBuf,
+    pub name: String,
+    /// Seconds since the Unix epoch.
+    pub start: i64,
+    pub totals: Totals,
+    pub average_power: Option<f64>,
+    pub normalized_power: Option<f64>,
+    pub training_stress_score: Option<f64>,
+}
+
+fn directory() -> PathBuf {
+    glib::user_data_dir().join("bike").join("rides")
+}
+
+/// Names a ride after the time of day it started, like "Evening Ride".
+pub fn default_name(start: i64) -> String {
+    let hour = glib::DateTime::from_unix_local(start)
+        .map(|start| start.hour())
+        .unwrap_or_default();
+    match hour {
+        5..12 => "Morning Ride",
+        12..17 => "Afternoon Ride",
+        17..21 => "Evening Ride",
+        _ => "Night Ride",
+    }
+    .to_string()
+}
+
+/// The zones a saved ride is summed up with: the ones set now, at the FTP
+/// the ride was saved with.
+pub fn zone_settings(ftp: Option<f64>) -> ZoneSettings {
+    let mut zones = Settings::load().zone_settings();
+    if ftp.is_some() {
+        zones.ftp = ftp;
+    }
+    zones
+}
+
+/// Every saved ride, the latest first. Files that can't be read are skipped.
+pub fn rides() -> Vec<SavedRide> {
+    let entries = match std::fs::read_dir(directory()) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+        Err(error) => {
+            log::warn!("Could not list the saved rides. {error}");
+            return Vec::new();
+        }
+    };
+    let mut rides = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == EXTENSION)
+        })
+        .filter_map(|path| match SavedRide::read(path.clone()) {
+            Ok(ride) => Some(ride),
+            Err(error) => {
+                log::warn!("Could not read {}. {error}", path.display());
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    rides.sort_by_key(|ride| std::cmp::Reverse(ride.start));
+    rides
+}
+
+/// Adds `activity` to the history, replacing an earlier save of it.
+pub fn save(activity: &Activity, name: &str) -> Result<SavedRide, glib::Error> {
+    let directory = directory();
+    if let Err(error) = std::fs::create_dir_all(&directory) {
+        log::warn!("Could not create {}. {error}", directory.display());
+    }
+    let path = directory.join(format!("{}.{EXTENSION}", activity.start));
+    // The records go first, so that a listed ride always has them.
+    let records = KeyFile::new();
+    write_records(&records, &activity.records);
+    records.save_to_file(path.with_extension(RECORDS_EXTENSION))?;
+    let key_file = KeyFile::new();
+    write_summary(&key_file, activity);
+    key_file.set_string(RIDE, "name", name);
+    key_file.save_to_file(&path)?;
+    SavedRide::from_key_file(path, &key_file)
+}
+
+impl SavedRide {
+    fn read(path: PathBuf) -> Result<Self, glib::Error> {
+        let key_file = KeyFile::new();
+        key_file.load_from_file(&path, KeyFileFlags::NONE)?;
+        Self::from_key_file(path, &key_file)
+    }
+
+    fn from_key_file(path: PathBuf, key_file: &KeyFile) -> Result<Self, glib::Error> {
+        let optional = |key| key_file.double(RIDE, key).ok().filter(|value| *value > 0.0);
+        Ok(Self {
+            name: key_file.string(RIDE, "name")?.into(),
+            start: key_file.int64(RIDE, "start")?,
+            totals: totals(key_file, RIDE)?,
+            average_power: optional("average-power"),
+            normalized_power: optional("normalized-power"),
+            training_stress_score: optional("training-stress-score"),
+            path,
+        })
+    }
+
+    /// Reads the whole ride back.
+    pub fn load(&self) -> Result<Activity, glib::Error> {
+        let key_file = KeyFile::new();
+        key_file.load_from_file(&self.path, KeyFileFlags::NONE)?;
+        let records = KeyFile::new();
+        records.load_from_file(self.records_path(), KeyFileFlags::NONE)?;
+        read_activity(&key_file, &records)
+    }
+
+    fn records_path(&self) -> PathBuf {
+        self.path.with_extension(RECORDS_EXTENSION)
+    }
+
+    pub fn rename(&mut self, name: &str) -> Result<(), glib::Error> {
+        let key_file = KeyFile::new();
+        key_file.load_from_file(&self.path, KeyFileFlags::KEEP_COMMENTS)?;
+        key_file.set_string(RIDE, "name", name);
+        key_file.save_to_file(&self.path)?;
+        self.name = name.to_string();
+        Ok(())
+    }
+
+    pub fn delete(&self) -> Result<(), glib::Error> {
+        gio::File::for_path(&self.path).delete(None::<&Cancellable>)?;
+        // Without its summary the ride is gone from the history already.
+        if let Err(error) = gio::File::for_path(self.records_path()).delete(None::<&Cancellable>) {
+            log::warn!(
+                "Could not delete {}. {error}",
+                self.records_path().display()
+            );
+        }
+        Ok(())
+    }
+}
+
+fn set_totals(key_file: &KeyFile, group: &str, totals: &Totals) {
+    key_file.set_double(group, "elapsed", totals.elapsed);
+    key_file.set_double(group, "moving-time", totals.moving_time);
+    key_file.set_double(group, "distance", totals.distance);
+    key_file.set_double(group, "work", totals.work);
+}
+
+fn totals(key_file: &KeyFile, group: &str) -> Result<Totals, glib::Error> {
+    Ok(Totals {
+        elapsed: key_file.double(group, "elapsed")?,
+        moving_time: key_file.double(group, "moving-time")?,
+        distance: key_file.double(group, "distance")?,
+        work: key_file.double(group, "work")?,
+    })
+}
+
+/// Writes one value per record, missing ones left empty.
+fn set_column<T: Display>(key_file: &KeyFile, key: &str, values: impl Iterator<Item = Option<T>>) {
+    let values = values
+        .map(|value| value.map(|value| value.to_string()).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join(";");
+    key_file.set_string(RECORDS, key, &values);
+}
+
+/// Reads `count` values written by [`set_column`], `None` for missing ones.
+fn column<T: FromStr>(key_file: &KeyFile, key: &str, count: usize) -> Vec<Option<T>> {
+    let values = key_file.string(RECORDS, key).unwrap_or_default();
+    let mut values = values
+        .split(';')
+        .map(|value| value.parse().ok())
+        .collect::<Vec<_>>();
+    values.resize_with(count, || None);
+    values
+}
+
+fn lap_group(index: usize) -> String {
+    format!("Lap {}", index + 1)
+}
+
+fn sensor_group(index: usize) -> String {
+    format!("Sensor {}", index + 1)
+}
+
+fn trigger_name(trigger: LapTrigger) -> &'static str {
+    match trigger {
+        LapTrigger::Manual => "manual",
+        LapTrigger::Distance => "distance",
+        LapTrigger::Time => "time",
+        LapTrigger::SessionEnd => "session-end",
+    }
+}
+
+fn trigger(name: &str) -> LapTrigger {
+    match name {
+        "distance" => LapTrigger::Distance,
+        "time" => LapTrigger::Time,
+        "session-end" => LapTrigger::SessionEnd,
+        _ => LapTrigger::Manual,
+    }
+}
+
+fn kind_name(kind: SensorKind) -> &'static str {
+    match kind {
+        SensorKind::FitnessEquipment => "fitness-equipment",
+        SensorKind::BikePower => "bike-power",
+        SensorKind::HeartRate => "heart-rate",
+    }
+}
+
+fn kind(name: &str) -> SensorKind {
+    match name {
+        "fitness-equipment" => SensorKind::FitnessEquipment,
+        "heart-rate" => SensorKind::HeartRate,
+        _ => SensorKind::BikePower,
+    }
+}
+
+fn write_summary(key_file: &KeyFile, activity: &Activity) {
+    key_file.set_int64(RIDE, "start", activity.start);
+    set_totals(key_file, RIDE, &activity.totals);
+    key_file.set_double(RIDE, "ftp", activity.ftp.unwrap_or_default());
+    // Kept for the list, so it doesn't have to read the records.
+    let summary = activity.power_summary().unwrap_or_default();
+    key_file.set_double(RIDE, "average-power", summary.average);
+    key_file.set_double(
+        RIDE,
+        "normalized-power",
+        summary.normalized.unwrap_or_default(),
+    );
+    key_file.set_double(
+        RIDE,
+        "training-stress-score",
+        summary.training_stress_score.unwrap_or_default(),
+    );
+
+    for (index, lap) in activity.laps.iter().enumerate() {
+        let group = lap_group(index);
+        key_file.set_int64(&group, "start", lap.start_timestamp);
+        key_file.set_double(&group, "start-elapsed", lap.start_elapsed);
+        set_totals(key_file, &group, &lap.totals);
+        key_file.set_string(&group, "trigger", trigger_name(lap.trigger));
+    }
+
+    for (index, sensor) in activity.sensors.iter().enumerate() {
+        let group = sensor_group(index);
+        key_file.set_string(&group, "name", &sensor.name);
+        key_file.set_string(&group, "brand", &sensor.brand);
+        key_file.set_string(&group, "kind", kind_name(sensor.kind));
+    }
+}
+
+fn write_records(key_file: &KeyFile, records: &[Record]) {
+    key_file.set_uint64(RECORDS, "count", records.len() as u64);
+    set_column(
+        key_file,
+        "timestamp",
+        records.iter().map(|record| Some(record.timestamp)),
+    );
+    set_column(
+        key_file,
+        "elapsed",
+        records.iter().map(|record| Some(record.elapsed)),
+    );
+    set_column(key_file, "power", records.iter().map(|record| record.power));
+    set_column(
+        key_file,
+        "cadence",
+        records.iter().map(|record| record.cadence),
+    );
+    set_column(
+        key_file,
+        "heart-rate",
+        records.iter().map(|record| record.heart_rate),
+    );
+    set_column(key_file, "speed", records.iter().map(|record| record.speed));
+    set_column(
+        key_file,
+        "distance",
+        records.iter().map(|record| Some(record.distance)),
+    );
+    set_column(
+        key_file,
+        "target-power",
+        records.iter().map(|record| record.target_power),
+    );
+}
+
+fn read_records(key_file: &KeyFile) -> Result<Vec<Record>, glib::Error> {
+    let count = key_file.uint64(RECORDS, "count")? as usize;
+    let timestamps = column::<i64>(key_file, "timestamp", count);
+    let elapsed = column::<f64>(key_file, "elapsed", count);
+    let power = column(key_file, "power", count);
+    let cadence = column(key_file, "cadence", count);
+    let heart_rate = column(key_file, "heart-rate", count);
+    let speed = column(key_file, "speed", count);
+    let distance = column::<f64>(key_file, "distance", count);
+    let target_power = column(key_file, "target-power", count);
+    Ok((0..count)
+        .map(|index| Record {
+            timestamp: timestamps[index].unwrap_or_default(),
+            elapsed: elapsed[index].unwrap_or_default(),
+            power: power[index],
+            cadence: cadence[index],
+            heart_rate: heart_rate[index],
+            speed: speed[index],
+            distance: distance[index].unwrap_or_default(),
+            target_power: target_power[index],
+        })
+        .collect())
+}
+
+/// Puts a ride back together from its summary and its records.
+fn read_activity(key_file: &KeyFile, records: &KeyFile) -> Result<Activity, glib::Error> {
+    let records = read_records(records)?;
+
+    let ftp = key_file.double(RIDE, "ftp").ok().filter(|ftp| *ftp > 0.0);
+    let zones = zone_settings(ftp);
+    let mut laps = Vec::new();
+    while key_file.has_group(&lap_group(laps.len())) {
+        let group = lap_group(laps.len());
+        laps.push(Lap::restore(
+            key_file.int64(&group, "start")?,
+            key_file.double(&group, "start-elapsed")?,
+            totals(key_file, &group)?,
+            trigger(&key_file.string(&group, "trigger").unwrap_or_default()),
+            &records,
+            &zones,
+        ));
+    }
+
+    let mut sensors = Vec::new();
+    while key_file.has_group(&sensor_group(sensors.len())) {
+        let group = sensor_group(sensors.len());
+        sensors.push(Sensor {
+            name: key_file.string(&group, "name")?.into(),
+            brand: key_file.string(&group, "brand").unwrap_or_default().into(),
+            kind: kind(&key_file.string(&group, "kind").unwrap_or_default()),
+        });
+    }
+
+    Ok(Activity {
+        start: key_file.int64(RIDE, "start")?,
+        totals: totals(key_file, RIDE)?,
+        records,
+        laps,
+        sensors,
+        ftp,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: i64 = 1_700_000_000;
+
+    /// Writes a key file out and parses it again, as saving and loading do.
+    fn reparse(key_file: &KeyFile) -> KeyFile {
+        let parsed = KeyFile::new();
+        parsed
+            .load_from_data(&key_file.to_data(), KeyFileFlags::NONE)
+            .unwrap();
+        parsed
+    }
+
+    fn activity(ftp: Option<f64>) -> Activity {
+        let records = (1..=40)
+            .map(|second| Record {
+                timestamp: START + second,
+                elapsed: second as f64 + 0.25,
+                // A dropout every ten seconds.
+                power: (second % 10 != 0).then_some(180 + second as i16),
+                cadence: (second % 10 != 0).then_some(85.5),
+                heart_rate: (second > 5).then_some(130 + second as u16),
+                speed: Some(32.125),
+                distance: second as f64 * 8.9,
+                target_power: (second > 20).then_some(200),
+            })
+            .collect::<Vec<_>>();
+        let zones = zone_settings(ftp);
+        let laps = vec![
+            Lap::restore(
+                START,
+                0.0,
+                Totals {
+                    elapsed: 20.25,
+                    moving_time: 20.0,
+                    distance: 178.0,
+                    work: 3600.0,
+                },
+                LapTrigger::Distance,
+                &records,
+                &zones,
+            ),
+            Lap::restore(
+                START + 20,
+                20.25,
+                Totals {
+                    elapsed: 20.0,
+                    moving_time: 19.0,
+                    distance: 178.0,
+                    work: 3950.0,
+                },
+                LapTrigger::SessionEnd,
+                &records,
+                &zones,
+            ),
+        ];
+        Activity {
+            start: START,
+            totals: Totals {
+                elapsed: 40.25,
+                moving_time: 39.0,
+                distance: 356.0,
+                work: 7550.0,
+            },
+            records,
+            laps,
+            sensors: vec![
+                Sensor {
+                    name: "KICKR".to_string(),
+                    brand: "Wahoo".to_string(),
+                    kind: SensorKind::FitnessEquipment,
+                },
+                Sensor {
+                    name: "HRM".to_string(),
+                    brand: String::new(),
+                    kind: SensorKind::HeartRate,
+                },
+            ],
+            ftp,
+        }
+    }
+
+    fn round_trip(activity: &Activity) -> Activity {
+        let key_file = KeyFile::new();
+        write_summary(&key_file, activity);
+        let records = KeyFile::new();
+        write_records(&records, &activity.records);
+        read_activity(&reparse(&key_file), &reparse(&records)).unwrap()
+    }
+
+    #[test]
+    fn activity_round_trip() {
+        let activity = activity(Some(250.0));
+        assert_eq!(round_trip(&activity), activity);
+    }
+
+    #[test]
+    fn activity_without_ftp_or_records() {
+        let activity = activity(None);
+        assert_eq!(round_trip(&activity), activity);
+
+        let empty = Activity {
+            records: vec![],
+            laps: vec![],
+            sensors: vec![],
+            ..activity
+        };
+        assert_eq!(round_trip(&empty), empty);
+    }
+
+    #[test]
+    fn summary_alone() {
+        let activity = activity(Some(250.0));
+        let key_file = KeyFile::new();
+        write_summary(&key_file, &activity);
+        key_file.set_string(RIDE, "name", "Evening Ride");
+        let saved =
+            SavedRide::from_key_file(PathBuf::from("/rides/1.ride"), &reparse(&key_file)).unwrap();
+        let summary = activity.power_summary().unwrap();
+        assert_eq!(saved.name, "Evening Ride");
+        assert_eq!(saved.start, START);
+        assert_eq!(saved.totals, activity.totals);
+        assert_eq!(saved.average_power, Some(summary.average));
+        assert_eq!(saved.normalized_power, summary.normalized);
+        assert_eq!(saved.training_stress_score, summary.training_stress_score);
+        assert_eq!(saved.records_path(), PathBuf::from("/rides/1.records"));
+
+        // Without records, the summary can't be read as a whole ride.
+        assert!(read_activity(&key_file, &KeyFile::new()).is_err());
+    }
+}
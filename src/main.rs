@@ -1,17 +1,26 @@
-use bluetooth::BluetoothService;
-use components::{App, BluetoothButton, DeviceDetailsPage, Window};
+use bluetooth::{BluetoothService, Device};
+use components::{
+    App, BluetoothButton, Dashboard, DeviceDetailsPage, RideChart, RidePage, SpinDownPage, Window,
+};
 use gtk::{gio::prelude::ApplicationExtManual, glib::types::StaticType};
 use once_cell::sync::Lazy;
-use std::io::Write;
+use session::Ride;
+use std::{io::Write, path::PathBuf};
 
 mod bluetooth;
 mod components;
+mod export;
+mod history;
+mod protocol;
+mod session;
+mod settings;
 
 pub static BLUETOOTH: Lazy<BluetoothService> = Lazy::new(BluetoothService::new);
 
 fn main() -> gtk::glib::ExitCode {
     setup_logger();
     setup_resources();
+    setup_capture();
     register_custom_types();
     let app = App::new();
     app.run()
@@ -39,9 +48,25 @@ fn setup_resources() {
         .expect("Failed to register resources.");
 }
 
+/// Setting `BIKE_CAPTURE_DIR` records all sensor traffic to a trace file in
+/// that directory, to be replayed later with `BIKE_REPLAY`.
+fn setup_capture() {
+    if let Some(directory) = std::env::var_os("BIKE_CAPTURE_DIR")
+        && let Err(error) = BLUETOOTH.start_capture(&PathBuf::from(directory))
+    {
+        log::error!("Could not start capturing bluetooth traffic. {error}");
+    }
+}
+
 fn register_custom_types() {
+    Device::static_type();
+    Ride::static_type();
+    SpinDownPage::static_type();
     DeviceDetailsPage::static_type();
     BluetoothButton::static_type();
+    Dashboard::static_type();
+    RideChart::static_type();
+    RidePage::static_type();
     Window::static_type();
     App::static_type();
 }
@@ -0,0 +1,279 @@
+//! What devices tell about themselves before anyone connects to them.
+
+use super::Reader;
+
+pub const FITNESS_MACHINE_SERVICE: &str = "00001826-0000-1000-8000-00805f9b34fb";
+pub const CYCLING_POWER_SERVICE: &str = "00001818-0000-1000-8000-00805f9b34fb";
+pub const CYCLING_SPEED_AND_CADENCE_SERVICE: &str = "00001816-0000-1000-8000-00805f9b34fb";
+pub const HEART_RATE_SERVICE: &str = "0000180d-0000-1000-8000-00805f9b34fb";
+pub const FEC_SERVICE: &str = "6e40fec1-b5a3-f393-e0a9-e50e24dcca9e";
+
+const FITNESS_MACHINE_AVAILABLE: u8 = 1 << 0;
+
+/// Fitness services in the order they are listed to the rider.
+const FITNESS_SERVICES: [(&str, &str); 5] = [
+    (FITNESS_MACHINE_SERVICE, "Smart Trainer"),
+    (FEC_SERVICE, "FE-C"),
+    (CYCLING_POWER_SERVICE, "Power"),
+    (CYCLING_SPEED_AND_CADENCE_SERVICE, "Speed & Cadence"),
+    (HEART_RATE_SERVICE, "Heart Rate"),
+];
+
+/// Bluetooth SIG company identifiers of the brands riders are likely to have
+/// around them.
+const COMPANIES: [(u16, &str); 12] = [
+    (0x0006, "Microsoft"),
+    (0x000a, "Qualcomm"),
+    (0x004c, "Apple"),
+    (0x0059, "Nordic Semiconductor"),
+    (0x006b, "Polar"),
+    (0x0075, "Samsung"),
+    (0x0087, "Garmin"),
+    (0x009f, "Suunto"),
+    (0x00e0, "Google"),
+    (0x0157, "Zepp"),
+    (0x0689, "Tacx"),
+    (0x094a, "Zwift"),
+];
+
+/// How a device picked the address it advertises with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressKind {
+    Public,
+    RandomStatic,
+    /// Rotates every few minutes, only bonded peers can resolve it.
+    ResolvablePrivate,
+    NonResolvablePrivate,
+}
+
+impl AddressKind {
+    /// Reads the BlueZ `Address` and `AddressType` properties. The kind of
+    /// random address is encoded in the two most significant bits.
+    pub fn new(address: &str, address_type: &str) -> Self {
+        if address_type != "random" {
+            return AddressKind::Public;
+        }
+        let most_significant = address
+            .get(..2)
+            .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+            .unwrap_or(0xc0);
+        match most_significant >> 6 {
+            0b11 => AddressKind::RandomStatic,
+            0b01 => AddressKind::ResolvablePrivate,
+            _ => AddressKind::NonResolvablePrivate,
+        }
+    }
+
+    /// Whether the address identifies the device across restarts.
+    pub fn is_stable(self) -> bool {
+        matches!(self, AddressKind::Public | AddressKind::RandomStatic)
+    }
+}
+
+/// The Fitness Machine service data a trainer advertises.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FitnessMachineAdvertisement {
+    /// Cleared while another app is controlling the trainer.
+    pub available: bool,
+}
+
+/// Decoded advertising data. BlueZ only reports the properties that changed,
+/// so every field is optional and updates are merged with [`Advertisement::update`].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Advertisement {
+    pub company_id: Option<u16>,
+    pub services: Option<Vec<String>>,
+    pub tx_power: Option<i16>,
+    pub fitness_machine: Option<FitnessMachineAdvertisement>,
+}
+
+impl Advertisement {
+    /// Reads one entry of the `ServiceData` dictionary.
+    pub fn read_service_data(&mut self, uuid: &str, data: &[u8]) {
+        if uuid.eq_ignore_ascii_case(FITNESS_MACHINE_SERVICE) {
+            self.fitness_machine = decode_fitness_machine(data);
+        }
+    }
+
+    pub fn update(&mut self, other: Advertisement) {
+        self.company_id = other.company_id.or(self.company_id);
+        self.services = other.services.or(self.services.take());
+        self.tx_power = other.tx_power.or(self.tx_power);
+        self.fitness_machine = other.fitness_machine.or(self.fitness_machine);
+    }
+
+    pub fn brand(&self) -> Option<&'static str> {
+        let company_id = self.company_id?;
+        COMPANIES
+            .iter()
+            .find(|(id, _)| *id == company_id)
+            .map(|(_, name)| *name)
+    }
+
+    /// Human readable names of the fitness services the device offers.
+    pub fn fitness_services(&self) -> Vec<&'static str> {
+        let services = self.services.as_deref().unwrap_or_default();
+        FITNESS_SERVICES
+            .iter()
+            .filter(|(uuid, _)| {
+                services
+                    .iter()
+                    .any(|service| service.eq_ignore_ascii_case(uuid))
+            })
+            .map(|(_, name)| *name)
+            .collect()
+    }
+
+    /// Describes the device by what it advertises, for devices that keep
+    /// changing their address. Two sensors of the same model look the same
+    /// to it, which is the best that can be done without bonding.
+    pub fn fingerprint(&self, name: &str) -> String {
+        let mut services: Vec<String> = self
+            .services
+            .iter()
+            .flatten()
+            .map(|service| service.to_ascii_lowercase())
+            .collect();
+        services.sort();
+        format!(
+            "{name}/{}/{}",
+            self.company_id
+                .map(|company_id| format!("{company_id:04x}"))
+                .unwrap_or_default(),
+            services.join(",")
+        )
+    }
+
+    /// How much the device matters for a ride, lower first: trainers, then
+    /// power meters, speed and cadence sensors, heart rate monitors and
+    /// finally anything else.
+    pub fn fitness_rank(&self) -> usize {
+        let services = self.services.as_deref().unwrap_or_default();
+        FITNESS_SERVICES
+            .iter()
+            .position(|(uuid, _)| {
+                services
+                    .iter()
+                    .any(|service| service.eq_ignore_ascii_case(uuid))
+            })
+            // Both ways of controlling a trainer rank the same.
+            .map(|position| position.saturating_sub(1))
+            .unwrap_or(FITNESS_SERVICES.len())
+    }
+
+    pub fn in_use(&self) -> bool {
+        self.fitness_machine
+            .is_some_and(|fitness_machine| !fitness_machine.available)
+    }
+}
+
+fn decode_fitness_machine(data: &[u8]) -> Option<FitnessMachineAdvertisement> {
+    let mut reader = Reader::new(data);
+    // The flags are followed by the machine type, which the services already
+    // tell well enough.
+    let flags = reader.u8()?;
+    Some(FitnessMachineAdvertisement {
+        available: flags & FITNESS_MACHINE_AVAILABLE != 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn advertising(services: &[&str]) -> Advertisement {
+        Advertisement {
+            services: Some(services.iter().map(|service| service.to_string()).collect()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn address_kinds() {
+        assert_eq!(
+            AddressKind::new("F0:5E:11:0B:1C:01", "public"),
+            AddressKind::Public
+        );
+        assert_eq!(
+            AddressKind::new("F0:5E:11:0B:1C:01", "random"),
+            AddressKind::RandomStatic
+        );
+        assert_eq!(
+            AddressKind::new("5A:3B:11:0B:1C:01", "random"),
+            AddressKind::ResolvablePrivate
+        );
+        assert_eq!(
+            AddressKind::new("2A:3B:11:0B:1C:01", "random"),
+            AddressKind::NonResolvablePrivate
+        );
+        assert!(AddressKind::RandomStatic.is_stable());
+        assert!(!AddressKind::ResolvablePrivate.is_stable());
+    }
+
+    #[test]
+    fn fitness_machine_service_data() {
+        let mut advertisement = Advertisement::default();
+        advertisement
+            .read_service_data(&FITNESS_MACHINE_SERVICE.to_uppercase(), &[0x01, 0x20, 0x00]);
+        assert!(!advertisement.in_use());
+        advertisement.read_service_data(FITNESS_MACHINE_SERVICE, &[0x00, 0x20, 0x00]);
+        assert!(advertisement.in_use());
+        advertisement.read_service_data(HEART_RATE_SERVICE, &[0x01]);
+        assert!(advertisement.in_use());
+        advertisement.read_service_data(FITNESS_MACHINE_SERVICE, &[]);
+        assert_eq!(advertisement.fitness_machine, None);
+    }
+
+    #[test]
+    fn updates_keep_unchanged_properties() {
+        let mut advertisement = Advertisement {
+            company_id: Some(0x0689),
+            tx_power: Some(-4),
+            ..advertising(&[FEC_SERVICE])
+        };
+        advertisement.update(Advertisement {
+            tx_power: Some(0),
+            ..Default::default()
+        });
+        assert_eq!(advertisement.brand(), Some("Tacx"));
+        assert_eq!(advertisement.tx_power, Some(0));
+        assert_eq!(advertisement.fitness_services(), ["FE-C"]);
+    }
+
+    #[test]
+    fn fitness_services_and_rank() {
+        let trainer = advertising(&[
+            CYCLING_POWER_SERVICE,
+            &FITNESS_MACHINE_SERVICE.to_uppercase(),
+        ]);
+        assert_eq!(trainer.fitness_services(), ["Smart Trainer", "Power"]);
+        assert_eq!(trainer.fitness_rank(), 0);
+        assert_eq!(advertising(&[FEC_SERVICE]).fitness_rank(), 0);
+        assert_eq!(advertising(&[CYCLING_POWER_SERVICE]).fitness_rank(), 1);
+        assert_eq!(advertising(&[HEART_RATE_SERVICE]).fitness_rank(), 3);
+        assert_eq!(Advertisement::default().fitness_rank(), 5);
+        assert!(Advertisement::default().fitness_services().is_empty());
+    }
+
+    #[test]
+    fn fingerprint_ignores_service_order() {
+        let first = Advertisement {
+            company_id: Some(0x006b),
+            ..advertising(&[HEART_RATE_SERVICE, "0000180F-0000-1000-8000-00805F9B34FB"])
+        };
+        let second = Advertisement {
+            company_id: Some(0x006b),
+            ..advertising(&["0000180f-0000-1000-8000-00805f9b34fb", HEART_RATE_SERVICE])
+        };
+        assert_eq!(
+            first.fingerprint("Polar H10"),
+            second.fingerprint("Polar H10")
+        );
+        assert_eq!(
+            first.fingerprint("Polar H10"),
+            "Polar H10/006b/0000180d-0000-1000-8000-00805f9b34fb,0000180f-0000-1000-8000-00805f9b34fb"
+        );
+        assert_eq!(Advertisement::default().fingerprint("Strap"), "Strap//");
+        assert_eq!(Advertisement::default().brand(), None);
+    }
+}
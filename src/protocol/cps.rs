@@ -0,0 +1,345 @@
+use std::fmt::Display;
+
+use super::{Measurement, Reader};
+
+const PEDAL_POWER_BALANCE: u16 = 1 << 0;
+const ACCUMULATED_TORQUE: u16 = 1 << 2;
+const WHEEL_REVOLUTION_DATA: u16 = 1 << 4;
+const CRANK_REVOLUTION_DATA: u16 = 1 << 5;
+
+/// Crank event time resolution, in ticks per second.
+const CRANK_TIME_RESOLUTION: f64 = 1024.0;
+
+/// Cadence is derived from the difference between two crank revolution
+/// samples, so the previous one needs to be remembered.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CrankState {
+    last: Option<(u16, u16)>,
+    cadence: Option<f64>,
+}
+
+/// Decodes a Cycling Power Measurement (0x2A63) value.
+pub fn decode_measurement(data: &[u8], crank: &mut CrankState) -> Option<Measurement> {
+    let mut reader = Reader::new(data);
+    let flags = reader.u16()?;
+    let power = reader.i16()?;
+
+    if flags & PEDAL_POWER_BALANCE != 0 {
+        reader.skip(1)?;
+    }
+    if flags & ACCUMULATED_TORQUE != 0 {
+        reader.skip(2)?;
+    }
+    if flags & WHEEL_REVOLUTION_DATA != 0 {
+        reader.skip(6)?;
+    }
+    if flags & CRANK_REVOLUTION_DATA != 0 {
+        let revolutions = reader.u16()?;
+        let event_time = reader.u16()?;
+        if let Some((last_revolutions, last_event_time)) = crank.last {
+            let new_revolutions = revolutions.wrapping_sub(last_revolutions);
+            let ticks = event_time.wrapping_sub(last_event_time);
+            if ticks > 0 {
+                crank.cadence = Some(
+                    f64::from(new_revolutions) * 60.0 * CRANK_TIME_RESOLUTION / f64::from(ticks),
+                );
+            } else if power == 0 {
+                // No new crank event and no power: the rider stopped pedalling.
+                crank.cadence = Some(0.0);
+            }
+        }
+        crank.last = Some((revolutions, event_time));
+    }
+
+    Some(Measurement {
+        power: Some(power),
+        cadence: crank.cadence,
+        ..Default::default()
+    })
+}
+
+/// The cumulative crank revolutions of a measurement, which only ever go
+/// forward and so tell when packets arrive out of order.
+pub fn crank_revolutions(data: &[u8]) -> Option<u16> {
+    let mut reader = Reader::new(data);
+    let flags = reader.u16()?;
+    if flags & CRANK_REVOLUTION_DATA == 0 {
+        return None;
+    }
+    reader.skip(2)?;
+    if flags & PEDAL_POWER_BALANCE != 0 {
+        reader.skip(1)?;
+    }
+    if flags & ACCUMULATED_TORQUE != 0 {
+        reader.skip(2)?;
+    }
+    if flags & WHEEL_REVOLUTION_DATA != 0 {
+        reader.skip(6)?;
+    }
+    reader.u16()
+}
+
+const SET_CRANK_LENGTH: u8 = 0x04;
+const REQUEST_CRANK_LENGTH: u8 = 0x05;
+const REQUEST_OFFSET_COMPENSATION: u8 = 0x0c;
+const RESPONSE_CODE: u8 = 0x20;
+
+const SUCCESS: u8 = 0x01;
+const NOT_SUPPORTED: u8 = 0x02;
+const INVALID_PARAMETER: u8 = 0x03;
+
+/// Procedures of the Cycling Power Control Point (0x2A66).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CyclingPowerRequest {
+    /// Zeroes the power meter, the cranks have to be unloaded.
+    OffsetCompensation,
+    RequestCrankLength,
+    /// Sets the crank length, in millimeters.
+    SetCrankLength(f64),
+}
+
+impl CyclingPowerRequest {
+    fn op_code(&self) -> u8 {
+        match self {
+            CyclingPowerRequest::OffsetCompensation => REQUEST_OFFSET_COMPENSATION,
+            CyclingPowerRequest::RequestCrankLength => REQUEST_CRANK_LENGTH,
+            CyclingPowerRequest::SetCrankLength(_) => SET_CRANK_LENGTH,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut value = vec![self.op_code()];
+        if let CyclingPowerRequest::SetCrankLength(length) = self {
+            value.extend(((length * 2.0).round() as u16).to_le_bytes());
+        }
+        value
+    }
+
+    /// Decodes a control point indication, returning `None` when it does not
+    /// answer this request.
+    pub fn decode_response(
+        &self,
+        data: &[u8],
+    ) -> Option<Result<CyclingPowerResponse, CyclingPowerError>> {
+        let mut reader = Reader::new(data);
+        if reader.u8()? != RESPONSE_CODE || reader.u8()? != self.op_code() {
+            return None;
+        }
+        let response = match reader.u8()? {
+            SUCCESS => Ok(match self {
+                CyclingPowerRequest::OffsetCompensation => {
+                    CyclingPowerResponse::Offset(reader.i16())
+                }
+                CyclingPowerRequest::RequestCrankLength => {
+                    CyclingPowerResponse::CrankLength(f64::from(reader.u16()?) / 2.0)
+                }
+                CyclingPowerRequest::SetCrankLength(length) => {
+                    CyclingPowerResponse::CrankLength(*length)
+                }
+            }),
+            NOT_SUPPORTED => Err(CyclingPowerError::NotSupported),
+            INVALID_PARAMETER => Err(CyclingPowerError::InvalidParameter),
+            _ => Err(CyclingPowerError::Failed),
+        };
+        Some(response)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CyclingPowerResponse {
+    /// The raw offset the power meter settled on, when it reports one.
+    Offset(Option<i16>),
+    /// The crank length in millimeters.
+    CrankLength(f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CyclingPowerError {
+    NotSupported,
+    InvalidParameter,
+    Failed,
+    TimedOut,
+}
+
+impl Display for CyclingPowerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CyclingPowerError::NotSupported => write!(f, "Not supported by this power meter"),
+            CyclingPowerError::InvalidParameter => write!(f, "The power meter refused the value"),
+            CyclingPowerError::Failed => {
+                write!(
+                    f,
+                    "The power meter could not do it, make sure the cranks are unloaded"
+                )
+            }
+            CyclingPowerError::TimedOut => write!(f, "The power meter did not answer"),
+        }
+    }
+}
+
+/// Where a power meter is mounted, as reported by Sensor Location (0x2A5D).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SensorLocation(pub u8);
+
+impl Display for SensorLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let location = match self.0 {
+            1 => "Top of shoe",
+            2 => "In shoe",
+            3 => "Hip",
+            4 => "Front wheel",
+            5 => "Left crank",
+            6 => "Right crank",
+            7 => "Left pedal",
+            8 => "Right pedal",
+            9 => "Front hub",
+            10 => "Rear dropout",
+            11 => "Chainstay",
+            12 => "Rear wheel",
+            13 => "Rear hub",
+            14 => "Chest",
+            15 => "Spider",
+            16 => "Chain ring",
+            _ => "Other",
+        };
+        write!(f, "{location}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cadence_from_crank_revolutions() {
+        let mut crank = CrankState::default();
+        // Every optional field before the crank data is present.
+        let first = [
+            0x35, 0x00, 0xc8, 0x00, 0x64, 0x00, 0x10, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x0a,
+            0x00, 0x00, 0x04,
+        ];
+        let second = [
+            0x35, 0x00, 0xd2, 0x00, 0x64, 0x00, 0x10, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x0b,
+            0x00, 0x00, 0x08,
+        ];
+        assert_eq!(
+            decode_measurement(&first, &mut crank),
+            Some(Measurement {
+                power: Some(200),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            decode_measurement(&second, &mut crank),
+            Some(Measurement {
+                power: Some(210),
+                cadence: Some(60.0),
+                ..Default::default()
+            })
+        );
+        assert_eq!(crank_revolutions(&second), Some(0x0b));
+
+        // No new crank event with power still there keeps the cadence.
+        let coasting = [
+            0x35, 0x00, 0x0a, 0x00, 0x64, 0x00, 0x10, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x0b,
+            0x00, 0x00, 0x08,
+        ];
+        assert_eq!(
+            decode_measurement(&coasting, &mut crank).and_then(|m| m.cadence),
+            Some(60.0)
+        );
+        let stopped = [
+            0x35, 0x00, 0x00, 0x00, 0x64, 0x00, 0x10, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x0b,
+            0x00, 0x00, 0x08,
+        ];
+        assert_eq!(
+            decode_measurement(&stopped, &mut crank).and_then(|m| m.cadence),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn crank_counters_wrap() {
+        let mut crank = CrankState::default();
+        decode_measurement(
+            &[0x20, 0x00, 0x96, 0x00, 0xff, 0xff, 0x00, 0xfc],
+            &mut crank,
+        );
+        let measurement = decode_measurement(
+            &[0x20, 0x00, 0x96, 0x00, 0x01, 0x00, 0x00, 0x04],
+            &mut crank,
+        );
+        assert_eq!(measurement.and_then(|m| m.cadence), Some(60.0));
+    }
+
+    #[test]
+    fn measurement_without_crank_data() {
+        let mut crank = CrankState::default();
+        let data = [0x00, 0x00, 0x38, 0xff];
+        assert_eq!(
+            decode_measurement(&data, &mut crank),
+            Some(Measurement {
+                power: Some(-200),
+                ..Default::default()
+            })
+        );
+        assert_eq!(crank_revolutions(&data), None);
+        // The crank data flag is set but the value is cut short.
+        assert_eq!(
+            decode_measurement(&[0x20, 0x00, 0x96, 0x00, 0x01], &mut crank),
+            None
+        );
+    }
+
+    #[test]
+    fn control_point_requests() {
+        assert_eq!(CyclingPowerRequest::OffsetCompensation.encode(), [0x0c]);
+        assert_eq!(CyclingPowerRequest::RequestCrankLength.encode(), [0x05]);
+        assert_eq!(
+            CyclingPowerRequest::SetCrankLength(172.5).encode(),
+            [0x04, 0x59, 0x01]
+        );
+    }
+
+    #[test]
+    fn control_point_responses() {
+        let offset = CyclingPowerRequest::OffsetCompensation;
+        assert_eq!(
+            offset.decode_response(&[0x20, 0x0c, 0x01, 0xf6, 0xff]),
+            Some(Ok(CyclingPowerResponse::Offset(Some(-10))))
+        );
+        assert_eq!(
+            offset.decode_response(&[0x20, 0x0c, 0x01]),
+            Some(Ok(CyclingPowerResponse::Offset(None)))
+        );
+        assert_eq!(
+            offset.decode_response(&[0x20, 0x0c, 0x02]),
+            Some(Err(CyclingPowerError::NotSupported))
+        );
+        assert_eq!(
+            offset.decode_response(&[0x20, 0x0c, 0x04]),
+            Some(Err(CyclingPowerError::Failed))
+        );
+        // Answers to other requests are not ours.
+        assert_eq!(
+            offset.decode_response(&[0x20, 0x05, 0x01, 0x5a, 0x01]),
+            None
+        );
+        assert_eq!(offset.decode_response(&[0x0c, 0x20, 0x01]), None);
+
+        assert_eq!(
+            CyclingPowerRequest::RequestCrankLength
+                .decode_response(&[0x20, 0x05, 0x01, 0x5a, 0x01]),
+            Some(Ok(CyclingPowerResponse::CrankLength(173.0)))
+        );
+        let set = CyclingPowerRequest::SetCrankLength(175.0);
+        assert_eq!(
+            set.decode_response(&[0x20, 0x04, 0x01]),
+            Some(Ok(CyclingPowerResponse::CrankLength(175.0)))
+        );
+        assert_eq!(
+            set.decode_response(&[0x20, 0x04, 0x03]),
+            Some(Err(CyclingPowerError::InvalidParameter))
+        );
+    }
+}
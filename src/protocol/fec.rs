@@ -0,0 +1,397 @@
+//! ANT+ FE-C data pages tunnelled over Tacx's BLE service.
+//!
+//! Every page is wrapped in a regular ANT broadcast message, checksum
+//! included, so the trainer firmware can treat BLE like any other ANT link.
+
+use super::{
+    Measurement, Simulation, SpinDownEvent, SpinDownFailure, TrainerCommand, UserConfiguration,
+};
+
+const SYNC: u8 = 0xa4;
+const BROADCAST_DATA: u8 = 0x4e;
+const CHANNEL: u8 = 0x05;
+const PAYLOAD_LENGTH: u8 = 0x09;
+
+const CALIBRATION_REQUEST: u8 = 0x01;
+const CALIBRATION_PROGRESS: u8 = 0x02;
+const GENERAL_FE_DATA: u8 = 0x10;
+const SPECIFIC_TRAINER_DATA: u8 = 0x19;
+const BASIC_RESISTANCE: u8 = 0x30;
+const TARGET_POWER: u8 = 0x31;
+const WIND_RESISTANCE: u8 = 0x32;
+const TRACK_RESISTANCE: u8 = 0x33;
+const USER_CONFIGURATION: u8 = 0x37;
+
+const INVALID: u8 = 0xff;
+
+const SPIN_DOWN: u8 = 1 << 7;
+const SPEED_CONDITION_MASK: u8 = 0b1100_0000;
+const SPEED_TOO_LOW: u8 = 0b0100_0000;
+const SPEED_OK: u8 = 0b1000_0000;
+
+/// The general data page only carries the last byte of the distance, so the
+/// total has to be accumulated across pages.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FecState {
+    last_distance: Option<u8>,
+    distance: u32,
+}
+
+/// Wraps a data page into the ANT message the trainer expects.
+fn message(page: [u8; 8]) -> Vec<u8> {
+    let mut message = vec![SYNC, PAYLOAD_LENGTH, BROADCAST_DATA, CHANNEL];
+    message.extend(page);
+    message.push(message.iter().fold(0, |checksum, byte| checksum ^ byte));
+    message
+}
+
+pub fn encode(command: &TrainerCommand) -> Vec<Vec<u8>> {
+    match command {
+        TrainerCommand::TargetPower(power) => {
+            let [low, high] =
+                ((f64::from((*power).max(0)) * 4.0).min(65_534.0) as u16).to_le_bytes();
+            vec![message([
+                TARGET_POWER,
+                INVALID,
+                INVALID,
+                INVALID,
+                INVALID,
+                INVALID,
+                low,
+                high,
+            ])]
+        }
+        TrainerCommand::Resistance(percent) => {
+            let resistance = (percent.clamp(0.0, 100.0) * 2.0).round() as u8;
+            vec![message([
+                BASIC_RESISTANCE,
+                INVALID,
+                INVALID,
+                INVALID,
+                INVALID,
+                INVALID,
+                INVALID,
+                resistance,
+            ])]
+        }
+        TrainerCommand::Simulation(simulation) => simulation_pages(simulation),
+        TrainerCommand::UserConfiguration(configuration) => {
+            vec![user_configuration_page(configuration)]
+        }
+    }
+}
+
+fn simulation_pages(simulation: &Simulation) -> Vec<Vec<u8>> {
+    let coefficient = (simulation.wind_resistance * 100.0)
+        .round()
+        .clamp(0.0, 254.0) as u8;
+    let wind_speed = (simulation.wind_speed * 3.6 + 127.0)
+        .round()
+        .clamp(0.0, 254.0) as u8;
+    let drafting_factor = 100;
+    let grade = ((simulation.grade.clamp(-200.0, 200.0) + 200.0) * 100.0).round() as u16;
+    let [grade_low, grade_high] = grade.to_le_bytes();
+    let crr = (simulation.crr / 0.00005).round().clamp(0.0, 254.0) as u8;
+    vec![
+        message([
+            WIND_RESISTANCE,
+            INVALID,
+            INVALID,
+            INVALID,
+            INVALID,
+            coefficient,
+            wind_speed,
+            drafting_factor,
+        ]),
+        message([
+            TRACK_RESISTANCE,
+            INVALID,
+            INVALID,
+            INVALID,
+            INVALID,
+            grade_low,
+            grade_high,
+            crr,
+        ]),
+    ]
+}
+
+fn user_configuration_page(configuration: &UserConfiguration) -> Vec<u8> {
+    let [weight_low, weight_high] =
+        ((configuration.rider_weight * 100.0).round() as u16).to_le_bytes();
+    let bike_weight = ((configuration.bike_weight / 0.05).round() as u16).min(0x0fff);
+    let wheel_diameter = (configuration.wheel_diameter * 100.0)
+        .round()
+        .clamp(0.0, 254.0) as u8;
+    let wheel_offset = ((configuration.wheel_diameter * 1000.0).round() as u16 % 10) as u8;
+    message([
+        USER_CONFIGURATION,
+        weight_low,
+        weight_high,
+        INVALID,
+        wheel_offset | ((bike_weight & 0x0f) as u8) << 4,
+        (bike_weight >> 4) as u8,
+        wheel_diameter,
+        // Gear ratio is not known.
+        0x00,
+    ])
+}
+
+pub fn spin_down_request() -> Vec<u8> {
+    message([
+        CALIBRATION_REQUEST,
+        SPIN_DOWN,
+        0x00,
+        INVALID,
+        INVALID,
+        INVALID,
+        INVALID,
+        INVALID,
+    ])
+}
+
+/// Unwraps the data page from a message notified on the receive
+/// characteristic.
+fn page(data: &[u8]) -> Option<&[u8; 8]> {
+    let [SYNC, PAYLOAD_LENGTH, BROADCAST_DATA, _, page @ .., checksum] = data else {
+        return None;
+    };
+    let expected = data[..data.len() - 1]
+        .iter()
+        .fold(0, |checksum, byte| checksum ^ byte);
+    if expected != *checksum {
+        log::warn!("Dropping FE-C message with a bad checksum: {data:02x?}");
+        return None;
+    }
+    page.try_into().ok()
+}
+
+pub fn decode_spin_down(data: &[u8]) -> Option<SpinDownEvent> {
+    let page = page(data)?;
+    match page[0] {
+        CALIBRATION_PROGRESS if page[1] & SPIN_DOWN != 0 => match page[2] & SPEED_CONDITION_MASK {
+            SPEED_TOO_LOW => {
+                let target = f64::from(u16::from_le_bytes([page[4], page[5]])) * 3.6 / 1000.0;
+                // Only a single target is given, leave some room above it.
+                Some(SpinDownEvent::TargetSpeed {
+                    low: target,
+                    high: target * 1.1,
+                })
+            }
+            SPEED_OK => Some(SpinDownEvent::StopPedalling),
+            _ => None,
+        },
+        CALIBRATION_REQUEST => {
+            if page[1] & SPIN_DOWN != 0 {
+                Some(SpinDownEvent::Succeeded)
+            } else {
+                Some(SpinDownEvent::Failed(SpinDownFailure::Error))
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Decodes a message notified on the FE-C receive characteristic.
+pub fn decode_message(data: &[u8], state: &mut FecState) -> Option<Measurement> {
+    let page = page(data)?;
+    match page[0] {
+        GENERAL_FE_DATA => {
+            let distance = page[3];
+            if let Some(last_distance) = state.last_distance {
+                state.distance += u32::from(distance.wrapping_sub(last_distance));
+            }
+            state.last_distance = Some(distance);
+            let speed = u16::from_le_bytes([page[4], page[5]]);
+            Some(Measurement {
+                speed: Some(f64::from(speed) * 3.6 / 1000.0),
+                distance: Some(state.distance),
+                heart_rate: Some(page[6])
+                    .filter(|rate| *rate != INVALID && *rate != 0)
+                    .map(u16::from),
+                ..Default::default()
+            })
+        }
+        SPECIFIC_TRAINER_DATA => {
+            let power = u16::from_le_bytes([page[5], page[6] & 0x0f]);
+            Some(Measurement {
+                cadence: Some(page[2])
+                    .filter(|cadence| *cadence != INVALID)
+                    .map(f64::from),
+                power: Some(power)
+                    .filter(|power| *power != 0x0fff)
+                    .map(|power| power as i16),
+                ..Default::default()
+            })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_power_page() {
+        assert_eq!(
+            encode(&TrainerCommand::TargetPower(200)),
+            [[
+                0xa4, 0x09, 0x4e, 0x05, 0x31, 0xff, 0xff, 0xff, 0xff, 0xff, 0x20, 0x03, 0x0b
+            ]]
+        );
+    }
+
+    #[test]
+    fn basic_resistance_page() {
+        assert_eq!(
+            encode(&TrainerCommand::Resistance(37.5)),
+            [[
+                0xa4, 0x09, 0x4e, 0x05, 0x30, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x4b, 0x9d
+            ]]
+        );
+    }
+
+    #[test]
+    fn simulation_pages() {
+        let simulation = Simulation {
+            grade: -1.5,
+            ..Default::default()
+        };
+        assert_eq!(
+            encode(&TrainerCommand::Simulation(simulation)),
+            [
+                [
+                    0xa4, 0x09, 0x4e, 0x05, 0x32, 0xff, 0xff, 0xff, 0xff, 0x33, 0x7f, 0x64, 0xfc
+                ],
+                [
+                    0xa4, 0x09, 0x4e, 0x05, 0x33, 0xff, 0xff, 0xff, 0xff, 0x8a, 0x4d, 0x50, 0x42
+                ]
+            ]
+        );
+    }
+
+    #[test]
+    fn user_configuration_page() {
+        assert_eq!(
+            encode(&TrainerCommand::UserConfiguration(
+                UserConfiguration::default()
+            )),
+            [[
+                0xa4, 0x09, 0x4e, 0x05, 0x37, 0x4c, 0x1d, 0xff, 0x80, 0x0c, 0x46, 0x00, 0xb5
+            ]]
+        );
+    }
+
+    #[test]
+    fn general_data_accumulates_distance() {
+        let mut state = FecState::default();
+        let first = [
+            0xa4, 0x09, 0x4e, 0x05, 0x10, 0x19, 0x00, 0xfa, 0xe8, 0x03, 0x00, 0x24, 0xda,
+        ];
+        let second = [
+            0xa4, 0x09, 0x4e, 0x05, 0x10, 0x19, 0x00, 0x04, 0xe8, 0x03, 0xff, 0x24, 0xdb,
+        ];
+        assert_eq!(
+            decode_message(&first, &mut state),
+            Some(Measurement {
+                speed: Some(3.6),
+                distance: Some(0),
+                ..Default::default()
+            })
+        );
+        // The distance byte rolled over from 250 to 4.
+        assert_eq!(
+            decode_message(&second, &mut state),
+            Some(Measurement {
+                speed: Some(3.6),
+                distance: Some(10),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn trainer_data() {
+        let mut state = FecState::default();
+        let data = [
+            0xa4, 0x09, 0x4e, 0x05, 0x19, 0x01, 0x55, 0x00, 0x00, 0xe6, 0x30, 0x20, 0x5d,
+        ];
+        assert_eq!(
+            decode_message(&data, &mut state),
+            Some(Measurement {
+                power: Some(230),
+                cadence: Some(85.0),
+                ..Default::default()
+            })
+        );
+        let invalid = [
+            0xa4, 0x09, 0x4e, 0x05, 0x19, 0x00, 0xff, 0x00, 0x00, 0xff, 0xff, 0x20, 0x20,
+        ];
+        assert_eq!(
+            decode_message(&invalid, &mut state),
+            Some(Measurement::default())
+        );
+    }
+
+    #[test]
+    fn bad_checksum_is_dropped() {
+        let mut state = FecState::default();
+        let data = [
+            0xa4, 0x09, 0x4e, 0x05, 0x19, 0x01, 0x55, 0x00, 0x00, 0xe6, 0x30, 0x20, 0x5e,
+        ];
+        assert_eq!(decode_message(&data, &mut state), None);
+        assert_eq!(decode_message(&data[..12], &mut state), None);
+    }
+
+    #[test]
+    fn spin_down_request_page() {
+        assert_eq!(
+            spin_down_request(),
+            [
+                0xa4, 0x09, 0x4e, 0x05, 0x01, 0x80, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0x98
+            ]
+        );
+    }
+
+    #[test]
+    fn spin_down_progress() {
+        let too_slow = [
+            0xa4, 0x09, 0x4e, 0x05, 0x02, 0x80, 0x40, 0xff, 0x10, 0x27, 0xff, 0xff, 0xec,
+        ];
+        assert_eq!(
+            decode_spin_down(&too_slow),
+            Some(SpinDownEvent::TargetSpeed {
+                low: 36.0,
+                high: 36.0 * 1.1,
+            })
+        );
+        let fast_enough = [
+            0xa4, 0x09, 0x4e, 0x05, 0x02, 0x80, 0x80, 0xff, 0x10, 0x27, 0xff, 0xff, 0x2c,
+        ];
+        assert_eq!(
+            decode_spin_down(&fast_enough),
+            Some(SpinDownEvent::StopPedalling)
+        );
+        // Progress of a zero offset calibration is not ours.
+        let zero_offset = [
+            0xa4, 0x09, 0x4e, 0x05, 0x02, 0x40, 0x40, 0xff, 0x10, 0x27, 0xff, 0xff, 0x2c,
+        ];
+        assert_eq!(decode_spin_down(&zero_offset), None);
+    }
+
+    #[test]
+    fn spin_down_response() {
+        let succeeded = [
+            0xa4, 0x09, 0x4e, 0x05, 0x01, 0x80, 0x00, 0x14, 0x10, 0x00, 0x20, 0x4e, 0x0d,
+        ];
+        assert_eq!(decode_spin_down(&succeeded), Some(SpinDownEvent::Succeeded));
+        let failed = [
+            0xa4, 0x09, 0x4e, 0x05, 0x01, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0x18,
+        ];
+        assert_eq!(
+            decode_spin_down(&failed),
+            Some(SpinDownEvent::Failed(SpinDownFailure::Error))
+        );
+    }
+}
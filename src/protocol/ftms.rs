@@ -0,0 +1,182 @@
+use super::{
+    Measurement, Range, Reader, SpinDownEvent, SpinDownFailure, TrainerCapabilities, TrainerCommand,
+};
+
+const MORE_DATA: u16 = 1 << 0;
+const AVERAGE_SPEED: u16 = 1 << 1;
+const INSTANTANEOUS_CADENCE: u16 = 1 << 2;
+const AVERAGE_CADENCE: u16 = 1 << 3;
+const TOTAL_DISTANCE: u16 = 1 << 4;
+const RESISTANCE_LEVEL: u16 = 1 << 5;
+const INSTANTANEOUS_POWER: u16 = 1 << 6;
+const AVERAGE_POWER: u16 = 1 << 7;
+const EXPENDED_ENERGY: u16 = 1 << 8;
+const HEART_RATE: u16 = 1 << 9;
+
+const INCLINATION_TARGET: u32 = 1 << 1;
+const RESISTANCE_TARGET: u32 = 1 << 2;
+const POWER_TARGET: u32 = 1 << 3;
+const INDOOR_BIKE_SIMULATION: u32 = 1 << 13;
+
+/// Default resistance level range when the trainer does not report one,
+/// the widest the single byte parameter allows.
+const DEFAULT_RESISTANCE_RANGE: Range = Range {
+    min: 0.0,
+    max: 25.5,
+    increment: 0.1,
+};
+
+const REQUEST_CONTROL: u8 = 0x00;
+const SET_TARGET_RESISTANCE: u8 = 0x04;
+const SET_TARGET_POWER: u8 = 0x05;
+const SET_INDOOR_BIKE_SIMULATION: u8 = 0x11;
+const SPIN_DOWN_CONTROL: u8 = 0x13;
+const RESPONSE_CODE: u8 = 0x80;
+
+const START_SPIN_DOWN: u8 = 0x01;
+const SUCCESS: u8 = 0x01;
+const CONTROL_NOT_PERMITTED: u8 = 0x05;
+
+const SPIN_DOWN_STATUS: u8 = 0x14;
+const SPIN_DOWN_SUCCESS: u8 = 0x02;
+const SPIN_DOWN_ERROR: u8 = 0x03;
+const STOP_PEDALLING: u8 = 0x04;
+
+/// Writes to the control point are only accepted after taking control.
+pub fn request_control() -> Vec<u8> {
+    vec![REQUEST_CONTROL]
+}
+
+pub fn spin_down_request() -> Vec<u8> {
+    vec![SPIN_DOWN_CONTROL, START_SPIN_DOWN]
+}
+
+/// Decodes the control point response to a spin down request.
+pub fn decode_spin_down_response(data: &[u8]) -> Option<SpinDownEvent> {
+    let mut reader = Reader::new(data);
+    if reader.u8()? != RESPONSE_CODE || reader.u8()? != SPIN_DOWN_CONTROL {
+        return None;
+    }
+    match reader.u8()? {
+        SUCCESS => Some(SpinDownEvent::TargetSpeed {
+            low: f64::from(reader.u16()?) / 100.0,
+            high: f64::from(reader.u16()?) / 100.0,
+        }),
+        CONTROL_NOT_PERMITTED => Some(SpinDownEvent::Failed(SpinDownFailure::NotPermitted)),
+        _ => Some(SpinDownEvent::Failed(SpinDownFailure::Rejected)),
+    }
+}
+
+/// Decodes the spin down progress from a Fitness Machine Status value.
+pub fn decode_spin_down_status(data: &[u8]) -> Option<SpinDownEvent> {
+    match data {
+        [SPIN_DOWN_STATUS, SPIN_DOWN_SUCCESS, ..] => Some(SpinDownEvent::Succeeded),
+        [SPIN_DOWN_STATUS, SPIN_DOWN_ERROR, ..] => {
+            Some(SpinDownEvent::Failed(SpinDownFailure::Error))
+        }
+        [SPIN_DOWN_STATUS, STOP_PEDALLING, ..] => Some(SpinDownEvent::StopPedalling),
+        _ => None,
+    }
+}
+
+/// Decodes the target settings from Fitness Machine Feature (0x2ACC).
+pub fn decode_features(data: &[u8], capabilities: &mut TrainerCapabilities) -> Option<()> {
+    let mut reader = Reader::new(data);
+    reader.skip(4)?;
+    let target_settings = u32::from(reader.u16()?) | u32::from(reader.u16()?) << 16;
+    capabilities.power_target = target_settings & POWER_TARGET != 0;
+    capabilities.resistance_target = target_settings & RESISTANCE_TARGET != 0;
+    capabilities.simulation = target_settings & (INDOOR_BIKE_SIMULATION | INCLINATION_TARGET) != 0;
+    Some(())
+}
+
+/// Decodes one of the Supported ... Range characteristics, which all share
+/// the same layout and only differ in resolution.
+pub fn decode_range(data: &[u8], resolution: f64) -> Option<Range> {
+    let mut reader = Reader::new(data);
+    let min = f64::from(reader.i16()?) * resolution;
+    let max = f64::from(reader.i16()?) * resolution;
+    let increment = f64::from(reader.u16()?) * resolution;
+    (min <= max).then_some(Range {
+        min,
+        max,
+        increment,
+    })
+}
+
+pub fn encode(command: &TrainerCommand, capabilities: &TrainerCapabilities) -> Vec<Vec<u8>> {
+    match command {
+        TrainerCommand::TargetPower(power) => {
+            let mut value = vec![SET_TARGET_POWER];
+            value.extend(power.to_le_bytes());
+            vec![value]
+        }
+        TrainerCommand::Resistance(percent) => {
+            // Spread the percentage over the levels the trainer supports, the
+            // parameter has a 0.1 resolution.
+            let range = capabilities
+                .resistance_range
+                .unwrap_or(DEFAULT_RESISTANCE_RANGE);
+            let level = range.min + (range.max - range.min) * percent.clamp(0.0, 100.0) / 100.0;
+            let level = (range.snap(level) * 10.0).round().clamp(0.0, 255.0) as u8;
+            vec![vec![SET_TARGET_RESISTANCE, level]]
+        }
+        TrainerCommand::Simulation(simulation) => {
+            let mut value = vec![SET_INDOOR_BIKE_SIMULATION];
+            value.extend(((simulation.wind_speed * 1000.0).round() as i16).to_le_bytes());
+            value.extend(((simulation.grade * 100.0).round() as i16).to_le_bytes());
+            value.push((simulation.crr * 10_000.0).round().clamp(0.0, 255.0) as u8);
+            value.push(
+                (simulation.wind_resistance * 100.0)
+                    .round()
+                    .clamp(0.0, 255.0) as u8,
+            );
+            vec![value]
+        }
+        // FTMS has no notion of the rider, the simulation covers it.
+        TrainerCommand::UserConfiguration(_) => vec![],
+    }
+}
+
+/// Decodes an FTMS Indoor Bike Data (0x2AD2) value.
+///
+/// Fields are laid out in flag order, so everything before the last field we
+/// care about has to be walked even when it is not used.
+pub fn decode_indoor_bike_data(data: &[u8]) -> Option<Measurement> {
+    let mut reader = Reader::new(data);
+    let flags = reader.u16()?;
+    let mut measurement = Measurement::default();
+
+    // The instantaneous speed is present when "More Data" is *not* set.
+    if flags & MORE_DATA == 0 {
+        measurement.speed = Some(f64::from(reader.u16()?) / 100.0);
+    }
+    if flags & AVERAGE_SPEED != 0 {
+        reader.skip(2)?;
+    }
+    if flags & INSTANTANEOUS_CADENCE != 0 {
+        measurement.cadence = Some(f64::from(reader.u16()?) / 2.0);
+    }
+    if flags & AVERAGE_CADENCE != 0 {
+        reader.skip(2)?;
+    }
+    if flags & TOTAL_DISTANCE != 0 {
+        measurement.distance = Some(reader.u24()?);
+    }
+    if flags & RESISTANCE_LEVEL != 0 {
+        reader.skip(2)?;
+    }
+    if flags & INSTANTANEOUS_POWER != 0 {
+        measurement.power = Some(reader.i16()?);
+    }
+    if flags & AVERAGE_POWER != 0 {
+        reader.skip(2)?;
+    }
+    if flags & EXPENDED_ENERGY != 0 {
+        reader.skip(5)?;
+    }
+    if flags & HEART_RATE != 0 {
+        measurement.heart_rate = Some(reader.u8()?.into()).filter(|rate| *rate > 0);
+    }
+    Some(measurement)
+}
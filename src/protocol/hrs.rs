@@ -0,0 +1,18 @@
+use super::{Measurement, Reader};
+
+const FLAG_HEART_RATE_16_BIT: u8 = 0x01;
+
+/// Decodes a Heart Rate Measurement (0x2A37) value.
+pub fn decode_measurement(data: &[u8]) -> Option<Measurement> {
+    let mut reader = Reader::new(data);
+    let flags = reader.u8()?;
+    let heart_rate = if flags & FLAG_HEART_RATE_16_BIT != 0 {
+        reader.u16()?
+    } else {
+        reader.u8()?.into()
+    };
+    Some(Measurement {
+        heart_rate: Some(heart_rate),
+        ..Default::default()
+    })
+}
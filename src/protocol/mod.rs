@@ -0,0 +1,488 @@
+mod advertising;
+mod cps;
+mod fec;
+mod ftms;
+mod hrs;
+mod wahoo;
+
+use std::fmt::Display;
+
+pub use advertising::{AddressKind, Advertisement};
+pub use cps::{CyclingPowerError, CyclingPowerRequest, CyclingPowerResponse, SensorLocation};
+
+pub const CYCLING_POWER_MEASUREMENT: &str = "00002a63-0000-1000-8000-00805f9b34fb";
+pub const CYCLING_POWER_CONTROL_POINT: &str = "00002a66-0000-1000-8000-00805f9b34fb";
+pub const SENSOR_LOCATION: &str = "00002a5d-0000-1000-8000-00805f9b34fb";
+pub const INDOOR_BIKE_DATA: &str = "00002ad2-0000-1000-8000-00805f9b34fb";
+pub const HEART_RATE_MEASUREMENT: &str = "00002a37-0000-1000-8000-00805f9b34fb";
+pub const FITNESS_MACHINE_CONTROL_POINT: &str = "00002ad9-0000-1000-8000-00805f9b34fb";
+pub const FITNESS_MACHINE_STATUS: &str = "00002ada-0000-1000-8000-00805f9b34fb";
+pub const FITNESS_MACHINE_FEATURE: &str = "00002acc-0000-1000-8000-00805f9b34fb";
+pub const SUPPORTED_INCLINATION_RANGE: &str = "00002ad5-0000-1000-8000-00805f9b34fb";
+pub const SUPPORTED_RESISTANCE_LEVEL_RANGE: &str = "00002ad6-0000-1000-8000-00805f9b34fb";
+pub const SUPPORTED_POWER_RANGE: &str = "00002ad8-0000-1000-8000-00805f9b34fb";
+pub const FEC_RECEIVE: &str = "6e40fec2-b5a3-f393-e0a9-e50e24dcca9e";
+pub const FEC_TRANSMIT: &str = "6e40fec3-b5a3-f393-e0a9-e50e24dcca9e";
+pub const WAHOO_TRAINER: &str = "a026e005-0a7d-4ab3-97fa-f1500f9feb8b";
+
+/// The characteristics sensors stream their data on, see [`Decoder`].
+pub const MEASUREMENT_CHARACTERISTICS: [&str; 4] = [
+    CYCLING_POWER_MEASUREMENT,
+    INDOOR_BIKE_DATA,
+    HEART_RATE_MEASUREMENT,
+    FEC_RECEIVE,
+];
+
+/// A single decoded sample coming from a sensor. Fields the sensor did not
+/// report in this particular packet are left as `None`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Measurement {
+    /// Instantaneous power in watts.
+    pub power: Option<i16>,
+    /// Cadence in revolutions per minute.
+    pub cadence: Option<f64>,
+    /// Speed in km/h.
+    pub speed: Option<f64>,
+    /// Total distance reported by the sensor, in meters.
+    pub distance: Option<u32>,
+    /// Heart rate in beats per minute.
+    pub heart_rate: Option<u16>,
+}
+
+/// Turns raw characteristic values into [`Measurement`]s.
+///
+/// Some characteristics only carry cumulative counters, so the decoder keeps
+/// the previous sample of each one around to derive rates from them. Use one
+/// decoder per device.
+#[derive(Debug, Default)]
+pub struct Decoder {
+    crank: cps::CrankState,
+    fec: fec::FecState,
+}
+
+impl Decoder {
+    pub fn decode(&mut self, uuid: &str, data: &[u8]) -> Option<Measurement> {
+        match uuid {
+            CYCLING_POWER_MEASUREMENT => cps::decode_measurement(data, &mut self.crank),
+            INDOOR_BIKE_DATA => ftms::decode_indoor_bike_data(data),
+            HEART_RATE_MEASUREMENT => hrs::decode_measurement(data),
+            FEC_RECEIVE => fec::decode_message(data, &mut self.fec),
+            _ => None,
+        }
+    }
+}
+
+/// A counter carried by the value that only moves forward, when the
+/// characteristic has one.
+pub fn sequence_number(uuid: &str, data: &[u8]) -> Option<u16> {
+    match uuid {
+        CYCLING_POWER_MEASUREMENT => cps::crank_revolutions(data),
+        _ => None,
+    }
+}
+
+/// Outdoor riding conditions for the trainer to simulate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Simulation {
+    /// Road grade in percent.
+    pub grade: f64,
+    /// Coefficient of rolling resistance.
+    pub crr: f64,
+    /// Wind resistance coefficient (CdA times air density), in kg/m.
+    pub wind_resistance: f64,
+    /// Head wind speed in m/s, negative for a tail wind.
+    pub wind_speed: f64,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Self {
+            grade: 0.0,
+            crr: 0.004,
+            wind_resistance: 0.51,
+            wind_speed: 0.0,
+        }
+    }
+}
+
+/// Rider and bike details some trainers use to make simulation realistic.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UserConfiguration {
+    /// Rider weight in kg.
+    pub rider_weight: f64,
+    /// Bike weight in kg.
+    pub bike_weight: f64,
+    /// Wheel diameter in meters.
+    pub wheel_diameter: f64,
+}
+
+impl Default for UserConfiguration {
+    fn default() -> Self {
+        Self {
+            rider_weight: 75.0,
+            bike_weight: 10.0,
+            wheel_diameter: 0.7,
+        }
+    }
+}
+
+/// A request to change how the trainer resists.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrainerCommand {
+    /// ERG mode, holding the given power in watts.
+    TargetPower(i16),
+    /// A fixed resistance, as a percentage of the trainer's maximum.
+    Resistance(f64),
+    Simulation(Simulation),
+    UserConfiguration(UserConfiguration),
+}
+
+/// Progress reported by a trainer during a spin down calibration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpinDownEvent {
+    /// The rider has to bring the speed within this range, in km/h.
+    TargetSpeed {
+        low: f64,
+        high: f64,
+    },
+    /// The target speed was reached, the rider should stop pedalling and let
+    /// the flywheel coast.
+    StopPedalling,
+    Succeeded,
+    Failed(SpinDownFailure),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpinDownFailure {
+    /// The trainer cannot do a spin down over the protocol in use.
+    Unsupported,
+    /// Another app is controlling the trainer.
+    NotPermitted,
+    Rejected,
+    /// The spin down was attempted but did not produce a usable result.
+    Error,
+}
+
+impl Display for SpinDownFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpinDownFailure::Unsupported => write!(f, "This trainer can not be calibrated by bike"),
+            SpinDownFailure::NotPermitted => {
+                write!(f, "Another app is controlling the trainer")
+            }
+            SpinDownFailure::Rejected => write!(f, "The trainer refused to start the calibration"),
+            SpinDownFailure::Error => write!(
+                f,
+                "The trainer could not complete the calibration, make sure the wheel coasts freely"
+            ),
+        }
+    }
+}
+
+/// The ways a trainer can be told to resist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrainerMode {
+    /// Hold a target power.
+    Erg,
+    /// Hold a fixed resistance.
+    Resistance,
+    /// Follow a simulated road.
+    Simulation,
+}
+
+impl Display for TrainerMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrainerMode::Erg => write!(f, "ERG"),
+            TrainerMode::Resistance => write!(f, "Resistance"),
+            TrainerMode::Simulation => write!(f, "Simulation"),
+        }
+    }
+}
+
+/// The range of a target a trainer accepts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Range {
+    pub min: f64,
+    pub max: f64,
+    /// The smallest step between two targets, zero when unknown.
+    pub increment: f64,
+}
+
+impl Range {
+    pub fn clamp(&self, value: f64) -> f64 {
+        value.clamp(self.min, self.max)
+    }
+
+    /// Clamps the value and rounds it to the nearest step.
+    pub fn snap(&self, value: f64) -> f64 {
+        let value = self.clamp(value);
+        if self.increment > 0.0 {
+            let steps = ((value - self.min) / self.increment).round();
+            (self.min + steps * self.increment).min(self.max)
+        } else {
+            value
+        }
+    }
+}
+
+/// What a trainer reported it can do. Protocols that have no way to tell
+/// leave every mode enabled and the ranges unknown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrainerCapabilities {
+    pub power_target: bool,
+    pub resistance_target: bool,
+    pub simulation: bool,
+    /// Power targets in watts.
+    pub power_range: Option<Range>,
+    /// Resistance levels, in the trainer's own unitless scale.
+    pub resistance_range: Option<Range>,
+    /// Grades in percent.
+    pub inclination_range: Option<Range>,
+}
+
+impl Default for TrainerCapabilities {
+    fn default() -> Self {
+        Self {
+            power_target: true,
+            resistance_target: true,
+            simulation: true,
+            power_range: None,
+            resistance_range: None,
+            inclination_range: None,
+        }
+    }
+}
+
+impl TrainerCapabilities {
+    pub fn modes(&self) -> Vec<TrainerMode> {
+        [
+            (self.power_target, TrainerMode::Erg),
+            (self.resistance_target, TrainerMode::Resistance),
+            (self.simulation, TrainerMode::Simulation),
+        ]
+        .into_iter()
+        .filter_map(|(supported, mode)| supported.then_some(mode))
+        .collect()
+    }
+
+    /// Brings a command's targets within what the trainer accepts.
+    pub fn clamp(&self, command: &TrainerCommand) -> TrainerCommand {
+        match command {
+            TrainerCommand::TargetPower(power) => match self.power_range {
+                Some(range) => TrainerCommand::TargetPower(range.snap(f64::from(*power)) as i16),
+                None => TrainerCommand::TargetPower((*power).max(0)),
+            },
+            TrainerCommand::Resistance(percent) => {
+                TrainerCommand::Resistance(percent.clamp(0.0, 100.0))
+            }
+            TrainerCommand::Simulation(simulation) => TrainerCommand::Simulation(Simulation {
+                grade: self
+                    .inclination_range
+                    .map(|range| range.clamp(simulation.grade))
+                    .unwrap_or(simulation.grade),
+                ..*simulation
+            }),
+            TrainerCommand::UserConfiguration(_) => *command,
+        }
+    }
+}
+
+/// The ways bike knows to control a smart trainer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrainerProtocol {
+    Ftms,
+    FeC,
+    Wahoo,
+}
+
+impl TrainerProtocol {
+    /// Picks the protocol to use from the characteristics a trainer exposes.
+    /// Tacx trainers behave better over FE-C, so it wins over FTMS, and the
+    /// Wahoo protocol is only used when nothing standard is available.
+    pub fn from_characteristics<S: AsRef<str>>(characteristics: &[S]) -> Option<Self> {
+        let offers = |uuid: &str| {
+            characteristics
+                .iter()
+                .any(|characteristic| characteristic.as_ref().eq_ignore_ascii_case(uuid))
+        };
+        if offers(FEC_TRANSMIT) {
+            Some(TrainerProtocol::FeC)
+        } else if offers(FITNESS_MACHINE_CONTROL_POINT) {
+            Some(TrainerProtocol::Ftms)
+        } else if offers(WAHOO_TRAINER) {
+            Some(TrainerProtocol::Wahoo)
+        } else {
+            None
+        }
+    }
+
+    /// The characteristic commands are written to.
+    pub fn control_characteristic(&self) -> &'static str {
+        match self {
+            TrainerProtocol::Ftms => FITNESS_MACHINE_CONTROL_POINT,
+            TrainerProtocol::FeC => FEC_TRANSMIT,
+            TrainerProtocol::Wahoo => WAHOO_TRAINER,
+        }
+    }
+
+    /// The characteristic the trainer reports its data on.
+    pub fn data_characteristic(&self) -> &'static str {
+        match self {
+            TrainerProtocol::Ftms => INDOOR_BIKE_DATA,
+            TrainerProtocol::FeC => FEC_RECEIVE,
+            TrainerProtocol::Wahoo => CYCLING_POWER_MEASUREMENT,
+        }
+    }
+}
+
+/// The control state of one connected trainer.
+///
+/// Some protocols need earlier settings repeated along with later commands,
+/// Wahoo's simulation mode wants the rider weight every time for example, so
+/// commands go through here rather than straight to the protocol.
+#[derive(Debug, Clone)]
+pub struct Trainer {
+    protocol: TrainerProtocol,
+    user_configuration: UserConfiguration,
+    capabilities: TrainerCapabilities,
+}
+
+impl Trainer {
+    pub fn new(protocol: TrainerProtocol) -> Self {
+        Self {
+            protocol,
+            user_configuration: UserConfiguration::default(),
+            capabilities: TrainerCapabilities::default(),
+        }
+    }
+
+    pub fn protocol(&self) -> TrainerProtocol {
+        self.protocol
+    }
+
+    pub fn capabilities(&self) -> TrainerCapabilities {
+        self.capabilities
+    }
+
+    /// The characteristics to read after connecting to learn what the
+    /// trainer supports, see [`Trainer::read_capability`].
+    pub fn capability_characteristics(&self) -> &'static [&'static str] {
+        match self.protocol {
+            TrainerProtocol::Ftms => &[
+                FITNESS_MACHINE_FEATURE,
+                SUPPORTED_POWER_RANGE,
+                SUPPORTED_RESISTANCE_LEVEL_RANGE,
+                SUPPORTED_INCLINATION_RANGE,
+            ],
+            TrainerProtocol::FeC | TrainerProtocol::Wahoo => &[],
+        }
+    }
+
+    pub fn read_capability(&mut self, uuid: &str, data: &[u8]) {
+        let capabilities = &mut self.capabilities;
+        let decoded = match uuid {
+            FITNESS_MACHINE_FEATURE => ftms::decode_features(data, capabilities),
+            SUPPORTED_POWER_RANGE => {
+                ftms::decode_range(data, 1.0).map(|range| capabilities.power_range = Some(range))
+            }
+            SUPPORTED_RESISTANCE_LEVEL_RANGE => ftms::decode_range(data, 0.1)
+                .map(|range| capabilities.resistance_range = Some(range)),
+            SUPPORTED_INCLINATION_RANGE => ftms::decode_range(data, 0.1)
+                .map(|range| capabilities.inclination_range = Some(range)),
+            _ => None,
+        };
+        if decoded.is_none() {
+            log::warn!("Could not decode trainer capability {uuid}: {data:02x?}");
+        }
+    }
+
+    /// Writes that need to happen once after connecting, before any command.
+    pub fn handshake(&self) -> Vec<Vec<u8>> {
+        match self.protocol {
+            TrainerProtocol::Ftms => vec![ftms::request_control()],
+            TrainerProtocol::FeC => vec![],
+            TrainerProtocol::Wahoo => vec![wahoo::unlock()],
+        }
+    }
+
+    /// The write to the control characteristic that starts a spin down, if
+    /// the protocol supports one.
+    pub fn spin_down_request(&self) -> Option<Vec<u8>> {
+        match self.protocol {
+            TrainerProtocol::Ftms => Some(ftms::spin_down_request()),
+            TrainerProtocol::FeC => Some(fec::spin_down_request()),
+            TrainerProtocol::Wahoo => None,
+        }
+    }
+
+    /// The characteristics spin down progress is reported on.
+    pub fn spin_down_characteristics(&self) -> &'static [&'static str] {
+        match self.protocol {
+            TrainerProtocol::Ftms => &[FITNESS_MACHINE_CONTROL_POINT, FITNESS_MACHINE_STATUS],
+            TrainerProtocol::FeC => &[FEC_RECEIVE],
+            TrainerProtocol::Wahoo => &[],
+        }
+    }
+
+    pub fn decode_spin_down(&self, uuid: &str, data: &[u8]) -> Option<SpinDownEvent> {
+        match uuid {
+            FITNESS_MACHINE_CONTROL_POINT => ftms::decode_spin_down_response(data),
+            FITNESS_MACHINE_STATUS => ftms::decode_spin_down_status(data),
+            FEC_RECEIVE => fec::decode_spin_down(data),
+            _ => None,
+        }
+    }
+
+    /// Encodes a command into the writes, in order, that carry it out.
+    pub fn encode(&mut self, command: &TrainerCommand) -> Vec<Vec<u8>> {
+        if let TrainerCommand::UserConfiguration(configuration) = command {
+            self.user_configuration = *configuration;
+        }
+        let command = self.capabilities.clamp(command);
+        match self.protocol {
+            TrainerProtocol::Ftms => ftms::encode(&command, &self.capabilities),
+            TrainerProtocol::FeC => fec::encode(&command),
+            TrainerProtocol::Wahoo => wahoo::encode(&command, &self.user_configuration),
+        }
+    }
+}
+
+/// Little endian cursor over a characteristic value.
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let bytes = self.data.get(self.position..self.position + N)?;
+        self.position += N;
+        bytes.try_into().ok()
+    }
+
+    pub fn skip(&mut self, count: usize) -> Option<()> {
+        (self.position + count <= self.data.len()).then(|| self.position += count)
+    }
+
+    pub fn u8(&mut self) -> Option<u8> {
+        self.take::<1>().map(|[byte]| byte)
+    }
+
+    pub fn u16(&mut self) -> Option<u16> {
+        self.take().map(u16::from_le_bytes)
+    }
+
+    pub fn i16(&mut self) -> Option<i16> {
+        self.take().map(i16::from_le_bytes)
+    }
+
+    pub fn u24(&mut self) -> Option<u32> {
+        self.take::<3>()
+            .map(|[a, b, c]| u32::from_le_bytes([a, b, c, 0]))
+    }
+}
@@ -0,0 +1,144 @@
+//! Wahoo's legacy trainer control, used by older KICKR and Snap units.
+//!
+//! Commands go to a vendor characteristic inside the Cycling Power Service,
+//! power and cadence keep coming from the regular power measurement.
+
+use super::{TrainerCommand, UserConfiguration};
+
+const UNLOCK: u8 = 0x20;
+const SET_RESISTANCE_MODE: u8 = 0x40;
+const SET_ERG_MODE: u8 = 0x42;
+const SET_SIMULATION_MODE: u8 = 0x43;
+const SET_SIMULATION_GRADE: u8 = 0x46;
+const SET_SIMULATION_WIND_SPEED: u8 = 0x47;
+const SET_WHEEL_CIRCUMFERENCE: u8 = 0x48;
+
+/// The trainer ignores every command until it has been unlocked.
+pub fn unlock() -> Vec<u8> {
+    vec![UNLOCK, 0xee, 0xfc]
+}
+
+fn command(op_code: u8, values: &[u16]) -> Vec<u8> {
+    let mut command = vec![op_code];
+    values
+        .iter()
+        .for_each(|value| command.extend(value.to_le_bytes()));
+    command
+}
+
+pub fn encode(command: &TrainerCommand, user_configuration: &UserConfiguration) -> Vec<Vec<u8>> {
+    match command {
+        TrainerCommand::TargetPower(power) => {
+            vec![self::command(SET_ERG_MODE, &[(*power).max(0) as u16])]
+        }
+        TrainerCommand::Resistance(percent) => {
+            // Resistance mode counts down from fully open.
+            let resistance = (1.0 - percent.clamp(0.0, 100.0) / 100.0) * 16_383.0;
+            vec![self::command(
+                SET_RESISTANCE_MODE,
+                &[resistance.round() as u16],
+            )]
+        }
+        TrainerCommand::Simulation(simulation) => {
+            let weight = user_configuration.rider_weight + user_configuration.bike_weight;
+            let grade = (simulation.grade / 100.0).clamp(-1.0, 1.0);
+            let wind_speed = (simulation.wind_speed + 32.768).clamp(0.0, 65.535);
+            vec![
+                self::command(
+                    SET_SIMULATION_MODE,
+                    &[
+                        (weight * 100.0).round() as u16,
+                        (simulation.crr * 10_000.0).round() as u16,
+                        (simulation.wind_resistance * 1000.0).round() as u16,
+                    ],
+                ),
+                self::command(
+                    SET_SIMULATION_GRADE,
+                    &[((grade + 1.0) * 65_535.0 / 2.0).round() as u16],
+                ),
+                self::command(
+                    SET_SIMULATION_WIND_SPEED,
+                    &[(wind_speed * 1000.0).round() as u16],
+                ),
+            ]
+        }
+        TrainerCommand::UserConfiguration(configuration) => {
+            // Circumference in tenths of a millimeter.
+            let circumference = configuration.wheel_diameter * std::f64::consts::PI * 10_000.0;
+            vec![self::command(
+                SET_WHEEL_CIRCUMFERENCE,
+                &[circumference.round().min(f64::from(u16::MAX)) as u16],
+            )]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Simulation;
+
+    fn encode(command: TrainerCommand) -> Vec<Vec<u8>> {
+        super::encode(&command, &UserConfiguration::default())
+    }
+
+    #[test]
+    fn unlock_command() {
+        assert_eq!(unlock(), [0x20, 0xee, 0xfc]);
+    }
+
+    #[test]
+    fn erg_mode() {
+        assert_eq!(
+            encode(TrainerCommand::TargetPower(250)),
+            [[0x42, 0xfa, 0x00]]
+        );
+        assert_eq!(
+            encode(TrainerCommand::TargetPower(-5)),
+            [[0x42, 0x00, 0x00]]
+        );
+    }
+
+    #[test]
+    fn resistance_mode() {
+        assert_eq!(
+            encode(TrainerCommand::Resistance(25.0)),
+            [[0x40, 0xff, 0x2f]]
+        );
+        assert_eq!(
+            encode(TrainerCommand::Resistance(0.0)),
+            [[0x40, 0xff, 0x3f]]
+        );
+        assert_eq!(
+            encode(TrainerCommand::Resistance(100.0)),
+            [[0x40, 0x00, 0x00]]
+        );
+    }
+
+    #[test]
+    fn simulation_mode() {
+        let simulation = Simulation {
+            grade: 5.0,
+            wind_speed: 2.0,
+            ..Default::default()
+        };
+        assert_eq!(
+            encode(TrainerCommand::Simulation(simulation)),
+            [
+                vec![0x43, 0x34, 0x21, 0x28, 0x00, 0xfe, 0x01],
+                vec![0x46, 0x66, 0x86],
+                vec![0x47, 0xd0, 0x87],
+            ]
+        );
+    }
+
+    #[test]
+    fn wheel_circumference() {
+        assert_eq!(
+            encode(TrainerCommand::UserConfiguration(
+                UserConfiguration::default()
+            )),
+            [[0x48, 0xe7, 0x55]]
+        );
+    }
+}
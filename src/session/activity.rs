@@ -0,0 +1,43 @@
+//! A finished ride, with everything needed to save or export it.
+
+use super::{Lap, PowerSummary, Record, Totals};
+
+/// What a sensor measured during the ride.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SensorKind {
+    /// A smart trainer.
+    FitnessEquipment,
+    BikePower,
+    HeartRate,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sensor {
+    pub name: String,
+    /// Empty when unknown.
+    pub brand: String,
+    pub kind: SensorKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Activity {
+    /// Seconds since the Unix epoch.
+    pub start: i64,
+    pub totals: Totals,
+    pub records: Vec<Record>,
+    pub laps: Vec<Lap>,
+    pub sensors: Vec<Sensor>,
+    /// Functional Threshold Power at the time of the ride, in watts.
+    pub ftp: Option<f64>,
+}
+
+impl Activity {
+    /// Seconds since the Unix epoch.
+    pub fn end(&self) -> i64 {
+        self.start + self.totals.elapsed.round() as i64
+    }
+
+    pub fn power_summary(&self) -> Option<PowerSummary> {
+        PowerSummary::new(&self.records, self.ftp)
+    }
+}
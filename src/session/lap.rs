@@ -0,0 +1,140 @@
+//! Laps split a ride into parts, each summed up on its own.
+
+use super::{Record, TimeInZones, Totals, ZoneSettings};
+
+/// What ended a lap.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LapTrigger {
+    /// The rider asked for it.
+    #[default]
+    Manual,
+    Distance,
+    Time,
+    /// The ride was finished.
+    SessionEnd,
+}
+
+/// Laps the session starts by itself.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum AutoLap {
+    #[default]
+    Off,
+    /// Every so many meters.
+    Distance(f64),
+    /// Every so many seconds of elapsed time.
+    Time(f64),
+}
+
+/// The average and maximum of a metric, `None` when it was never recorded.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Stat {
+    pub average: Option<f64>,
+    pub maximum: Option<f64>,
+}
+
+impl Stat {
+    pub fn new(records: &[Record], value: fn(&Record) -> Option<f64>) -> Self {
+        let values = records.iter().filter_map(value).collect::<Vec<_>>();
+        if values.is_empty() {
+            return Self::default();
+        }
+        Self {
+            average: Some(values.iter().sum::<f64>() / values.len() as f64),
+            maximum: values.iter().copied().reduce(f64::max),
+        }
+    }
+}
+
+/// Where the session was when a lap started.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(super) struct LapStart {
+    pub timestamp: i64,
+    pub totals: Totals,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Lap {
+    /// Seconds since the Unix epoch.
+    pub start_timestamp: i64,
+    /// Seconds into the ride the lap started.
+    pub start_elapsed: f64,
+    /// The totals of the lap alone.
+    pub totals: Totals,
+    pub trigger: LapTrigger,
+    /// Watts.
+    pub power: Stat,
+    /// Beats per minute.
+    pub heart_rate: Stat,
+    /// Revolutions per minute.
+    pub cadence: Stat,
+    /// km/h.
+    pub speed: Stat,
+    pub time_in_zones: TimeInZones,
+}
+
+impl Lap {
+    /// Sums up a lap from the `records` it holds, and the session totals at
+    /// its start and end.
+    pub(super) fn new(
+        start: &LapStart,
+        end: &Totals,
+        records: &[Record],
+        trigger: LapTrigger,
+        zones: &ZoneSettings,
+    ) -> Self {
+        Self {
+            start_timestamp: start.timestamp,
+            start_elapsed: start.totals.elapsed,
+            totals: Totals {
+                elapsed: end.elapsed - start.totals.elapsed,
+                moving_time: end.moving_time - start.totals.moving_time,
+                distance: end.distance - start.totals.distance,
+                work: end.work - start.totals.work,
+            },
+            trigger,
+            ..Default::default()
+        }
+        .with_stats(records, zones)
+    }
+
+    /// Puts a saved lap back together, its averages, maxima and time in
+    /// `zones` worked out again from the `records` of the whole ride.
+    pub fn restore(
+        start_timestamp: i64,
+        start_elapsed: f64,
+        totals: Totals,
+        trigger: LapTrigger,
+        records: &[Record],
+        zones: &ZoneSettings,
+    ) -> Self {
+        let lap = Self {
+            start_timestamp,
+            start_elapsed,
+            totals,
+            trigger,
+            ..Default::default()
+        };
+        let records = lap.records(records);
+        lap.with_stats(records, zones)
+    }
+
+    fn with_stats(self, records: &[Record], zones: &ZoneSettings) -> Self {
+        Self {
+            time_in_zones: TimeInZones::new(records, zones),
+            power: Stat::new(records, |record| record.power.map(f64::from)),
+            heart_rate: Stat::new(records, |record| record.heart_rate.map(f64::from)),
+            cadence: Stat::new(records, |record| record.cadence),
+            speed: Stat::new(records, |record| record.speed),
+            ..self
+        }
+    }
+
+    /// The part of the ride's `records` that belongs to the lap.
+    pub fn records<'a>(&self, records: &'a [Record]) -> &'a [Record] {
+        // Leave room for rounding, the last record is right at the end.
+        let end = self.start_elapsed + self.totals.elapsed + 1e-6;
+        let first = records.partition_point(|record| record.elapsed <= self.start_elapsed);
+        let last = records.partition_point(|record| record.elapsed <= end);
+        &records[first..last.max(first)]
+    }
+}
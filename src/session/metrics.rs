@@ -0,0 +1,225 @@
+//! Power based training metrics, from the 1 Hz records of a ride.
+
+use super::Record;
+
+/// Normalized Power smooths power over this many seconds.
+const NORMALIZED_WINDOW: usize = 30;
+
+/// The durations, in seconds, best efforts are looked for over.
+pub const BEST_EFFORTS: [usize; 6] = [5, 60, 300, 600, 1200, 3600];
+
+fn power(record: &Record) -> f64 {
+    record.power.map(f64::from).unwrap_or_default().max(0.0)
+}
+
+fn has_power(records: &[Record]) -> bool {
+    records.iter().any(|record| record.power.is_some())
+}
+
+/// Average power over the last `seconds` records, seconds without power
+/// counting as zero.
+pub fn rolling_average(records: &[Record], seconds: usize) -> Option<f64> {
+    let window = &records[records.len().saturating_sub(seconds)..];
+    has_power(window).then(|| window.iter().map(power).sum::<f64>() / window.len() as f64)
+}
+
+/// The highest average power held for `seconds` in a row, `None` when the
+/// ride is shorter or carries no power.
+pub fn best_power(records: &[Record], seconds: usize) -> Option<f64> {
+    if seconds == 0 || records.len() < seconds || !has_power(records) {
+        return None;
+    }
+    let mut sum = records[..seconds].iter().map(power).sum::<f64>();
+    let mut best = sum;
+    for (entering, leaving) in records[seconds..].iter().zip(records) {
+        sum += power(entering) - power(leaving);
+        best = best.max(sum);
+    }
+    Some(best / seconds as f64)
+}
+
+/// Normalized Power, `None` for rides shorter than its smoothing window.
+pub fn normalized_power(records: &[Record]) -> Option<f64> {
+    if records.len() < NORMALIZED_WINDOW || !has_power(records) {
+        return None;
+    }
+    let powers = records.iter().map(power).collect::<Vec<_>>();
+    let windows = powers.windows(NORMALIZED_WINDOW);
+    let count = windows.len() as f64;
+    let mean = windows
+        .map(|window| (window.iter().sum::<f64>() / NORMALIZED_WINDOW as f64).powi(4))
+        .sum::<f64>()
+        / count;
+    Some(mean.powf(0.25))
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PowerSummary {
+    /// Watts.
+    pub average: f64,
+    /// Watts.
+    pub maximum: f64,
+    /// Watts.
+    pub normalized: Option<f64>,
+    pub intensity_factor: Option<f64>,
+    pub training_stress_score: Option<f64>,
+    pub variability_index: Option<f64>,
+    pub kilojoules: f64,
+}
+
+impl PowerSummary {
+    /// Sums up `records`, `None` when none of them carries power. Intensity
+    /// and stress need the rider's FTP, in watts.
+    pub fn new(records: &[Record], ftp: Option<f64>) -> Option<Self> {
+        if !has_power(records) {
+            return None;
+        }
+        let total = records.iter().map(power).sum::<f64>();
+        let average = total / records.len() as f64;
+        let normalized = normalized_power(records);
+        let ftp = ftp.filter(|ftp| *ftp > 0.0);
+        let intensity_factor = normalized
+            .zip(ftp)
+            .map(|(normalized, ftp)| normalized / ftp);
+        // Records are a second apart, so their count is the duration.
+        let seconds = records.len() as f64;
+        let training_stress_score =
+            normalized
+                .zip(intensity_factor)
+                .zip(ftp)
+                .map(|((normalized, intensity), ftp)| {
+                    seconds * normalized * intensity / (ftp * 3600.0) * 100.0
+                });
+        Some(Self {
+            average,
+            maximum: records.iter().map(power).fold(0.0, f64::max),
+            normalized,
+            intensity_factor,
+            training_stress_score,
+            variability_index: normalized
+                .filter(|_| average > 0.0)
+                .map(|normalized| normalized / average),
+            kilojoules: total / 1000.0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(powers: &[Option<i16>]) -> Vec<Record> {
+        powers
+            .iter()
+            .enumerate()
+            .map(|(second, power)| Record {
+                timestamp: 1_700_000_000 + second as i64,
+                elapsed: second as f64,
+                power: *power,
+                cadence: None,
+                heart_rate: None,
+                speed: None,
+                distance: 0.0,
+                target_power: None,
+            })
+            .collect()
+    }
+
+    fn steady(power: i16, seconds: usize) -> Vec<Option<i16>> {
+        vec![Some(power); seconds]
+    }
+
+    fn assert_close(value: Option<f64>, expected: f64) {
+        let value = value.expect("a value");
+        assert!(
+            (value - expected).abs() < 1e-3,
+            "{value} is not close to {expected}"
+        );
+    }
+
+    #[test]
+    fn steady_ride() {
+        let summary = PowerSummary::new(&records(&steady(200, 60)), Some(250.0)).unwrap();
+        assert_eq!(summary.average, 200.0);
+        assert_eq!(summary.maximum, 200.0);
+        assert_close(summary.normalized, 200.0);
+        assert_close(summary.intensity_factor, 0.8);
+        // 60 s at IF 0.8: 60 × 200 × 0.8 / (250 × 3600) × 100.
+        assert_close(summary.training_stress_score, 1.0667);
+        assert_close(summary.variability_index, 1.0);
+        assert_eq!(summary.kilojoules, 12.0);
+    }
+
+    #[test]
+    fn variable_ride() {
+        let powers = [steady(100, 30), steady(300, 30)].concat();
+        let summary = PowerSummary::new(&records(&powers), Some(250.0)).unwrap();
+        assert_eq!(summary.average, 200.0);
+        assert_eq!(summary.maximum, 300.0);
+        // The 31 rolling averages climb from 100 W to 300 W in even steps,
+        // NP is the fourth root of the mean of their fourth powers.
+        assert_close(summary.normalized, 223.0695);
+        assert_close(summary.intensity_factor, 0.8923);
+        assert_close(summary.training_stress_score, 1.3269);
+        assert_close(summary.variability_index, 1.1153);
+    }
+
+    #[test]
+    fn without_ftp() {
+        let summary = PowerSummary::new(&records(&steady(200, 60)), None).unwrap();
+        assert_close(summary.normalized, 200.0);
+        assert_eq!(summary.intensity_factor, None);
+        assert_eq!(summary.training_stress_score, None);
+        let summary = PowerSummary::new(&records(&steady(200, 60)), Some(0.0)).unwrap();
+        assert_eq!(summary.intensity_factor, None);
+    }
+
+    #[test]
+    fn short_ride_has_no_normalized_power() {
+        let summary = PowerSummary::new(&records(&steady(150, 29)), Some(250.0)).unwrap();
+        assert_eq!(summary.average, 150.0);
+        assert_eq!(summary.normalized, None);
+        assert_eq!(summary.intensity_factor, None);
+        assert_eq!(summary.training_stress_score, None);
+        assert_eq!(summary.variability_index, None);
+    }
+
+    #[test]
+    fn gaps_count_as_zero() {
+        let summary =
+            PowerSummary::new(&records(&[Some(100), None, Some(300)]), Some(250.0)).unwrap();
+        assert_close(Some(summary.average), 133.3333);
+        assert_eq!(summary.maximum, 300.0);
+        assert_eq!(summary.kilojoules, 0.4);
+        // Negative power is clamped as well.
+        let summary = PowerSummary::new(&records(&[Some(-20), Some(40)]), None).unwrap();
+        assert_eq!(summary.average, 20.0);
+    }
+
+    #[test]
+    fn no_power() {
+        assert_eq!(PowerSummary::new(&[], Some(250.0)), None);
+        assert_eq!(PowerSummary::new(&records(&[None; 60]), Some(250.0)), None);
+        assert_eq!(normalized_power(&records(&[None; 60])), None);
+        assert_eq!(rolling_average(&[], 3), None);
+    }
+
+    #[test]
+    fn best_efforts() {
+        let records = records(&[Some(100), Some(200), Some(300), None, Some(400)]);
+        assert_eq!(best_power(&records, 1), Some(400.0));
+        assert_eq!(best_power(&records, 2), Some(250.0));
+        assert_eq!(best_power(&records, 5), Some(200.0));
+        assert_eq!(best_power(&records, 6), None);
+        assert_eq!(best_power(&records, 0), None);
+        assert_eq!(best_power(&[], 5), None);
+    }
+
+    #[test]
+    fn rolling_average_of_the_last_seconds() {
+        let records = records(&[Some(100), None, Some(300), Some(200)]);
+        assert_close(rolling_average(&records, 3), 166.6667);
+        assert_eq!(rolling_average(&records, 10), Some(150.0));
+        assert_eq!(rolling_average(&records[..2], 1), None);
+    }
+}
@@ -0,0 +1,477 @@
+//! The ride itself: what state it is in, what was recorded and the running
+//! totals, fed by the measurements of the connected sensors.
+
+mod activity;
+mod lap;
+mod metrics;
+mod physics;
+mod ride;
+mod zones;
+
+use std::fmt::Display;
+
+use crate::protocol::Measurement;
+
+use lap::LapStart;
+
+pub use activity::{Activity, Sensor, SensorKind};
+pub use lap::{AutoLap, Lap, LapTrigger, Stat};
+pub use metrics::{BEST_EFFORTS, PowerSummary, best_power, rolling_average};
+pub use physics::{RiderModel, VirtualSpeed};
+pub use ride::{MetricState, Ride, RideState};
+pub use zones::{TimeInZones, ZoneModel, ZoneSettings, Zones};
+
+/// Timestamps are in microseconds, as `glib::monotonic_time` returns them.
+const SECOND: f64 = 1_000_000.0;
+/// A value older than this is no longer shown nor recorded.
+const STALE_AFTER: i64 = 5_000_000;
+/// Below this speed, in km/h, the rider is considered stopped.
+const STOPPED_SPEED: f64 = 2.0;
+/// How long the rider has to be stopped before the ride pauses by itself.
+const AUTO_PAUSE_AFTER: i64 = 3_000_000;
+/// Share of the energy burned that makes it to the pedals. Riders are about
+/// 24 % efficient, which makes kilocalories and kilojoules about equal.
+const GROSS_EFFICIENCY: f64 = 0.24;
+const JOULES_PER_KILOCALORIE: f64 = 4184.0;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    #[default]
+    Idle,
+    Riding,
+    /// Paused because the rider stopped, resumes as soon as they go again.
+    AutoPaused,
+    /// Paused by the rider.
+    Paused,
+    Finished,
+}
+
+impl Display for SessionState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionState::Idle => write!(f, "Ready"),
+            SessionState::Riding => write!(f, "Riding"),
+            SessionState::AutoPaused => write!(f, "Auto-paused"),
+            SessionState::Paused => write!(f, "Paused"),
+            SessionState::Finished => write!(f, "Finished"),
+        }
+    }
+}
+
+/// Where the speed of the ride comes from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SpeedSource {
+    /// The sensors, falling back to [`VirtualSpeed`] when none reports speed.
+    #[default]
+    Auto,
+    Sensor,
+    /// Always [`VirtualSpeed`], for trainers whose speed can't be trusted.
+    Virtual,
+}
+
+/// One second of the ride.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Record {
+    /// Seconds since the Unix epoch.
+    pub timestamp: i64,
+    /// Seconds since the start, pauses included.
+    pub elapsed: f64,
+    pub power: Option<i16>,
+    pub cadence: Option<f64>,
+    pub heart_rate: Option<u16>,
+    /// Speed in km/h.
+    pub speed: Option<f64>,
+    /// Distance since the start, in meters.
+    pub distance: f64,
+    /// The power the trainer was told to hold, in watts.
+    pub target_power: Option<i16>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Totals {
+    /// Seconds since the start, pauses included.
+    pub elapsed: f64,
+    /// Seconds spent riding.
+    pub moving_time: f64,
+    /// Meters.
+    pub distance: f64,
+    /// Joules delivered to the pedals.
+    pub work: f64,
+}
+
+impl Totals {
+    pub fn kilojoules(&self) -> f64 {
+        self.work / 1000.0
+    }
+
+    pub fn calories(&self) -> f64 {
+        self.work / GROSS_EFFICIENCY / JOULES_PER_KILOCALORIE
+    }
+}
+
+/// The latest value of one metric along with when it arrived.
+#[derive(Debug, Clone, Copy)]
+struct Sample<T> {
+    value: T,
+    at: i64,
+}
+
+/// The freshest value of every metric, from whichever sensor sent it.
+#[derive(Debug, Default, Clone, Copy)]
+struct Latest {
+    power: Option<Sample<i16>>,
+    cadence: Option<Sample<f64>>,
+    heart_rate: Option<Sample<u16>>,
+    speed: Option<Sample<f64>>,
+}
+
+fn fresh<T: Copy>(sample: Option<Sample<T>>, now: i64) -> Option<T> {
+    sample
+        .filter(|sample| now - sample.at <= STALE_AFTER)
+        .map(|sample| sample.value)
+}
+
+/// The current value of every metric, `None` when it is missing or stale.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Current {
+    pub power: Option<i16>,
+    pub cadence: Option<f64>,
+    pub heart_rate: Option<u16>,
+    /// Speed in km/h.
+    pub speed: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+pub struct Session {
+    state: SessionState,
+    auto_pause: bool,
+    latest: Latest,
+    /// When the rider was first seen stopped, while riding.
+    stopped_since: Option<i64>,
+    last_tick: Option<i64>,
+    /// When the ride was started, in seconds since the Unix epoch.
+    started: Option<i64>,
+    totals: Totals,
+    records: Vec<Record>,
+    /// The laps ridden so far, the current one left out.
+    laps: Vec<Lap>,
+    /// Where the current lap started, while riding.
+    lap_start: Option<LapStart>,
+    auto_lap: AutoLap,
+    speed_source: SpeedSource,
+    virtual_speed: VirtualSpeed,
+    /// When the virtual speed was last moved forward.
+    last_virtual_step: Option<i64>,
+    target_power: Option<i16>,
+    /// What the time in zones of each lap is worked out with.
+    zone_settings: ZoneSettings,
+}
+
+impl Session {
+    pub fn new(auto_pause: bool) -> Self {
+        Self {
+            auto_pause,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_auto_lap(&mut self, auto_lap: AutoLap) {
+        self.auto_lap = auto_lap;
+    }
+
+    pub fn zone_settings(&self) -> &ZoneSettings {
+        &self.zone_settings
+    }
+
+    /// Counts for the laps that end from now on.
+    pub fn set_zone_settings(&mut self, zone_settings: ZoneSettings) {
+        self.zone_settings = zone_settings;
+    }
+
+    pub fn set_speed_source(&mut self, speed_source: SpeedSource) {
+        self.speed_source = speed_source;
+    }
+
+    /// Keeps the grade, which follows the trainer rather than the rider.
+    pub fn set_rider_model(&mut self, model: RiderModel) {
+        let grade = self.virtual_speed.model.grade;
+        self.virtual_speed.model = RiderModel { grade, ..model };
+    }
+
+    /// The power the trainer holds in ERG mode, `None` outside of it.
+    pub fn set_target_power(&mut self, target_power: Option<i16>) {
+        self.target_power = target_power;
+    }
+
+    /// The grade the trainer simulates, in percent.
+    pub fn set_grade(&mut self, grade: f64) {
+        self.virtual_speed.model.grade = grade;
+    }
+
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
+
+    /// When the ride was started, in seconds since the Unix epoch.
+    pub fn started(&self) -> Option<i64> {
+        self.started
+    }
+
+    pub fn totals(&self) -> Totals {
+        self.totals
+    }
+
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// The laps done so far. Once the ride is finished, all of them.
+    pub fn laps(&self) -> &[Lap] {
+        &self.laps
+    }
+
+    /// The records of the lap being ridden.
+    pub fn lap_records(&self) -> &[Record] {
+        match &self.lap_start {
+            Some(start) => self.lap_records_since(start),
+            None => &[],
+        }
+    }
+
+    /// The number of the lap being ridden, counting from one. 0 before the
+    /// start and once finished.
+    pub fn lap(&self) -> usize {
+        match self.lap_start {
+            Some(_) => self.laps.len() + 1,
+            None => 0,
+        }
+    }
+
+    /// Seconds since the current lap started.
+    pub fn lap_time(&self) -> f64 {
+        self.lap_start
+            .map(|start| self.totals.elapsed - start.totals.elapsed)
+            .unwrap_or_default()
+    }
+
+    pub fn start(&mut self, now: i64, timestamp: i64) {
+        if self.state == SessionState::Idle {
+            self.state = SessionState::Riding;
+            self.last_tick = Some(now);
+            self.started = Some(timestamp);
+            self.lap_start = Some(LapStart {
+                timestamp,
+                totals: self.totals,
+            });
+        }
+    }
+
+    /// Ends the current lap and starts the next one.
+    pub fn new_lap(&mut self, now: i64, timestamp: i64) {
+        if matches!(self.state, SessionState::Idle | SessionState::Finished) {
+            return;
+        }
+        self.advance(now);
+        self.end_lap(LapTrigger::Manual, timestamp);
+    }
+
+    fn end_lap(&mut self, trigger: LapTrigger, timestamp: i64) {
+        let Some(start) = self.lap_start.take() else {
+            return;
+        };
+        let lap = Lap::new(
+            &start,
+            &self.totals,
+            self.lap_records_since(&start),
+            trigger,
+            &self.zone_settings,
+        );
+        log::debug!("Lap {} ended: {lap:?}", self.laps.len() + 1);
+        self.laps.push(lap);
+        if trigger != LapTrigger::SessionEnd {
+            self.lap_start = Some(LapStart {
+                timestamp,
+                totals: self.totals,
+            });
+        }
+    }
+
+    fn lap_records_since(&self, start: &LapStart) -> &[Record] {
+        let first = self
+            .records
+            .partition_point(|record| record.elapsed <= start.totals.elapsed);
+        &self.records[first..]
+    }
+
+    /// Starts a new lap once the current one is long enough.
+    fn auto_lap(&mut self, timestamp: i64) {
+        let Some(start) = self.lap_start else {
+            return;
+        };
+        let trigger = match self.auto_lap {
+            AutoLap::Distance(distance)
+                if distance > 0.0 && self.totals.distance - start.totals.distance >= distance =>
+            {
+                LapTrigger::Distance
+            }
+            AutoLap::Time(time) if time > 0.0 && self.lap_time() >= time => LapTrigger::Time,
+            _ => return,
+        };
+        self.end_lap(trigger, timestamp);
+    }
+
+    /// Pauses, or resumes a paused ride.
+    pub fn toggle_pause(&mut self) {
+        self.state = match self.state {
+            SessionState::Riding | SessionState::AutoPaused => SessionState::Paused,
+            SessionState::Paused => SessionState::Riding,
+            state => state,
+        };
+        self.stopped_since = None;
+    }
+
+    pub fn finish(&mut self, now: i64, timestamp: i64) {
+        if matches!(self.state, SessionState::Idle | SessionState::Finished) {
+            return;
+        }
+        self.advance(now);
+        self.end_lap(LapTrigger::SessionEnd, timestamp);
+        self.state = SessionState::Finished;
+    }
+
+    /// Takes in a measurement from any sensor. Only the fields it carries
+    /// replace what is known.
+    pub fn update(&mut self, measurement: &Measurement, now: i64) {
+        if let Some(power) = measurement.power {
+            self.latest.power = Some(Sample { value: power, at: now });
+        }
+        if let Some(cadence) = measurement.cadence {
+            self.latest.cadence = Some(Sample { value: cadence, at: now });
+        }
+        if let Some(heart_rate) = measurement.heart_rate {
+            self.latest.heart_rate = Some(Sample {
+                value: heart_rate,
+                at: now,
+            });
+        }
+        if let Some(speed) = measurement.speed
+            && self.speed_source != SpeedSource::Virtual
+        {
+            self.latest.speed = Some(Sample { value: speed, at: now });
+        }
+    }
+
+    /// The last value of every metric, however old. See [`Session::current`]
+    /// for the ones still fresh.
+    pub fn last_known(&self) -> Current {
+        Current {
+            power: self.latest.power.map(|sample| sample.value),
+            cadence: self.latest.cadence.map(|sample| sample.value),
+            heart_rate: self.latest.heart_rate.map(|sample| sample.value),
+            speed: self
+                .latest
+                .speed
+                .map(|sample| sample.value)
+                .or(self.virtual_speed()),
+        }
+    }
+
+    pub fn current(&self, now: i64) -> Current {
+        Current {
+            power: fresh(self.latest.power, now),
+            cadence: fresh(self.latest.cadence, now),
+            heart_rate: fresh(self.latest.heart_rate, now),
+            speed: fresh(self.latest.speed, now).or(self.virtual_speed()),
+        }
+    }
+
+    /// The speed from power, when it stands in for the sensors.
+    fn virtual_speed(&self) -> Option<f64> {
+        match self.speed_source {
+            SpeedSource::Sensor => None,
+            SpeedSource::Auto if self.latest.power.is_none() => None,
+            SpeedSource::Auto | SpeedSource::Virtual => Some(self.virtual_speed.speed()),
+        }
+    }
+
+    /// Moves the ride forward, meant to be called once a second. Returns the
+    /// record of that second while riding.
+    pub fn tick(&mut self, now: i64, timestamp: i64) -> Option<Record> {
+        self.step_virtual_speed(now);
+        if matches!(self.state, SessionState::Idle | SessionState::Finished) {
+            return None;
+        }
+        let current = self.current(now);
+        self.auto_pause(&current, now);
+        self.advance(now);
+        if self.state != SessionState::Riding {
+            return None;
+        }
+        let record = Record {
+            timestamp,
+            elapsed: self.totals.elapsed,
+            power: current.power,
+            cadence: current.cadence,
+            heart_rate: current.heart_rate,
+            speed: current.speed,
+            distance: self.totals.distance,
+            target_power: self.target_power,
+        };
+        self.records.push(record);
+        self.auto_lap(timestamp);
+        Some(record)
+    }
+
+    /// Rides the virtual bike at the current power since the last step, it
+    /// keeps rolling before the start and during pauses like a real one.
+    fn step_virtual_speed(&mut self, now: i64) {
+        let power = fresh(self.latest.power, now).unwrap_or_default();
+        if let Some(last_step) = self.last_virtual_step.replace(now) {
+            self.virtual_speed
+                .advance(f64::from(power), (now - last_step) as f64 / SECOND);
+        }
+    }
+
+    fn auto_pause(&mut self, current: &Current, now: i64) {
+        if !self.auto_pause {
+            return;
+        }
+        let moving = current.speed.is_some_and(|speed| speed >= STOPPED_SPEED)
+            || current.power.is_some_and(|power| power > 0)
+            || current.cadence.is_some_and(|cadence| cadence > 0.0);
+        match self.state {
+            SessionState::Riding if moving => self.stopped_since = None,
+            SessionState::Riding => {
+                let stopped_since = *self.stopped_since.get_or_insert(now);
+                if now - stopped_since >= AUTO_PAUSE_AFTER {
+                    self.state = SessionState::AutoPaused;
+                }
+            }
+            SessionState::AutoPaused if moving => {
+                self.state = SessionState::Riding;
+                self.stopped_since = None;
+            }
+            _ => (),
+        }
+    }
+
+    /// Adds the time since the last tick to the totals.
+    fn advance(&mut self, now: i64) {
+        let Some(last_tick) = self.last_tick.replace(now) else {
+            return;
+        };
+        let seconds = (now - last_tick) as f64 / SECOND;
+        self.totals.elapsed += seconds;
+        if self.state != SessionState::Riding {
+            return;
+        }
+        let current = self.current(now);
+        self.totals.moving_time += seconds;
+        if let Some(speed) = current.speed {
+            self.totals.distance += speed / 3.6 * seconds;
+        }
+        if let Some(power) = current.power {
+            self.totals.work += f64::from(power.max(0)) * seconds;
+        }
+    }
+}
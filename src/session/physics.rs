@@ -0,0 +1,88 @@
+//! Speed on the road for the power the rider puts out, for trainers that
+//! report no speed or one that has little to do with it.
+
+/// Standard gravity, in m/s².
+const GRAVITY: f64 = 9.80665;
+/// Below this, in m/s, pedaling force is taken at this speed, otherwise
+/// starting from a standstill would take infinite force.
+const MIN_SPEED: f64 = 0.5;
+/// Integration step, in seconds.
+const STEP: f64 = 0.1;
+
+/// What the speed depends on besides power.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RiderModel {
+    /// Rider mass, in kg.
+    pub rider_mass: f64,
+    /// Bike mass, in kg.
+    pub bike_mass: f64,
+    /// Drag area, in m².
+    pub cda: f64,
+    /// Rolling resistance coefficient.
+    pub crr: f64,
+    /// Share of the power lost in the drivetrain.
+    pub drivetrain_loss: f64,
+    /// Air density, in kg/m³.
+    pub air_density: f64,
+    /// Road grade, in percent.
+    pub grade: f64,
+}
+
+impl Default for RiderModel {
+    fn default() -> Self {
+        Self {
+            rider_mass: 75.0,
+            bike_mass: 9.0,
+            cda: 0.32,
+            crr: 0.004,
+            drivetrain_loss: 0.03,
+            air_density: 1.225,
+            grade: 0.0,
+        }
+    }
+}
+
+impl RiderModel {
+    fn mass(&self) -> f64 {
+        self.rider_mass + self.bike_mass
+    }
+
+    /// The forces holding the rider back at `speed`, in newtons.
+    fn resistance(&self, speed: f64) -> f64 {
+        let slope = (self.grade / 100.0).atan();
+        let gravity = self.mass() * GRAVITY * slope.sin();
+        let rolling = self.mass() * GRAVITY * slope.cos() * self.crr;
+        let aero = 0.5 * self.air_density * self.cda * speed * speed;
+        gravity + rolling + aero
+    }
+}
+
+/// Integrates a [`RiderModel`] over time.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct VirtualSpeed {
+    pub model: RiderModel,
+    /// m/s.
+    speed: f64,
+}
+
+impl VirtualSpeed {
+    /// Speed in km/h.
+    pub fn speed(&self) -> f64 {
+        self.speed * 3.6
+    }
+
+    /// Rides `seconds` at `power` watts.
+    pub fn advance(&mut self, power: f64, seconds: f64) {
+        let mut left = seconds;
+        while left > 0.0 {
+            let step = left.min(STEP);
+            let propulsion =
+                power.max(0.0) * (1.0 - self.model.drivetrain_loss) / self.speed.max(MIN_SPEED);
+            let acceleration = (propulsion - self.model.resistance(self.speed)) / self.model.mass();
+            // Rolling resistance doesn't push back once stopped, and going
+            // downhill backwards isn't something a trainer does.
+            self.speed = (self.speed + acceleration * step).max(0.0);
+            left -= step;
+        }
+    }
+}
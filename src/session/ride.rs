@@ -0,0 +1,610 @@
+mod imp {
+    use std::cell::{Cell, RefCell};
+
+    use adw::prelude::ObjectExt;
+    use adw::subclass::prelude::{
+        DerivedObjectProperties, ObjectImpl, ObjectImplExt, ObjectSubclass, ObjectSubclassExt,
+    };
+    use gtk::glib::{self, Properties, SignalHandlerId, SourceId};
+
+    use std::sync::OnceLock;
+
+    use gtk::glib::subclass::Signal;
+
+    use crate::{
+        BLUETOOTH,
+        bluetooth::Device,
+        session::{Sensor, Session},
+        settings::Settings,
+    };
+
+    use super::{MetricState, RideState};
+
+    #[derive(Debug, Default, Properties)]
+    #[properties(wrapper_type = super::Ride)]
+    pub struct RidePrivate {
+        #[property(name = "state", get, set, builder(RideState::default()))]
+        state: Cell<RideState>,
+
+        /// Seconds since the start, pauses included.
+        #[property(name = "elapsed", get, set)]
+        elapsed: Cell<f64>,
+
+        /// Seconds spent riding.
+        #[property(name = "moving-time", get, set)]
+        moving_time: Cell<f64>,
+
+        /// Meters.
+        #[property(name = "distance", get, set)]
+        distance: Cell<f64>,
+
+        #[property(name = "kilojoules", get, set)]
+        kilojoules: Cell<f64>,
+
+        #[property(name = "calories", get, set)]
+        calories: Cell<f64>,
+
+        /// The lap being ridden, counting from one.
+        #[property(name = "lap", get, set)]
+        lap: Cell<u32>,
+
+        /// Seconds since the current lap started.
+        #[property(name = "lap-time", get, set)]
+        lap_time: Cell<f64>,
+
+        /// What the trainer is holding, empty when nothing is controlled.
+        #[property(name = "target", get, set)]
+        target: RefCell<String>,
+
+        #[property(name = "power", get, set)]
+        power: Cell<f64>,
+
+        #[property(name = "power-state", get, set, builder(MetricState::default()))]
+        power_state: Cell<MetricState>,
+
+        #[property(name = "cadence", get, set)]
+        cadence: Cell<f64>,
+
+        #[property(name = "cadence-state", get, set, builder(MetricState::default()))]
+        cadence_state: Cell<MetricState>,
+
+        #[property(name = "heart-rate", get, set)]
+        heart_rate: Cell<f64>,
+
+        #[property(name = "heart-rate-state", get, set, builder(MetricState::default()))]
+        heart_rate_state: Cell<MetricState>,
+
+        /// km/h.
+        #[property(name = "speed", get, set)]
+        speed: Cell<f64>,
+
+        #[property(name = "speed-state", get, set, builder(MetricState::default()))]
+        speed_state: Cell<MetricState>,
+
+        /// Rolling power averages, 0 until there is power.
+        #[property(name = "power-3s", get, set)]
+        power_3s: Cell<f64>,
+
+        #[property(name = "power-10s", get, set)]
+        power_10s: Cell<f64>,
+
+        #[property(name = "power-30s", get, set)]
+        power_30s: Cell<f64>,
+
+        #[property(name = "lap-power", get, set)]
+        lap_power: Cell<f64>,
+
+        #[property(name = "average-power", get, set)]
+        average_power: Cell<f64>,
+
+        /// 0 until there is enough of the ride to tell.
+        #[property(name = "normalized-power", get, set)]
+        normalized_power: Cell<f64>,
+
+        /// 0 without an FTP.
+        #[property(name = "intensity-factor", get, set)]
+        intensity_factor: Cell<f64>,
+
+        #[property(name = "training-stress-score", get, set)]
+        training_stress_score: Cell<f64>,
+
+        #[property(name = "variability-index", get, set)]
+        variability_index: Cell<f64>,
+
+        /// The zone the current power is in, counting from one. 0 when
+        /// there is no power or no zones.
+        #[property(name = "power-zone", get, set)]
+        power_zone: Cell<u32>,
+
+        /// The zone the current heart rate is in, like `power-zone`.
+        #[property(name = "heart-rate-zone", get, set)]
+        heart_rate_zone: Cell<u32>,
+
+        pub session: RefCell<Session>,
+
+        /// The sensors that sent measurements during the ride.
+        pub sensors: RefCell<Vec<Sensor>>,
+
+        pub timer: RefCell<Option<SourceId>>,
+
+        pub devices_handler: RefCell<Option<SignalHandlerId>>,
+        /// The handlers of every device followed, see `Ride::follow`.
+        pub device_handlers: RefCell<Vec<(Device, SignalHandlerId)>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for RidePrivate {
+        const NAME: &'static str = "Ride";
+        type Type = super::Ride;
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for RidePrivate {
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: OnceLock<Vec<Signal>> = OnceLock::new();
+            SIGNALS.get_or_init(|| vec![Signal::builder("zones-changed").build()])
+        }
+
+        fn constructed(&self) {
+            self.parent_constructed();
+            let settings = Settings::load();
+            self.session.replace(super::new_session(&settings));
+            self.obj().follow_devices();
+            self.obj().start_timer();
+        }
+
+        fn dispose(&self) {
+            if let Some(timer) = self.timer.take() {
+                timer.remove();
+            }
+            if let Some(handler) = self.devices_handler.take() {
+                BLUETOOTH.devices().disconnect(handler);
+            }
+            for (device, handler) in self.device_handlers.take() {
+                device.disconnect(handler);
+            }
+        }
+    }
+}
+
+use std::{cell::Ref, time::Duration};
+
+use adw::subclass::prelude::ObjectSubclassIsExt;
+use gtk::{
+    gio::prelude::ListModelExt,
+    glib::{self, Object, SignalHandlerId, clone, object::CastNone, prelude::ObjectExt},
+};
+
+use super::{
+    Activity, AutoLap, Lap, PowerSummary, Record, RiderModel, Sensor, SensorKind, Session,
+    SessionState, SpeedSource, ZoneSettings, Zones, rolling_average,
+};
+use crate::{BLUETOOTH, bluetooth::Device, protocol::Measurement, settings::Settings};
+
+/// Mirrors [`SessionState`] so views can bind to it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, glib::Enum)]
+#[enum_type(name = "RideState")]
+pub enum RideState {
+    #[default]
+    Idle,
+    Riding,
+    AutoPaused,
+    Paused,
+    Finished,
+}
+
+impl From<SessionState> for RideState {
+    fn from(state: SessionState) -> Self {
+        match state {
+            SessionState::Idle => RideState::Idle,
+            SessionState::Riding => RideState::Riding,
+            SessionState::AutoPaused => RideState::AutoPaused,
+            SessionState::Paused => RideState::Paused,
+            SessionState::Finished => RideState::Finished,
+        }
+    }
+}
+
+/// Whether a live value can be trusted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, glib::Enum)]
+#[enum_type(name = "MetricState")]
+pub enum MetricState {
+    /// No sensor has sent the value yet.
+    #[default]
+    Missing,
+    Live,
+    /// The sensor stopped sending it, the last value is kept around.
+    Stale,
+}
+
+glib::wrapper! {
+    /// A [`super::Session`] fed by the connected sensors and moved forward
+    /// every second.
+    pub struct Ride(ObjectSubclass<imp::RidePrivate>);
+}
+
+impl Ride {
+    pub fn new() -> Self {
+        Object::builder().build()
+    }
+
+    pub fn start(&self) {
+        self.imp()
+            .session
+            .borrow_mut()
+            .start(glib::monotonic_time(), unix_now());
+        self.refresh();
+    }
+
+    /// Ends the current lap and starts the next one.
+    pub fn new_lap(&self) {
+        self.imp()
+            .session
+            .borrow_mut()
+            .new_lap(glib::monotonic_time(), unix_now());
+        self.refresh();
+    }
+
+    /// Pauses, or resumes a paused ride.
+    pub fn toggle_pause(&self) {
+        self.imp().session.borrow_mut().toggle_pause();
+        self.refresh();
+    }
+
+    pub fn finish(&self) {
+        self.imp()
+            .session
+            .borrow_mut()
+            .finish(glib::monotonic_time(), unix_now());
+        self.refresh();
+    }
+
+    /// Puts the finished ride away and gets ready for the next one.
+    pub fn reset(&self) {
+        let settings = Settings::load();
+        self.imp().session.replace(new_session(&settings));
+        self.imp().sensors.borrow_mut().clear();
+        self.refresh();
+    }
+
+    /// The model virtual speed is worked out with, for the ride under way
+    /// as well.
+    pub fn set_rider_model(&self, model: RiderModel) {
+        self.imp().session.borrow_mut().set_rider_model(model);
+    }
+
+    /// Applies to the lap under way too, counting from its start.
+    pub fn set_auto_lap(&self, auto_lap: AutoLap) {
+        self.imp().session.borrow_mut().set_auto_lap(auto_lap);
+    }
+
+    pub fn set_speed_source(&self, speed_source: SpeedSource) {
+        self.imp()
+            .session
+            .borrow_mut()
+            .set_speed_source(speed_source);
+    }
+
+    pub fn records(&self) -> Ref<'_, [Record]> {
+        Ref::map(self.imp().session.borrow(), |session| session.records())
+    }
+
+    /// The rider's Functional Threshold Power, in watts.
+    pub fn ftp(&self) -> Option<f64> {
+        self.imp().session.borrow().zone_settings().ftp
+    }
+
+    pub fn zone_settings(&self) -> ZoneSettings {
+        self.imp().session.borrow().zone_settings().clone()
+    }
+
+    pub fn set_zone_settings(&self, zone_settings: ZoneSettings) {
+        self.imp()
+            .session
+            .borrow_mut()
+            .set_zone_settings(zone_settings);
+        self.refresh();
+        self.emit_by_name::<()>("zones-changed", &[]);
+    }
+
+    /// Called whenever the rider changes the zones.
+    pub fn connect_zones_changed<F: Fn(&Self) + 'static>(&self, callback: F) -> SignalHandlerId {
+        self.connect_local("zones-changed", false, move |values| {
+            let ride = values[0].get::<Self>().expect("the sender is a ride");
+            callback(&ride);
+            None
+        })
+    }
+
+    pub fn power_zones(&self) -> Zones {
+        self.imp().session.borrow().zone_settings().power_zones()
+    }
+
+    pub fn heart_rate_zones(&self) -> Zones {
+        self.imp()
+            .session
+            .borrow()
+            .zone_settings()
+            .heart_rate_zones()
+    }
+
+    /// The laps done so far. Once the ride is finished, all of them.
+    pub fn laps(&self) -> Ref<'_, [Lap]> {
+        Ref::map(self.imp().session.borrow(), |session| session.laps())
+    }
+
+    /// The power metrics of the whole ride so far, `None` without power.
+    pub fn power_summary(&self) -> Option<PowerSummary> {
+        PowerSummary::new(&self.records(), self.ftp())
+    }
+
+    /// Everything recorded since the start, `None` if the ride never started.
+    pub fn activity(&self) -> Option<Activity> {
+        let session = self.imp().session.borrow();
+        Some(Activity {
+            start: session.started()?,
+            totals: session.totals(),
+            records: session.records().to_vec(),
+            laps: session.laps().to_vec(),
+            sensors: self.imp().sensors.borrow().clone(),
+            ftp: self.ftp(),
+        })
+    }
+
+    /// Remembers `device` as one of the ride's sensors, once it has
+    /// measured something while riding.
+    fn add_sensor(&self, device: &Device, measurement: &Measurement) {
+        if matches!(
+            self.imp().session.borrow().state(),
+            SessionState::Idle | SessionState::Finished
+        ) {
+            return;
+        }
+        let mut sensors = self.imp().sensors.borrow_mut();
+        let name = device.name();
+        if sensors.iter().any(|sensor| sensor.name == name) {
+            return;
+        }
+        let kind = if device.controllable() {
+            SensorKind::FitnessEquipment
+        } else if measurement.heart_rate.is_some() && measurement.power.is_none() {
+            SensorKind::HeartRate
+        } else {
+            SensorKind::BikePower
+        };
+        sensors.push(Sensor {
+            name,
+            brand: device.brand(),
+            kind,
+        });
+    }
+
+    /// Keeps the live values current even before the ride starts, so the
+    /// rider can check the sensors.
+    fn start_timer(&self) {
+        let timer = glib::timeout_add_local(
+            Duration::from_secs(1),
+            clone!(
+                #[weak(rename_to = slf)]
+                self,
+                #[upgrade_or]
+                glib::ControlFlow::Break,
+                move || {
+                    slf.tick();
+                    glib::ControlFlow::Continue
+                }
+            ),
+        );
+        self.imp().timer.replace(Some(timer));
+    }
+
+    fn tick(&self) {
+        self.imp()
+            .session
+            .borrow_mut()
+            .tick(glib::monotonic_time(), unix_now());
+        self.refresh();
+    }
+
+    fn refresh(&self) {
+        let (state, totals, lap, lap_time) = {
+            let session = self.imp().session.borrow();
+            (
+                session.state(),
+                session.totals(),
+                session.lap(),
+                session.lap_time(),
+            )
+        };
+        self.set_state(RideState::from(state));
+        self.set_lap(lap as u32);
+        self.set_lap_time(lap_time);
+        self.set_elapsed(totals.elapsed);
+        self.set_moving_time(totals.moving_time);
+        self.set_distance(totals.distance);
+        self.set_kilojoules(totals.kilojoules());
+        self.set_calories(totals.calories());
+        self.refresh_power();
+        self.refresh_live();
+    }
+
+    fn refresh_power(&self) {
+        let (rolling, lap) = {
+            let session = self.imp().session.borrow();
+            let records = session.records();
+            (
+                [3, 10, 30].map(|seconds| rolling_average(records, seconds).unwrap_or_default()),
+                PowerSummary::new(session.lap_records(), None),
+            )
+        };
+        let [power_3s, power_10s, power_30s] = rolling;
+        self.set_power_3s(power_3s);
+        self.set_power_10s(power_10s);
+        self.set_power_30s(power_30s);
+        self.set_lap_power(lap.map(|lap| lap.average).unwrap_or_default());
+        let summary = self.power_summary().unwrap_or_default();
+        self.set_average_power(summary.average);
+        self.set_normalized_power(summary.normalized.unwrap_or_default());
+        self.set_intensity_factor(summary.intensity_factor.unwrap_or_default());
+        self.set_training_stress_score(summary.training_stress_score.unwrap_or_default());
+        self.set_variability_index(summary.variability_index.unwrap_or_default());
+    }
+
+    fn refresh_live(&self) {
+        let (current, last_known) = {
+            let session = self.imp().session.borrow();
+            (
+                session.current(glib::monotonic_time()),
+                session.last_known(),
+            )
+        };
+        let state = |fresh: bool, known: bool| match (fresh, known) {
+            (true, _) => MetricState::Live,
+            (false, true) => MetricState::Stale,
+            (false, false) => MetricState::Missing,
+        };
+        self.set_power(last_known.power.map(f64::from).unwrap_or_default());
+        self.set_power_state(state(current.power.is_some(), last_known.power.is_some()));
+        self.set_cadence(last_known.cadence.unwrap_or_default());
+        self.set_cadence_state(state(
+            current.cadence.is_some(),
+            last_known.cadence.is_some(),
+        ));
+        self.set_heart_rate(last_known.heart_rate.map(f64::from).unwrap_or_default());
+        self.set_heart_rate_state(state(
+            current.heart_rate.is_some(),
+            last_known.heart_rate.is_some(),
+        ));
+        let zone = |zones: Zones, value: Option<f64>| {
+            value
+                .and_then(|value| zones.index(value))
+                .map_or(0, |index| index as u32 + 1)
+        };
+        self.set_power_zone(zone(self.power_zones(), current.power.map(f64::from)));
+        self.set_heart_rate_zone(zone(
+            self.heart_rate_zones(),
+            current.heart_rate.map(f64::from),
+        ));
+        self.set_speed(last_known.speed.unwrap_or_default());
+        self.set_speed_state(state(current.speed.is_some(), last_known.speed.is_some()));
+    }
+
+    /// Takes in the measurements of every connected device, including the
+    /// ones connected later on.
+    fn follow_devices(&self) {
+        let devices = BLUETOOTH.devices();
+        for device in (0..devices.n_items())
+            .filter_map(|position| devices.item(position).and_downcast::<Device>())
+        {
+            self.follow(&device);
+        }
+        let handler = devices.connect_items_changed(clone!(
+            #[weak(rename_to = slf)]
+            self,
+            move |devices, position, _, added| {
+                for device in (position..position + added)
+                    .filter_map(|position| devices.item(position).and_downcast::<Device>())
+                {
+                    slf.follow(&device);
+                }
+            }
+        ));
+        self.imp().devices_handler.replace(Some(handler));
+    }
+
+    /// Evicted devices are put back in the list as the same object, so a
+    /// device is only followed the first time it shows up.
+    fn follow(&self, device: &Device) {
+        let mut device_handlers = self.imp().device_handlers.borrow_mut();
+        if device_handlers
+            .iter()
+            .any(|(followed, _)| followed == device)
+        {
+            return;
+        }
+        let handlers = [
+            device.connect_measurement(clone!(
+                #[weak(rename_to = slf)]
+                self,
+                move |device| {
+                    if device.connected() {
+                        let measurement = device.measurement();
+                        slf.imp()
+                            .session
+                            .borrow_mut()
+                            .update(&measurement, glib::monotonic_time());
+                        slf.add_sensor(device, &measurement);
+                        slf.refresh_live();
+                    }
+                }
+            )),
+            device.connect_target_notify(clone!(
+                #[weak(rename_to = slf)]
+                self,
+                move |device| {
+                    if device.connected() {
+                        slf.set_target(device.target());
+                    }
+                }
+            )),
+            device.connect_target_power_notify(clone!(
+                #[weak(rename_to = slf)]
+                self,
+                move |device| {
+                    if device.connected() {
+                        let target_power = i16::try_from(device.target_power())
+                            .ok()
+                            .filter(|power| *power > 0);
+                        slf.imp()
+                            .session
+                            .borrow_mut()
+                            .set_target_power(target_power);
+                    }
+                }
+            )),
+            device.connect_grade_notify(clone!(
+                #[weak(rename_to = slf)]
+                self,
+                move |device| {
+                    if device.connected() {
+                        slf.imp().session.borrow_mut().set_grade(device.grade());
+                    }
+                }
+            )),
+            device.connect_connected_notify(clone!(
+                #[weak(rename_to = slf)]
+                self,
+                move |device| {
+                    if !device.connected() && slf.target() == device.target() {
+                        slf.set_target("");
+                        slf.imp().session.borrow_mut().set_target_power(None);
+                    }
+                }
+            )),
+        ];
+        device_handlers.extend(handlers.map(|handler| (device.clone(), handler)));
+    }
+}
+
+/// A session set up the way the rider configured it.
+fn new_session(settings: &Settings) -> Session {
+    let mut session = Session::new(true);
+    session.set_rider_model(settings.rider_model());
+    session.set_speed_source(settings.speed_source());
+    session.set_auto_lap(settings.auto_lap());
+    session.set_zone_settings(settings.zone_settings());
+    session
+}
+
+/// Seconds since the Unix epoch.
+fn unix_now() -> i64 {
+    glib::DateTime::now_utc()
+        .map(|now| now.to_unix())
+        .unwrap_or_default()
+}
+
+impl Default for Ride {
+    fn default() -> Self {
+        Self::new()
+    }
+}
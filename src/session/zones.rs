@@ -0,0 +1,306 @@
+//! Training zones for power and heart rate, and the time spent in each.
+
+use super::Record;
+
+/// Coggan's seven power zones, starting at these shares of FTP.
+const COGGAN: [(f64, &str); 7] = [
+    (0.0, "Active Recovery"),
+    (0.56, "Endurance"),
+    (0.76, "Tempo"),
+    (0.91, "Threshold"),
+    (1.06, "VO2 Max"),
+    (1.21, "Anaerobic"),
+    (1.51, "Neuromuscular"),
+];
+
+/// Five heart rate zones, starting at these shares of threshold heart rate.
+const THRESHOLD_HEART_RATE: [(f64, &str); 5] = [
+    (0.0, "Recovery"),
+    (0.85, "Aerobic"),
+    (0.90, "Tempo"),
+    (0.95, "Threshold"),
+    (1.0, "Anaerobic"),
+];
+
+/// The same five zones from maximum heart rate, when threshold is unknown.
+const MAX_HEART_RATE: [(f64, &str); 5] = [
+    (0.0, "Recovery"),
+    (0.6, "Aerobic"),
+    (0.7, "Tempo"),
+    (0.8, "Threshold"),
+    (0.9, "Anaerobic"),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zone {
+    pub name: String,
+    /// Where the zone starts, in watts or beats per minute.
+    pub from: f64,
+    /// Where the next zone starts, `f64::INFINITY` for the top one.
+    pub to: f64,
+}
+
+/// Zones covering every value from zero up, in order.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Zones(Vec<Zone>);
+
+impl Zones {
+    fn from_shares(shares: &[(f64, &str)], reference: f64) -> Self {
+        Self::from_starts(
+            shares
+                .iter()
+                .map(|(share, name)| (share * reference, name.to_string())),
+        )
+    }
+
+    fn from_starts(starts: impl IntoIterator<Item = (f64, String)>) -> Self {
+        let starts = starts.into_iter().collect::<Vec<_>>();
+        Self(
+            starts
+                .iter()
+                .enumerate()
+                .map(|(index, (from, name))| Zone {
+                    name: name.clone(),
+                    from: *from,
+                    to: starts.get(index + 1).map_or(f64::INFINITY, |(to, _)| *to),
+                })
+                .collect(),
+        )
+    }
+
+    /// Coggan's seven zones.
+    pub fn coggan(ftp: f64) -> Self {
+        Self::from_shares(&COGGAN, ftp)
+    }
+
+    /// Five zones from threshold heart rate, or from maximum heart rate
+    /// when only that is known.
+    pub fn heart_rate(threshold: Option<f64>, max: Option<f64>) -> Self {
+        match (threshold, max) {
+            (Some(threshold), _) => Self::from_shares(&THRESHOLD_HEART_RATE, threshold),
+            (None, Some(max)) => Self::from_shares(&MAX_HEART_RATE, max),
+            (None, None) => Self::default(),
+        }
+    }
+
+    /// Zones starting at zero and split at `boundaries`, in any order.
+    pub fn custom(boundaries: &[f64]) -> Self {
+        let mut boundaries = boundaries
+            .iter()
+            .copied()
+            .filter(|boundary| *boundary > 0.0)
+            .collect::<Vec<_>>();
+        boundaries.sort_by(f64::total_cmp);
+        boundaries.dedup();
+        Self::from_starts(
+            std::iter::once(0.0)
+                .chain(boundaries)
+                .enumerate()
+                .map(|(index, from)| (from, format!("Zone {}", index + 1))),
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Zone> {
+        self.0.iter()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Zone> {
+        self.0.get(index)
+    }
+
+    /// The zone `value` falls in.
+    pub fn index(&self, value: f64) -> Option<usize> {
+        self.0
+            .iter()
+            .position(|zone| value >= zone.from && value < zone.to)
+    }
+
+    /// Seconds spent in each zone over `records`, which are a second apart.
+    pub fn time_in_zones(&self, records: &[Record], value: fn(&Record) -> Option<f64>) -> Vec<f64> {
+        let mut times = vec![0.0; self.len()];
+        for index in records
+            .iter()
+            .filter_map(value)
+            .filter_map(|value| self.index(value))
+        {
+            times[index] += 1.0;
+        }
+        times
+    }
+}
+
+/// How zones are laid out.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ZoneModel {
+    /// Coggan's seven zones for power, five zones for heart rate.
+    #[default]
+    Standard,
+    /// Boundaries the rider picked.
+    Custom,
+}
+
+/// What the zones are worked out from.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ZoneSettings {
+    /// Functional Threshold Power, in watts.
+    pub ftp: Option<f64>,
+    pub max_heart_rate: Option<f64>,
+    pub threshold_heart_rate: Option<f64>,
+    pub power_model: ZoneModel,
+    /// Where the custom power zones start, in watts, past the first one.
+    pub custom_power_zones: Vec<f64>,
+    pub heart_rate_model: ZoneModel,
+    /// Where the custom heart rate zones start, in bpm, past the first one.
+    pub custom_heart_rate_zones: Vec<f64>,
+}
+
+impl ZoneSettings {
+    pub fn power_zones(&self) -> Zones {
+        match (self.power_model, self.ftp) {
+            (ZoneModel::Custom, _) => Zones::custom(&self.custom_power_zones),
+            (ZoneModel::Standard, Some(ftp)) => Zones::coggan(ftp),
+            (ZoneModel::Standard, None) => Zones::default(),
+        }
+    }
+
+    pub fn heart_rate_zones(&self) -> Zones {
+        match self.heart_rate_model {
+            ZoneModel::Custom => Zones::custom(&self.custom_heart_rate_zones),
+            ZoneModel::Standard => {
+                Zones::heart_rate(self.threshold_heart_rate, self.max_heart_rate)
+            }
+        }
+    }
+}
+
+/// Seconds spent in each power and heart rate zone.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TimeInZones {
+    pub power: Vec<f64>,
+    pub heart_rate: Vec<f64>,
+}
+
+impl TimeInZones {
+    pub fn new(records: &[Record], settings: &ZoneSettings) -> Self {
+        Self {
+            power: settings
+                .power_zones()
+                .time_in_zones(records, |record| record.power.map(f64::from)),
+            heart_rate: settings
+                .heart_rate_zones()
+                .time_in_zones(records, |record| record.heart_rate.map(f64::from)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn starts(zones: &Zones) -> Vec<f64> {
+        zones.iter().map(|zone| zone.from).collect()
+    }
+
+    fn record(power: Option<i16>, heart_rate: Option<u16>) -> Record {
+        Record {
+            timestamp: 1_700_000_000,
+            elapsed: 0.0,
+            power,
+            cadence: None,
+            heart_rate,
+            speed: None,
+            distance: 0.0,
+            target_power: None,
+        }
+    }
+
+    #[test]
+    fn coggan_boundaries() {
+        let zones = Zones::coggan(250.0);
+        assert_eq!(
+            starts(&zones),
+            [0.0, 140.0, 190.0, 227.5, 265.0, 302.5, 377.5]
+        );
+        assert_eq!(zones.get(1).unwrap().to, 190.0);
+        assert_eq!(zones.get(6).unwrap().to, f64::INFINITY);
+        assert_eq!(zones.get(3).unwrap().name, "Threshold");
+        assert_eq!(zones.index(0.0), Some(0));
+        assert_eq!(zones.index(139.9), Some(0));
+        // A boundary belongs to the zone it starts.
+        assert_eq!(zones.index(140.0), Some(1));
+        assert_eq!(zones.index(1500.0), Some(6));
+        assert_eq!(zones.index(-1.0), None);
+    }
+
+    #[test]
+    fn heart_rate_boundaries() {
+        assert_eq!(
+            starts(&Zones::heart_rate(Some(170.0), Some(190.0))),
+            [0.0, 144.5, 153.0, 161.5, 170.0]
+        );
+        assert_eq!(
+            starts(&Zones::heart_rate(None, Some(190.0))),
+            [0.0, 114.0, 133.0, 152.0, 171.0]
+        );
+        assert!(Zones::heart_rate(None, None).is_empty());
+    }
+
+    #[test]
+    fn custom_boundaries() {
+        let zones = Zones::custom(&[300.0, 150.0, 0.0, -5.0, 150.0]);
+        assert_eq!(starts(&zones), [0.0, 150.0, 300.0]);
+        assert_eq!(zones.get(2).unwrap().name, "Zone 3");
+        assert_eq!(starts(&Zones::custom(&[])), [0.0]);
+    }
+
+    #[test]
+    fn settings_without_thresholds() {
+        let settings = ZoneSettings::default();
+        assert!(settings.power_zones().is_empty());
+        assert!(settings.heart_rate_zones().is_empty());
+        let time = TimeInZones::new(&[record(Some(200), Some(150))], &settings);
+        assert_eq!(time, TimeInZones::default());
+
+        // Custom zones don't need a threshold.
+        let settings = ZoneSettings {
+            power_model: ZoneModel::Custom,
+            custom_power_zones: vec![200.0],
+            ..Default::default()
+        };
+        assert_eq!(starts(&settings.power_zones()), [0.0, 200.0]);
+    }
+
+    #[test]
+    fn time_in_zones() {
+        let settings = ZoneSettings {
+            ftp: Some(250.0),
+            threshold_heart_rate: Some(170.0),
+            ..Default::default()
+        };
+        let records = [
+            record(Some(100), Some(120)),
+            record(Some(139), Some(150)),
+            record(Some(140), None),
+            record(None, Some(175)),
+            record(Some(400), Some(161)),
+        ];
+        let time = TimeInZones::new(&records, &settings);
+        assert_eq!(time.power, [2.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(time.heart_rate, [1.0, 1.0, 1.0, 0.0, 1.0]);
+        assert_eq!(
+            TimeInZones::new(&[], &settings),
+            TimeInZones {
+                power: vec![0.0; 7],
+                heart_rate: vec![0.0; 5],
+            }
+        );
+    }
+}
@@ -0,0 +1,175 @@
+//! What the rider configured, kept in a key file under the user config dir.
+
+use std::path::PathBuf;
+
+use gtk::glib::{self, KeyFile, KeyFileFlags};
+
+use crate::session::{AutoLap, RiderModel, SpeedSource, ZoneModel, ZoneSettings};
+
+const RIDER: &str = "Rider";
+const RIDE: &str = "Ride";
+const ZONES: &str = "Zones";
+
+pub struct Settings {
+    key_file: KeyFile,
+}
+
+impl Settings {
+    /// Reads the settings, a missing or broken file leaves the defaults.
+    pub fn load() -> Self {
+        let key_file = KeyFile::new();
+        if let Err(error) = key_file.load_from_file(Self::path(), KeyFileFlags::KEEP_COMMENTS)
+            && !error.matches(glib::FileError::Noent)
+        {
+            log::warn!("Could not read the settings. {error}");
+        }
+        Self { key_file }
+    }
+
+    fn path() -> PathBuf {
+        glib::user_config_dir().join("bike").join("settings.ini")
+    }
+
+    fn double(&self, group: &str, key: &str, default: f64) -> f64 {
+        self.key_file.double(group, key).unwrap_or(default)
+    }
+
+    pub fn rider_model(&self) -> RiderModel {
+        let default = RiderModel::default();
+        RiderModel {
+            rider_mass: self.double(RIDER, "mass", default.rider_mass),
+            bike_mass: self.double(RIDER, "bike-mass", default.bike_mass),
+            cda: self.double(RIDER, "cda", default.cda),
+            crr: self.double(RIDER, "crr", default.crr),
+            drivetrain_loss: self.double(RIDER, "drivetrain-loss", default.drivetrain_loss),
+            air_density: self.double(RIDER, "air-density", default.air_density),
+            grade: default.grade,
+        }
+    }
+
+    pub fn set_rider_model(&self, model: &RiderModel) {
+        self.key_file.set_double(RIDER, "mass", model.rider_mass);
+        self.key_file
+            .set_double(RIDER, "bike-mass", model.bike_mass);
+        self.key_file.set_double(RIDER, "cda", model.cda);
+        self.key_file.set_double(RIDER, "crr", model.crr);
+        self.key_file
+            .set_double(RIDER, "drivetrain-loss", model.drivetrain_loss);
+        self.key_file
+            .set_double(RIDER, "air-density", model.air_density);
+    }
+
+    /// A value the rider may have left unset, 0 counting as unset.
+    fn optional(&self, group: &str, key: &str) -> Option<f64> {
+        self.key_file
+            .double(group, key)
+            .ok()
+            .filter(|value| *value > 0.0)
+    }
+
+    fn set_optional(&self, group: &str, key: &str, value: Option<f64>) {
+        self.key_file
+            .set_double(group, key, value.unwrap_or_default());
+    }
+
+    fn model(&self, key: &str) -> ZoneModel {
+        match self.key_file.string(ZONES, key).as_deref() {
+            Ok("custom") => ZoneModel::Custom,
+            _ => ZoneModel::Standard,
+        }
+    }
+
+    fn set_model(&self, key: &str, model: ZoneModel) {
+        let model = match model {
+            ZoneModel::Standard => "standard",
+            ZoneModel::Custom => "custom",
+        };
+        self.key_file.set_string(ZONES, key, model);
+    }
+
+    fn boundaries(&self, key: &str) -> Vec<f64> {
+        self.key_file.double_list(ZONES, key).unwrap_or_default()
+    }
+
+    fn set_boundaries(&self, key: &str, boundaries: &[f64]) {
+        let boundaries = boundaries
+            .iter()
+            .map(f64::to_string)
+            .collect::<Vec<_>>()
+            .join(";");
+        self.key_file.set_string(ZONES, key, &boundaries);
+    }
+
+    pub fn zone_settings(&self) -> ZoneSettings {
+        ZoneSettings {
+            ftp: self.optional(RIDER, "ftp"),
+            max_heart_rate: self.optional(RIDER, "max-heart-rate"),
+            threshold_heart_rate: self.optional(RIDER, "threshold-heart-rate"),
+            power_model: self.model("power-model"),
+            custom_power_zones: self.boundaries("custom-power-zones"),
+            heart_rate_model: self.model("heart-rate-model"),
+            custom_heart_rate_zones: self.boundaries("custom-heart-rate-zones"),
+        }
+    }
+
+    pub fn set_zone_settings(&self, zones: &ZoneSettings) {
+        self.set_optional(RIDER, "ftp", zones.ftp);
+        self.set_optional(RIDER, "max-heart-rate", zones.max_heart_rate);
+        self.set_optional(RIDER, "threshold-heart-rate", zones.threshold_heart_rate);
+        self.set_model("power-model", zones.power_model);
+        self.set_boundaries("custom-power-zones", &zones.custom_power_zones);
+        self.set_model("heart-rate-model", zones.heart_rate_model);
+        self.set_boundaries("custom-heart-rate-zones", &zones.custom_heart_rate_zones);
+    }
+
+    pub fn save(&self) -> Result<(), glib::Error> {
+        let path = Self::path();
+        if let Some(directory) = path.parent()
+            && let Err(error) = std::fs::create_dir_all(directory)
+        {
+            log::warn!("Could not create {}. {error}", directory.display());
+        }
+        self.key_file.save_to_file(path)
+    }
+
+    /// Laps every `auto-lap-distance` meters, or else every `auto-lap-time`
+    /// seconds.
+    pub fn auto_lap(&self) -> AutoLap {
+        match (
+            self.optional(RIDE, "auto-lap-distance"),
+            self.optional(RIDE, "auto-lap-time"),
+        ) {
+            (Some(distance), _) => AutoLap::Distance(distance),
+            (None, Some(time)) => AutoLap::Time(time),
+            (None, None) => AutoLap::Off,
+        }
+    }
+
+    pub fn set_auto_lap(&self, auto_lap: AutoLap) {
+        let (distance, time) = match auto_lap {
+            AutoLap::Off => (None, None),
+            AutoLap::Distance(distance) => (Some(distance), None),
+            AutoLap::Time(time) => (None, Some(time)),
+        };
+        self.set_optional(RIDE, "auto-lap-distance", distance);
+        self.set_optional(RIDE, "auto-lap-time", time);
+    }
+
+    /// `auto`, `sensor` or `virtual`.
+    pub fn speed_source(&self) -> SpeedSource {
+        match self.key_file.string(RIDE, "speed-source").as_deref() {
+            Ok("sensor") => SpeedSource::Sensor,
+            Ok("virtual") => SpeedSource::Virtual,
+            _ => SpeedSource::Auto,
+        }
+    }
+
+    pub fn set_speed_source(&self, speed_source: SpeedSource) {
+        let speed_source = match speed_source {
+            SpeedSource::Auto => "auto",
+            SpeedSource::Sensor => "sensor",
+            SpeedSource::Virtual => "virtual",
+        };
+        self.key_file.set_string(RIDE, "speed-source", speed_source);
+    }
+}